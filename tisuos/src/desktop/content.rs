@@ -95,10 +95,11 @@ impl Content {
                                     + &self.directory.path[..] + "/"
                                     + &self.directory.item.get(idx).unwrap().name[..];
                                 println!("path {}", path);
-                                let id = exec(path);
-                                let id = get_task_mgr().unwrap().get_task_exec(id).unwrap().pid;
-                                if id > 0 {
-                                    return ContentEvent::Exec(id)
+                                let id = exec(path, 0);
+                                if let Some(info) = get_task_mgr().unwrap().get_task_exec(id) {
+                                    if info.pid > 0 {
+                                        return ContentEvent::Exec(info.pid)
+                                    }
                                 }
                             }
                         }
@@ -2,6 +2,10 @@
 //! 
 //! 2021年1月29日 zg
 
+const FAT32_MAGIC : [u8;4] = [0x52, 0x52, 0x61, 0x41];
+const TIANMU_MAGIC : [u8;4] = [0x33, 0x23, 0, 0];
+/// 读取的头部长度，ext2 的超级块在 1024 字节处
+const HEAD_SIZE : usize = 2048;
 
 pub struct DiskType {
     device_id : usize,
@@ -10,30 +14,52 @@ pub struct DiskType {
 
 impl DiskType {
     pub fn new(device_id : usize)->Self {
-        let head = Block::new(1024);
-        sync_read_buffer(device_id, head.to_array(0, 1024), 0);
+        let head = Block::new(HEAD_SIZE);
+        sync_read_buffer(device_id, head.to_array(0, HEAD_SIZE), 0);
         Self {
             device_id,
             head,
         }
     }
 
-    pub fn get_type(&self)->BlockType {
-        let fat32 = [0x52, 0x52, 0x61, 0x41];
-        let tianmu = [0x33,0x23,0,0];
+    fn magic(&self)->[u8;4] {
         let mut flag = [0;4];
         for i in 512..516 {
             flag[i - 512] = self.head.get(i).unwrap();
         }
-        if fat32 == flag {
+        flag
+    }
+
+    fn is_ext2(&self)->bool {
+        let magic = EXT2_MAGIC.to_le_bytes();
+        self.head.get(EXT2_MAGIC_ADDR).unwrap() == magic[0] && self.head.get(EXT2_MAGIC_ADDR + 1).unwrap() == magic[1]
+    }
+
+    /// 是否能识别出文件系统，用于区分整个磁盘的文件系统和分区表
+    pub fn is_known(&self)->bool {
+        let flag = self.magic();
+        flag == FAT32_MAGIC || flag == TIANMU_MAGIC || self.is_ext2()
+    }
+
+    pub fn get_type(&self)->BlockType {
+        let flag = self.magic();
+        if FAT32_MAGIC == flag {
             let info = FATInfo::new(&self.head);
             let mgr = FATManger::new(unsafe {&*info}, self.device_id);
             BlockType::FAT32(Arc::new(mgr))
         }
-        else if tianmu == flag {
+        else if TIANMU_MAGIC == flag {
             let tm = TianMu::new(self.device_id);
             BlockType::TianMu(Arc::new(tm))
         }
+        else if self.is_ext2() {
+            if let Some(fs) = Ext2::new(self.device_id) {
+                BlockType::Ext2(Arc::new(fs))
+            }
+            else {
+                BlockType::Unknown
+            }
+        }
         else {
             BlockType::Unknown
         }
@@ -44,6 +70,7 @@ pub enum BlockType {
     Unknown,
     TianMu(Arc<TianMu>),
     FAT32(Arc<FATManger>),
+    Ext2(Arc<Ext2>),
 }
 
 
@@ -51,4 +78,4 @@ use alloc::sync::Arc;
 
 use crate::{memory::block::Block, virtio::disk_cache::sync_read_buffer};
 
-use super::{fat32::{FATInfo, FATManger}, tianmu::TianMu};
+use super::{ext2::{EXT2_MAGIC, EXT2_MAGIC_ADDR, Ext2}, fat32::{FATInfo, FATManger}, tianmu::TianMu};
@@ -0,0 +1,634 @@
+//! # ext2 文件系统
+//! 实现 tisu_fs::Format，块号即卷内地址除以块大小，目录与文件都以 inode 号作为起始块号交给 tisu_fs
+//! 读取支持直接块与一、二、三级间接块；写入时从块位图、inode 位图中分配，并更新组描述符与超级块中的空闲计数
+//! 不认识的不兼容特性拒绝挂载，不认识的只读兼容特性以只读方式挂载
+//!
+//! 2021年6月22日 zg
+
+const SUPER_BLOCK_ADDR : usize = 1024;
+const SUPER_BLOCK_SIZE : usize = 1024;
+pub const EXT2_MAGIC : u16 = 0xef53;
+/// 魔数在卷内的位置
+pub const EXT2_MAGIC_ADDR : usize = SUPER_BLOCK_ADDR + 56;
+const ROOT_INODE : usize = 2;
+const DIRECT_BLOCKS : usize = 12;
+const GROUP_DESC_SIZE : usize = 32;
+const DIR_ENTRY_HEAD : usize = 8;
+const NAME_MAX : usize = 255;
+/// 目录项中带文件类型
+const INCOMPAT_FILETYPE : u32 = 0x2;
+/// 位图与 inode 表可以放在其它组，组描述符中的位置仍然有效
+const INCOMPAT_FLEX_BG : u32 = 0x200;
+const RO_COMPAT_SPARSE_SUPER : u32 = 0x1;
+const RO_COMPAT_LARGE_FILE : u32 = 0x2;
+/// 目录带有哈希索引，只按线性目录处理
+const FLAG_INDEX : u32 = 0x1000;
+/// 使用 extent 的 inode，只有 ext4 才有
+const FLAG_EXTENTS : u32 = 0x80000;
+const MODE_TYPE : u16 = 0xf000;
+const MODE_FILE : u16 = 0x8000;
+const MODE_DIRECTORY : u16 = 0x4000;
+const MODE_SYMLINK : u16 = 0xa000;
+const FT_FILE : u8 = 1;
+const FT_DIRECTORY : u8 = 2;
+const SECTOR_SIZE : usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ext2 {
+    pub device_id : usize,
+    pub block_size : usize,
+    pub total_size : usize,
+    /// 有不认识的只读兼容特性时为假
+    pub writable : bool,
+    inode_size : usize,
+    inodes_count : usize,
+    inodes_per_group : usize,
+    blocks_count : usize,
+    blocks_per_group : usize,
+    first_data_block : usize,
+    /// 第一个非保留的 inode
+    first_inode : usize,
+    group_num : usize,
+    filetype : bool,
+}
+
+/// ## inode
+/// 只解析用到的字段，写回时保留其它字段，时间为 1970 年起的秒数
+#[derive(Debug, Clone, Copy)]
+pub struct Inode {
+    pub mode : u16,
+    pub size : usize,
+    pub atime : usize,
+    pub ctime : usize,
+    pub mtime : usize,
+    pub links : u16,
+    /// 占用的扇区数，包括间接块
+    sectors : usize,
+    flags : u32,
+    block : [u32;15],
+}
+
+impl Inode {
+    fn parse(raw : &[u8])->Self {
+        let mode = read_u16(raw, 0);
+        let mut size = read_u32(raw, 4) as usize;
+        if mode & MODE_TYPE == MODE_FILE {
+            size |= (read_u32(raw, 108) as usize) << 32;
+        }
+        let mut block = [0;15];
+        for (i, b) in block.iter_mut().enumerate() {
+            *b = read_u32(raw, 40 + i * 4);
+        }
+        Self {
+            mode,
+            size,
+            atime : read_u32(raw, 8) as usize,
+            ctime : read_u32(raw, 12) as usize,
+            mtime : read_u32(raw, 16) as usize,
+            links : read_u16(raw, 26),
+            sectors : read_u32(raw, 28) as usize,
+            flags : read_u32(raw, 32),
+            block,
+        }
+    }
+
+    fn save(&self, raw : &mut [u8]) {
+        write_u16(raw, 0, self.mode);
+        write_u32(raw, 4, self.size as u32);
+        if self.is_file() {
+            write_u32(raw, 108, (self.size >> 32) as u32);
+        }
+        write_u32(raw, 8, self.atime as u32);
+        write_u32(raw, 12, self.ctime as u32);
+        write_u32(raw, 16, self.mtime as u32);
+        write_u16(raw, 26, self.links);
+        write_u32(raw, 28, self.sectors as u32);
+        write_u32(raw, 32, self.flags);
+        for (i, b) in self.block.iter().enumerate() {
+            write_u32(raw, 40 + i * 4, *b);
+        }
+    }
+
+    pub fn is_dir(&self)->bool {
+        self.mode & MODE_TYPE == MODE_DIRECTORY
+    }
+
+    pub fn is_file(&self)->bool {
+        self.mode & MODE_TYPE == MODE_FILE
+    }
+
+    /// rwx 权限位
+    pub fn permission(&self)->usize {
+        (self.mode & 0o777) as usize
+    }
+}
+
+impl Ext2 {
+    /// 读取超级块，魔数不对、字段损坏或者有不支持的特性时返回 None
+    pub fn new(device_id : usize)->Option<Self> {
+        let mut sp = vec![0u8;SUPER_BLOCK_SIZE];
+        sync_read_buffer(device_id, &mut sp[..], SUPER_BLOCK_ADDR);
+        if read_u16(&sp, 56) != EXT2_MAGIC {
+            return None;
+        }
+        let rev = read_u32(&sp, 76);
+        let incompat = if rev > 0 { read_u32(&sp, 96) } else { 0 };
+        let ro_compat = if rev > 0 { read_u32(&sp, 100) } else { 0 };
+        if incompat & !(INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG) != 0 {
+            println!("volume {} ext2 incompatible features {:x}", device_id, incompat);
+            return None;
+        }
+        let log_block_size = read_u32(&sp, 24);
+        let blocks_count = read_u32(&sp, 4) as usize;
+        let first_data_block = read_u32(&sp, 20) as usize;
+        let blocks_per_group = read_u32(&sp, 32) as usize;
+        let inode_size = if rev > 0 { read_u16(&sp, 88) as usize } else { 128 };
+        if log_block_size > 6 || first_data_block >= blocks_count || blocks_per_group == 0
+                || read_u32(&sp, 40) == 0 || inode_size < 128 || !inode_size.is_power_of_two() {
+            println!("volume {} ext2 superblock corrupted", device_id);
+            return None;
+        }
+        let block_size = 1024 << log_block_size;
+        Some(Self {
+            device_id,
+            block_size,
+            total_size : blocks_count * block_size,
+            writable : ro_compat & !(RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE) == 0,
+            inode_size,
+            inodes_count : read_u32(&sp, 0) as usize,
+            inodes_per_group : read_u32(&sp, 40) as usize,
+            blocks_count,
+            blocks_per_group,
+            first_data_block,
+            first_inode : if rev > 0 { read_u32(&sp, 84) as usize } else { 11 },
+            group_num : (blocks_count - first_data_block + blocks_per_group - 1) / blocks_per_group,
+            filetype : incompat & INCOMPAT_FILETYPE != 0,
+        })
+    }
+
+    pub fn to_system(fs : Arc<Ext2>)->FileSystem {
+        FileSystem::new(get_cache(), fs.clone(), get_id_mgr(), fs.device_id)
+    }
+
+    fn read(&self, addr : usize, len : usize)->Vec<u8> {
+        let mut rt = vec![0u8;len];
+        sync_read_buffer(self.device_id, &mut rt[..], addr);
+        rt
+    }
+
+    fn write(&self, addr : usize, data : &[u8]) {
+        sync_write_buffer(self.device_id, data, addr);
+    }
+
+    fn read_block(&self, block : usize)->Vec<u8> {
+        self.read(block * self.block_size, self.block_size)
+    }
+
+    fn get_u16(&self, addr : usize)->u16 {
+        read_u16(&self.read(addr, 2), 0)
+    }
+
+    fn get_u32(&self, addr : usize)->u32 {
+        read_u32(&self.read(addr, 4), 0)
+    }
+
+    fn set_u16(&self, addr : usize, val : u16) {
+        self.write(addr, &val.to_le_bytes());
+    }
+
+    fn set_u32(&self, addr : usize, val : u32) {
+        self.write(addr, &val.to_le_bytes());
+    }
+
+    /// 组描述符的地址，描述符表在超级块之后的块中
+    fn group_desc(&self, group : usize)->usize {
+        (self.first_data_block + 1) * self.block_size + group * GROUP_DESC_SIZE
+    }
+
+    fn inode_addr(&self, ino : usize)->usize {
+        let group = (ino - 1) / self.inodes_per_group;
+        let idx = (ino - 1) % self.inodes_per_group;
+        let table = self.get_u32(self.group_desc(group) + 8) as usize;
+        table * self.block_size + idx * self.inode_size
+    }
+
+    pub fn inode(&self, ino : usize)->Option<Inode> {
+        if ino == 0 || ino > self.inodes_count {
+            return None;
+        }
+        Some(Inode::parse(&self.read(self.inode_addr(ino), self.inode_size)[..]))
+    }
+
+    /// fresh 为真时其它字段清零，用于新分配的 inode
+    fn save_inode(&self, ino : usize, inode : &Inode, fresh : bool) {
+        let addr = self.inode_addr(ino);
+        let mut raw = if fresh { vec![0u8;self.inode_size] } else { self.read(addr, self.inode_size) };
+        inode.save(&mut raw[..]);
+        self.write(addr, &raw[..]);
+    }
+
+    /// ## 文件的数据块
+    /// 按逻辑块顺序排列，空洞为 0，快速符号链接的目标放在 inode 中，没有数据块
+    fn block_list(&self, inode : &Inode, num : usize)->Vec<usize> {
+        let mut rt = Vec::new();
+        if inode.mode & MODE_TYPE == MODE_SYMLINK && inode.sectors == 0 {
+            return rt;
+        }
+        for i in 0..DIRECT_BLOCKS {
+            self.collect(inode.block[i] as usize, 0, num, &mut rt);
+        }
+        for level in 1..4 {
+            self.collect(inode.block[DIRECT_BLOCKS + level - 1] as usize, level, num, &mut rt);
+        }
+        rt
+    }
+
+    /// 展开一个 level 级的间接块
+    fn collect(&self, ptr : usize, level : usize, num : usize, rt : &mut Vec<usize>) {
+        if rt.len() >= num {
+            return;
+        }
+        if level == 0 {
+            rt.push(ptr);
+            return;
+        }
+        let per = self.block_size / 4;
+        if ptr == 0 {
+            let mut span = 1;
+            for _ in 0..level {
+                span *= per;
+            }
+            let n = rt.len() + span.min(num - rt.len());
+            rt.resize(n, 0);
+            return;
+        }
+        let data = self.read_block(ptr);
+        for i in 0..per {
+            if rt.len() >= num {
+                break;
+            }
+            self.collect(read_u32(&data, i * 4) as usize, level - 1, num, rt);
+        }
+    }
+
+    fn block_num(&self, size : usize)->usize {
+        (size + self.block_size - 1) / self.block_size
+    }
+
+    /// ## 解析目录
+    /// 返回 (inode 号, 名字, 文件类型)，没有文件类型特性时类型为 0
+    fn entries(&self, dir : &Inode)->Vec<(usize, String, u8)> {
+        let mut rt = Vec::new();
+        for block in self.block_list(dir, self.block_num(dir.size)) {
+            if block == 0 {
+                continue;
+            }
+            let data = self.read_block(block);
+            let mut off = 0;
+            while off + DIR_ENTRY_HEAD <= self.block_size {
+                let ino = read_u32(&data, off) as usize;
+                let rec_len = read_u16(&data, off + 4) as usize;
+                if rec_len < DIR_ENTRY_HEAD || off + rec_len > self.block_size {
+                    break;
+                }
+                let name_len = if self.filetype { data[off + 6] as usize } else { read_u16(&data, off + 6) as usize };
+                if ino != 0 && off + DIR_ENTRY_HEAD + name_len <= self.block_size {
+                    let name = &data[off + DIR_ENTRY_HEAD..off + DIR_ENTRY_HEAD + name_len];
+                    let ftype = if self.filetype { data[off + 7] } else { 0 };
+                    rt.push((ino, String::from_utf8_lossy(name).to_string(), ftype));
+                }
+                off += rec_len;
+            }
+        }
+        rt
+    }
+
+    /// 在 bitmap 块中从第 from 位开始找到第一个空闲位并置位，count 为有效位数
+    fn alloc_bit(&self, bitmap : usize, from : usize, count : usize)->Option<usize> {
+        let data = self.read_block(bitmap);
+        let idx = (from..count).find(|i| data[i / 8] & (1 << (i % 8)) == 0)?;
+        self.write(bitmap * self.block_size + idx / 8, &[data[idx / 8] | 1 << (idx % 8)]);
+        Some(idx)
+    }
+
+    /// 调整组描述符与超级块中的空闲计数，desc_offset、sp_offset 为字段的偏移
+    fn count(&self, group : usize, desc_offset : usize, sp_offset : usize, add : bool) {
+        let gd = self.group_desc(group) + desc_offset;
+        let val = self.get_u16(gd);
+        self.set_u16(gd, if add { val + 1 } else { val.saturating_sub(1) });
+        let sp = SUPER_BLOCK_ADDR + sp_offset;
+        let val = self.get_u32(sp);
+        self.set_u32(sp, if add { val + 1 } else { val.saturating_sub(1) });
+    }
+
+    /// ## 分配数据块
+    /// 从 goal 组开始找，新块清零
+    fn alloc_block(&self, goal : usize)->Option<usize> {
+        for k in 0..self.group_num {
+            let group = (goal + k) % self.group_num;
+            let gd = self.group_desc(group);
+            if self.get_u16(gd + 12) == 0 {
+                continue;
+            }
+            let base = self.first_data_block + group * self.blocks_per_group;
+            let count = self.blocks_per_group.min(self.blocks_count - base);
+            if let Some(idx) = self.alloc_bit(self.get_u32(gd) as usize, 0, count) {
+                self.count(group, 12, 12, false);
+                let block = base + idx;
+                self.write(block * self.block_size, &vec![0u8;self.block_size][..]);
+                return Some(block);
+            }
+        }
+        None
+    }
+
+    fn alloc_inode(&self, goal : usize, dir : bool)->Option<usize> {
+        for k in 0..self.group_num {
+            let group = (goal + k) % self.group_num;
+            let gd = self.group_desc(group);
+            if self.get_u16(gd + 14) == 0 {
+                continue;
+            }
+            // 保留的 inode 都在第一组
+            let from = if group == 0 { self.first_inode - 1 } else { 0 };
+            if let Some(idx) = self.alloc_bit(self.get_u32(gd + 4) as usize, from, self.inodes_per_group) {
+                self.count(group, 14, 16, false);
+                if dir {
+                    self.set_u16(gd + 16, self.get_u16(gd + 16) + 1);
+                }
+                return Some(group * self.inodes_per_group + idx + 1);
+            }
+        }
+        None
+    }
+
+    /// 间接块指针为 0 时分配一个
+    fn ensure(&self, ptr : &mut u32, inode : &mut Inode, goal : usize)->Option<usize> {
+        if *ptr == 0 {
+            *ptr = self.alloc_block(goal)? as u32;
+            inode.sectors += self.block_size / SECTOR_SIZE;
+        }
+        Some(*ptr as usize)
+    }
+
+    /// 间接块 table 中第 idx 项，为 0 时分配
+    fn ensure_in(&self, table : usize, idx : usize, inode : &mut Inode, goal : usize)->Option<usize> {
+        let addr = table * self.block_size + idx * 4;
+        let mut ptr = self.get_u32(addr);
+        if ptr == 0 {
+            self.ensure(&mut ptr, inode, goal)?;
+            self.set_u32(addr, ptr);
+        }
+        Some(ptr as usize)
+    }
+
+    /// ## 设置逻辑块 idx 对应的数据块
+    /// 需要时分配间接块，三级间接块不支持写入
+    fn set_block(&self, inode : &mut Inode, idx : usize, block : usize, goal : usize)->Option<()> {
+        let per = self.block_size / 4;
+        if idx < DIRECT_BLOCKS {
+            inode.block[idx] = block as u32;
+            return Some(());
+        }
+        let idx = idx - DIRECT_BLOCKS;
+        if idx >= per + per * per {
+            return None;
+        }
+        let level = if idx < per { 0 } else { 1 };
+        let mut ptr = inode.block[DIRECT_BLOCKS + level];
+        let mut table = self.ensure(&mut ptr, inode, goal)?;
+        inode.block[DIRECT_BLOCKS + level] = ptr;
+        let mut idx = idx;
+        if level == 1 {
+            idx -= per;
+            table = self.ensure_in(table, idx / per, inode, goal)?;
+            idx %= per;
+        }
+        self.set_u32(table * self.block_size + idx * 4, block as u32);
+        Some(())
+    }
+
+    /// ## 写入
+    /// 从 offset 写入 data，需要时分配数据块并扩展文件，返回新的文件大小
+    fn write_at(&self, ino : usize, offset : usize, data : &[u8])->Option<usize> {
+        let mut inode = self.inode(ino)?;
+        let goal = (ino - 1) / self.inodes_per_group;
+        let end = offset + data.len();
+        let list = self.block_list(&inode, self.block_num(inode.size));
+        let mut rt = Some(());
+        for idx in offset / self.block_size..self.block_num(end) {
+            let mut block = list.get(idx).cloned().unwrap_or(0);
+            if block == 0 {
+                block = if let Some(block) = self.alloc_block(goal) { block } else { rt = None; break; };
+                inode.sectors += self.block_size / SECTOR_SIZE;
+                if self.set_block(&mut inode, idx, block, goal).is_none() {
+                    rt = None;
+                    break;
+                }
+            }
+            let st = offset.max(idx * self.block_size);
+            let ed = end.min((idx + 1) * self.block_size);
+            self.write(block * self.block_size + st - idx * self.block_size, &data[st - offset..ed - offset]);
+            inode.size = inode.size.max(ed);
+        }
+        // 磁盘满时已写入的部分保留
+        inode.mtime = rtc::now();
+        inode.ctime = inode.mtime;
+        self.save_inode(ino, &inode, false);
+        rt.map(|_| inode.size)
+    }
+
+    /// 追加到文件末尾，返回新的文件大小，只读或者不是普通文件时返回 None
+    pub fn append(&self, ino : usize, data : &[u8])->Option<usize> {
+        let inode = self.inode(ino)?;
+        if !self.writable || !inode.is_file() {
+            return None;
+        }
+        self.write_at(ino, inode.size, data)
+    }
+
+    /// 在目录 dir 中加入一项，放不下时给目录加一个块
+    /// 哈希索引不再维护，清除索引标志后 ext2 按线性目录读取
+    fn add_entry(&self, dir : usize, name : &str, ino : usize, ftype : u8)->Option<()> {
+        let need = align4(DIR_ENTRY_HEAD + name.len());
+        let mut inode = self.inode(dir)?;
+        if inode.flags & FLAG_INDEX != 0 {
+            inode.flags &= !FLAG_INDEX;
+            self.save_inode(dir, &inode, false);
+        }
+        let list = self.block_list(&inode, self.block_num(inode.size));
+        for block in list.iter().filter(|b| **b != 0) {
+            let mut data = self.read_block(*block);
+            let mut off = 0;
+            while off + DIR_ENTRY_HEAD <= self.block_size {
+                let rec_len = read_u16(&data, off + 4) as usize;
+                if rec_len < DIR_ENTRY_HEAD || off + rec_len > self.block_size {
+                    break;
+                }
+                let used = if read_u32(&data, off) == 0 {
+                    0
+                }
+                else if self.filetype {
+                    align4(DIR_ENTRY_HEAD + data[off + 6] as usize)
+                }
+                else {
+                    align4(DIR_ENTRY_HEAD + read_u16(&data, off + 6) as usize)
+                };
+                if rec_len - used >= need {
+                    if used > 0 {
+                        write_u16(&mut data, off + 4, used as u16);
+                    }
+                    self.fill_entry(&mut data[off + used..off + rec_len], name, ino, ftype);
+                    self.write(*block * self.block_size, &data[..]);
+                    return Some(());
+                }
+                off += rec_len;
+            }
+        }
+        let goal = (dir - 1) / self.inodes_per_group;
+        let block = self.alloc_block(goal)?;
+        inode.sectors += self.block_size / SECTOR_SIZE;
+        self.set_block(&mut inode, list.len(), block, goal)?;
+        let mut data = vec![0u8;self.block_size];
+        self.fill_entry(&mut data[..], name, ino, ftype);
+        self.write(block * self.block_size, &data[..]);
+        inode.size += self.block_size;
+        self.save_inode(dir, &inode, false);
+        Some(())
+    }
+
+    /// 目录项占满 entry
+    fn fill_entry(&self, entry : &mut [u8], name : &str, ino : usize, ftype : u8) {
+        write_u32(entry, 0, ino as u32);
+        write_u16(entry, 4, entry.len() as u16);
+        if self.filetype {
+            entry[6] = name.len() as u8;
+            entry[7] = ftype;
+        }
+        else {
+            write_u16(entry, 6, name.len() as u16);
+        }
+        entry[DIR_ENTRY_HEAD..DIR_ENTRY_HEAD + name.len()].copy_from_slice(name.as_bytes());
+    }
+
+    /// ## 新建
+    /// 在目录 parent 中新建空文件或者目录，返回 inode 号，已存在时返回 None
+    pub fn create(&self, parent : usize, name : &str, dir : bool)->Option<usize> {
+        let mut parent_inode = self.inode(parent)?;
+        if !self.writable || !parent_inode.is_dir() || name.len() == 0 || name.len() > NAME_MAX
+                || name.contains('/') {
+            return None;
+        }
+        if self.entries(&parent_inode).iter().any(|(_, n, _)| n == name) {
+            return None;
+        }
+        let goal = (parent - 1) / self.inodes_per_group;
+        let ino = self.alloc_inode(goal, dir)?;
+        let now = rtc::now();
+        let mut inode = Inode {
+            mode : if dir { MODE_DIRECTORY | 0o755 } else { MODE_FILE | 0o644 },
+            size : 0,
+            atime : now,
+            ctime : now,
+            mtime : now,
+            links : 1,
+            sectors : 0,
+            flags : 0,
+            block : [0;15],
+        };
+        if dir {
+            let block = self.alloc_block(goal)?;
+            let mut data = vec![0u8;self.block_size];
+            self.fill_entry(&mut data[0..12], ".", ino, FT_DIRECTORY);
+            self.fill_entry(&mut data[12..], "..", parent, FT_DIRECTORY);
+            self.write(block * self.block_size, &data[..]);
+            inode.block[0] = block as u32;
+            inode.size = self.block_size;
+            inode.sectors = self.block_size / SECTOR_SIZE;
+            inode.links = 2;
+            parent_inode.links += 1;
+            self.save_inode(parent, &parent_inode, false);
+        }
+        self.save_inode(ino, &inode, true);
+        self.add_entry(parent, name, ino, if dir { FT_DIRECTORY } else { FT_FILE })?;
+        Some(ino)
+    }
+}
+
+impl Format for Ext2 {
+    fn parse_node(&self, block_idx : usize)->Result<Vec<Leaf>, ()> {
+        let dir = self.inode(block_idx).ok_or(())?;
+        if !dir.is_dir() {
+            return Err(());
+        }
+        let mut rt = Vec::new();
+        for (ino, name, ftype) in self.entries(&dir) {
+            let inode = if let Some(inode) = self.inode(ino) { inode } else { continue; };
+            let is_dir = if ftype != 0 { ftype == FT_DIRECTORY } else { inode.is_dir() };
+            rt.push(Leaf {
+                name,
+                ltype : if is_dir { LeafType::Directory } else { LeafType::File },
+                block_idx : ino,
+                size : inode.size,
+            });
+        }
+        Ok(rt)
+    }
+
+    /// tisu_fs 按块号读取，空洞没有对应的块，有空洞的文件与使用 extent 的 inode 都不支持
+    fn get_block_chain(&self, start_idx : usize)->Result<Vec<usize>, ()> {
+        let inode = self.inode(start_idx).ok_or(())?;
+        if inode.flags & FLAG_EXTENTS != 0 {
+            return Err(());
+        }
+        let rt = self.block_list(&inode, self.block_num(inode.size));
+        if rt.contains(&0) {
+            return Err(());
+        }
+        Ok(rt)
+    }
+
+    /// tisu_fs 只区分 FAT32 与天目，ext2 与天目一样块号乘以块大小即为地址
+    fn parse_super_block(&self)->DiskInfo {
+        DiskInfo {
+            stype: SystemType::Tianmu,
+            total_size: self.total_size,
+            block_size: self.block_size,
+            root_directory_block_idx: ROOT_INODE,
+            block_start_addr: 0,
+        }
+    }
+
+    fn get_device(&self)->usize {
+        self.device_id
+    }
+}
+
+fn align4(len : usize)->usize {
+    (len + 3) / 4 * 4
+}
+
+fn read_u16(data : &[u8], st : usize)->u16 {
+    let mut b = [0;2];
+    b.copy_from_slice(&data[st..st + 2]);
+    u16::from_le_bytes(b)
+}
+
+fn read_u32(data : &[u8], st : usize)->u32 {
+    let mut b = [0;4];
+    b.copy_from_slice(&data[st..st + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn write_u16(data : &mut [u8], st : usize, val : u16) {
+    data[st..st + 2].copy_from_slice(&val.to_le_bytes());
+}
+
+fn write_u32(data : &mut [u8], st : usize, val : u32) {
+    data[st..st + 4].copy_from_slice(&val.to_le_bytes());
+}
+
+
+use alloc::{prelude::v1::*, sync::Arc};
+use tisu_fs::{DiskInfo, FileSystem, Format, Leaf, LeafType, SystemType};
+use crate::{filesystem::syscall_io::get_id_mgr, rtc, virtio::disk_cache::{get_cache, sync_read_buffer, sync_write_buffer}};
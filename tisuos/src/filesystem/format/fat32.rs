@@ -389,13 +389,13 @@ pub struct FATShortDirItem{
     ext_name : [u8;3],
     pub attr : u8,
     reserved : u8,
-    create_time_ext : u8, // 10 毫秒位
-    create_time : u16,
-    create_date : u16, // 16
-    last_access_date : u16,
+    pub create_time_ext : u8, // 10 毫秒位
+    pub create_time : u16,
+    pub create_date : u16, // 16
+    pub last_access_date : u16,
     start_cluster_high : u16,
-    last_change_time : u16,
-    last_change_date : u16,
+    pub last_change_time : u16,
+    pub last_change_date : u16,
     start_cluster_low : u16,
     pub file_length : u32,
 }
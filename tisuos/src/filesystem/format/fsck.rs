@@ -0,0 +1,526 @@
+//! # 文件系统检查
+//! 检查 FAT32 与天目文件系统：簇链、块映射链是否越界、成环、交叉，
+//! 是否有不属于任何文件的丢失簇，目录项与长文件名校验和是否正确，天目硬链接的引用计数是否一致
+//! 修复时截断损坏的链、释放丢失的簇。只依赖 alloc，内核与主机上的 fsck 共用
+//!
+//! 2021年6月18日 zg
+
+/// FAT32 表项的有效位
+const FAT_MASK : u32 = 0x0fff_ffff;
+const FAT_BAD : u32 = 0x0fff_fff7;
+const FAT_END : u32 = 0x0fff_fff8;
+const FAT_ENTRY_SIZE : usize = 32;
+const ATTR_DIRECTORY : u8 = 0x10;
+const ATTR_VOLUME : u8 = 0x08;
+const ATTR_LONG_NAME : u8 = 0x0f;
+const ENTRY_DELETED : u8 = 0xe5;
+const LFN_LAST : u8 = 0x40;
+
+/// 被检查的磁盘，地址以字节为单位
+pub trait Disk {
+    fn read(&mut self, st : usize, data : &mut [u8]);
+    fn write(&mut self, st : usize, data : &[u8]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsType {
+    FAT32,
+    TianMu,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub fs : FsType,
+    pub error : Vec<String>,
+    pub repaired : usize,
+    pub file : usize,
+    pub directory : usize,
+    /// 被文件占用的簇或块
+    pub used : usize,
+    pub lost : usize,
+}
+
+impl Report {
+    fn new(fs : FsType)->Self {
+        Self {
+            fs,
+            error : Vec::new(),
+            repaired : 0,
+            file : 0,
+            directory : 0,
+            used : 0,
+            lost : 0,
+        }
+    }
+
+    pub fn is_clean(&self)->bool {
+        self.error.len() == 0
+    }
+}
+
+/// 根据第 512 字节处的魔数识别文件系统
+pub fn detect(disk : &mut dyn Disk)->Option<FsType> {
+    let mut flag = [0u8;4];
+    disk.read(512, &mut flag);
+    if flag == [0x52, 0x52, 0x61, 0x41] {
+        Some(FsType::FAT32)
+    }
+    else if read_u32(&flag, 0) == MAGIC {
+        Some(FsType::TianMu)
+    }
+    else {
+        None
+    }
+}
+
+pub fn check(disk : &mut dyn Disk, repair : bool)->Option<Report> {
+    match detect(disk)? {
+        FsType::FAT32 => Some(check_fat32(disk, repair)),
+        FsType::TianMu => check_tianmu(disk, repair),
+    }
+}
+
+/// 链上的一个位置：所属文件的路径，用于报告交叉链接
+#[derive(Clone)]
+struct Owner {
+    path : String,
+}
+
+/// ## 跟随一条链
+/// next 返回下一个表项，valid 判断编号是否在范围内，is_end 判断链尾
+/// 遇到越界、空闲表项、成环或者与其它文件交叉时停止，返回合法部分以及错误描述
+fn follow<N, V, E>(start : usize, owner : &mut [Option<Owner>], path : &str,
+        mut next : N, valid : V, is_end : E)->(Vec<usize>, Option<String>)
+        where N : FnMut(usize)->u64, V : Fn(u64)->bool, E : Fn(u64)->bool {
+    let mut chain = Vec::new();
+    let mut cur = start as u64;
+    loop {
+        if !valid(cur) {
+            return (chain, Some(format!("{}: chain points to invalid {}", path, cur)));
+        }
+        let idx = cur as usize;
+        if let Some(o) = &owner[idx] {
+            let err = if o.path == path {
+                format!("{}: chain loops at {}", path, idx)
+            }
+            else {
+                format!("{}: cross-linked with {} at {}", path, o.path, idx)
+            };
+            return (chain, Some(err));
+        }
+        owner[idx] = Some(Owner { path : String::from(path) });
+        chain.push(idx);
+        let n = next(idx);
+        if is_end(n) {
+            return (chain, None);
+        }
+        if n == 0 {
+            return (chain, Some(format!("{}: chain runs into free entry after {}", path, idx)));
+        }
+        cur = n;
+    }
+}
+
+struct Fat32 {
+    cluster_size : usize,
+    fat_addr : usize,
+    fat_size : usize,
+    fat_num : usize,
+    data_addr : usize,
+    /// 最大簇号加一
+    cluster_end : usize,
+    root : usize,
+    fat : Vec<u32>,
+    dirty : bool,
+}
+
+impl Fat32 {
+    fn new(disk : &mut dyn Disk)->Option<Self> {
+        let mut boot = [0u8;512];
+        disk.read(0, &mut boot);
+        let bytes_per_sector = read_u16(&boot, 11) as usize;
+        let sector_per_cluster = boot[13] as usize;
+        let reserved = read_u16(&boot, 14) as usize;
+        let fat_num = boot[16] as usize;
+        let total_sector = read_u32(&boot, 32) as usize;
+        let fat_sector = read_u32(&boot, 36) as usize;
+        let root = read_u32(&boot, 44) as usize;
+        if bytes_per_sector == 0 || sector_per_cluster == 0 || fat_num == 0 {
+            return None;
+        }
+        let data_sector = reserved + fat_num * fat_sector;
+        let cluster_num = total_sector.saturating_sub(data_sector) / sector_per_cluster;
+        let fat_size = fat_sector * bytes_per_sector;
+        let cluster_end = (cluster_num + 2).min(fat_size / 4);
+        let mut raw = vec![0u8;cluster_end * 4];
+        disk.read(reserved * bytes_per_sector, &mut raw[..]);
+        let fat = raw.chunks(4).map(|b| read_u32(b, 0) & FAT_MASK).collect();
+        Some(Self {
+            cluster_size : sector_per_cluster * bytes_per_sector,
+            fat_addr : reserved * bytes_per_sector,
+            fat_size,
+            fat_num,
+            data_addr : data_sector * bytes_per_sector,
+            cluster_end,
+            root,
+            fat,
+            dirty : false,
+        })
+    }
+
+    fn cluster_addr(&self, cluster : usize)->usize {
+        self.data_addr + (cluster - 2) * self.cluster_size
+    }
+
+    fn set(&mut self, cluster : usize, val : u32) {
+        self.fat[cluster] = val;
+        self.dirty = true;
+    }
+
+    /// 修改过的表项写回所有 FAT 表副本
+    fn save(&self, disk : &mut dyn Disk) {
+        if !self.dirty {
+            return;
+        }
+        let mut raw = Vec::with_capacity(self.fat.len() * 4);
+        for val in self.fat.iter() {
+            raw.extend_from_slice(&val.to_le_bytes());
+        }
+        for i in 0..self.fat_num {
+            disk.write(self.fat_addr + i * self.fat_size, &raw[..]);
+        }
+    }
+
+    /// 跟随簇链，修复时在最后一个合法簇处截断
+    fn chain(&mut self, start : usize, owner : &mut [Option<Owner>], path : &str,
+            repair : bool, report : &mut Report)->Vec<usize> {
+        let end = self.cluster_end as u64;
+        let fat = &self.fat;
+        let (chain, err) = follow(start, owner, path, |idx| fat[idx] as u64,
+            |c| c >= 2 && c < end, |c| c >= FAT_END as u64 || c == FAT_BAD as u64);
+        if let Some(err) = err {
+            report.error.push(err);
+            if repair {
+                if let Some(last) = chain.last() {
+                    self.set(*last, FAT_MASK);
+                    report.repaired += 1;
+                }
+            }
+        }
+        chain
+    }
+}
+
+/// 短文件名的校验和，写在对应的每个长文件名目录项中
+fn lfn_checksum(name : &[u8])->u8 {
+    let mut sum = 0u8;
+    for c in name.iter() {
+        sum = ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*c);
+    }
+    sum
+}
+
+fn short_name(name : &[u8])->String {
+    let base : String = name[0..8].iter().map(|c| *c as char).collect();
+    let ext : String = name[8..11].iter().map(|c| *c as char).collect();
+    let base = base.trim_end();
+    let ext = ext.trim_end();
+    if ext.len() == 0 {
+        String::from(base)
+    }
+    else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+pub fn check_fat32(disk : &mut dyn Disk, repair : bool)->Report {
+    let mut report = Report::new(FsType::FAT32);
+    let mut fs = if let Some(fs) = Fat32::new(disk) { fs } else {
+        report.error.push(String::from("boot sector broken"));
+        return report;
+    };
+    let mut owner = vec![None;fs.cluster_end];
+    let mut stack = vec![(fs.root, String::from(""))];
+    while let Some((dir, path)) = stack.pop() {
+        report.directory += 1;
+        let chain = fs.chain(dir, &mut owner, if path.len() == 0 { "/" } else { &path },
+            repair, &mut report);
+        // 尚未匹配短文件名的长文件名目录项：(序号, 校验和, 地址)
+        let mut lfn : Vec<(u8, u8, usize)> = Vec::new();
+        'dir: for cluster in chain {
+            let mut data = vec![0u8;fs.cluster_size];
+            let base = fs.cluster_addr(cluster);
+            disk.read(base, &mut data[..]);
+            for (i, item) in data.chunks(FAT_ENTRY_SIZE).enumerate() {
+                let addr = base + i * FAT_ENTRY_SIZE;
+                if item[0] == 0 {
+                    break 'dir;
+                }
+                if item[0] == ENTRY_DELETED {
+                    lfn.clear();
+                    continue;
+                }
+                let attr = item[11];
+                if attr == ATTR_LONG_NAME {
+                    lfn.push((item[0], item[13], addr));
+                    continue;
+                }
+                let name = short_name(&item[0..11]);
+                let full = format!("{}/{}", path, name);
+                if lfn.len() > 0 {
+                    let sum = lfn_checksum(&item[0..11]);
+                    let n = lfn.len() as u8;
+                    let order_ok = lfn[0].0 == (LFN_LAST | n) &&
+                        lfn.iter().enumerate().skip(1).all(|(j, e)| e.0 == n - j as u8);
+                    if lfn.iter().any(|e| e.1 != sum) || !order_ok {
+                        report.error.push(format!("{}: long name entries do not match", full));
+                        if repair {
+                            for e in lfn.iter() {
+                                disk.write(e.2, &[ENTRY_DELETED]);
+                            }
+                            report.repaired += 1;
+                        }
+                    }
+                    lfn.clear();
+                }
+                if attr & ATTR_VOLUME != 0 || name == "." || name == ".." {
+                    continue;
+                }
+                let start = ((read_u16(item, 20) as usize) << 16) | read_u16(item, 26) as usize;
+                let size = read_u32(item, 28) as usize;
+                if start == 0 {
+                    if size != 0 || attr & ATTR_DIRECTORY != 0 {
+                        report.error.push(format!("{}: no cluster but size {}", full, size));
+                        if repair {
+                            let mut entry = item.to_vec();
+                            write_u32(&mut entry, 28, 0);
+                            disk.write(addr, &entry[..]);
+                            report.repaired += 1;
+                        }
+                    }
+                    report.file += 1;
+                    continue;
+                }
+                if attr & ATTR_DIRECTORY != 0 {
+                    stack.push((start, full));
+                    continue;
+                }
+                report.file += 1;
+                let chain = fs.chain(start, &mut owner, &full, repair, &mut report);
+                let need = (size + fs.cluster_size - 1) / fs.cluster_size;
+                if chain.len() > need && need > 0 {
+                    report.error.push(format!("{}: {} clusters for size {}", full, chain.len(), size));
+                    if repair {
+                        fs.set(chain[need - 1], FAT_MASK);
+                        for c in chain[need..].iter() {
+                            owner[*c] = None;
+                        }
+                        report.repaired += 1;
+                    }
+                }
+                else if chain.len() < need {
+                    report.error.push(format!("{}: size {} beyond {} clusters", full, size, chain.len()));
+                    if repair {
+                        let mut entry = item.to_vec();
+                        write_u32(&mut entry, 28, (chain.len() * fs.cluster_size) as u32);
+                        disk.write(addr, &entry[..]);
+                        report.repaired += 1;
+                    }
+                }
+            }
+        }
+    }
+    for (c, o) in owner.iter().enumerate().skip(2) {
+        if o.is_some() {
+            report.used += 1;
+        }
+        else if fs.fat[c] != 0 && fs.fat[c] != FAT_BAD {
+            report.lost += 1;
+            if repair {
+                fs.set(c, 0);
+            }
+        }
+    }
+    if report.lost > 0 {
+        report.error.push(format!("{} lost clusters", report.lost));
+        if repair {
+            report.repaired += 1;
+        }
+    }
+    fs.save(disk);
+    report
+}
+
+/// 被多个目录项引用的数据链，即硬链接
+struct Shared {
+    path : String,
+    length : usize,
+    /// 引用这条链的目录项地址与内容
+    item : Vec<(usize, DirItem)>,
+}
+
+/// 天目检查过程中的状态
+struct TianMuCheck {
+    map : Vec<u64>,
+    owner : Vec<Option<Owner>>,
+    /// 修复时改动的块映射表项，最后统一写回
+    changed : Vec<(usize, u64)>,
+    repair : bool,
+}
+
+impl TianMuCheck {
+    /// 跟随块映射链，修复时把最后一个合法块标记为链尾
+    fn chain(&mut self, start : usize, path : &str, report : &mut Report)->Vec<usize> {
+        let num = self.map.len();
+        let map = &self.map;
+        let (chain, err) = follow(start, &mut self.owner, path, |idx| map[idx],
+            |c| c > 0 && (c as usize) < num, |c| c == END);
+        if let Some(err) = err {
+            report.error.push(err);
+            if self.repair {
+                if let Some(last) = chain.last() {
+                    self.map[*last] = END;
+                    self.changed.push((*last, END));
+                    report.repaired += 1;
+                }
+            }
+        }
+        chain
+    }
+}
+
+pub fn check_tianmu(disk : &mut dyn Disk, repair : bool)->Option<Report> {
+    let mut report = Report::new(FsType::TianMu);
+    let mut head = vec![0u8;SUPER_BLOCK_SIZE];
+    disk.read(0, &mut head[..]);
+    let sp = SuperBlock::parse(&head[..])?;
+    let num = sp.block_num();
+    let mut raw = vec![0u8;num * MAP_ITEM_SIZE];
+    disk.read(sp.block_map_addr, &mut raw[..]);
+    let mut ck = TianMuCheck {
+        map : raw.chunks(MAP_ITEM_SIZE).map(|b| read_u64(b, 0)).collect(),
+        owner : vec![None;num],
+        changed : Vec::new(),
+        repair,
+    };
+    // 超级块与块映射所在的块
+    let meta_end = (sp.block_map_addr + num * MAP_ITEM_SIZE + sp.block_size - 1) / sp.block_size;
+    for owner in ck.owner.iter_mut().take(meta_end) {
+        *owner = Some(Owner { path : String::from("<metadata>") });
+    }
+    // 日志保留的块
+    if num > JOURNAL_BLOCKS && ck.map[num - JOURNAL_BLOCKS..].iter().all(|v| *v != FREE) {
+        for owner in ck.owner.iter_mut().skip(num - JOURNAL_BLOCKS) {
+            *owner = Some(Owner { path : String::from("<journal>") });
+        }
+    }
+    let per_block = sp.block_size / DIR_ITEM_SIZE;
+    let mut shared : BTreeMap<usize, Shared> = BTreeMap::new();
+    let mut stack = vec![(sp.root_idx, String::from(""))];
+    while let Some((dir, path)) = stack.pop() {
+        report.directory += 1;
+        let name = if path.len() == 0 { String::from("/") } else { path.clone() };
+        for block in ck.chain(dir, &name, &mut report) {
+            let mut data = vec![0u8;sp.block_size];
+            disk.read(sp.block_addr(block), &mut data[..]);
+            for i in 0..per_block {
+                let addr = sp.block_addr(block) + i * DIR_ITEM_SIZE;
+                let item = DirItem::parse(&data[i * DIR_ITEM_SIZE..(i + 1) * DIR_ITEM_SIZE]);
+                if item.empty() {
+                    continue;
+                }
+                let full = format!("{}/{}", path, item.name());
+                let valid = item.dtype == TYPE_FILE || item.dtype == TYPE_DIRECTORY || item.dtype == TYPE_SYMLINK;
+                if !valid || item.name().len() == 0 {
+                    report.error.push(format!("{}: invalid entry type {}", full, item.dtype));
+                    if repair {
+                        disk.write(addr, &[0u8;DIR_ITEM_SIZE]);
+                        report.repaired += 1;
+                    }
+                    continue;
+                }
+                if item.start_block == 0 {
+                    report.file += 1;
+                    continue;
+                }
+                if item.is_dir() {
+                    stack.push((item.start_block, full));
+                    continue;
+                }
+                report.file += 1;
+                // 已经检查过的链是硬链接，只记录引用
+                if let Some(s) = shared.get_mut(&item.start_block) {
+                    if item.length != s.length {
+                        report.error.push(format!("{}: size {} differs from link {} size {}",
+                            full, item.length, s.path, s.length));
+                    }
+                    s.item.push((addr, item));
+                    continue;
+                }
+                let chain = ck.chain(item.start_block, &full, &mut report);
+                let mut item = item;
+                if chain.len() * sp.block_size < item.length {
+                    report.error.push(format!("{}: size {} beyond {} blocks", full, item.length, chain.len()));
+                    if repair {
+                        item.length = chain.len() * sp.block_size;
+                        disk.write(addr, &item.to_bytes()[..]);
+                        report.repaired += 1;
+                    }
+                }
+                shared.insert(item.start_block, Shared {
+                    path : full,
+                    length : item.length,
+                    item : vec![(addr, item)],
+                });
+            }
+        }
+    }
+    for s in shared.values() {
+        let refs = s.item.len();
+        if s.item.iter().all(|(_, item)| item.link_count() == refs) {
+            continue;
+        }
+        report.error.push(format!("{}: link count {} but {} entries refer to it",
+            s.path, s.item[0].1.link_count(), refs));
+        if repair {
+            for (addr, item) in s.item.iter() {
+                let mut fixed = item.clone();
+                fixed.links = refs.min(LINK_MAX) as u8;
+                disk.write(*addr, &fixed.to_bytes()[..]);
+            }
+            report.repaired += 1;
+        }
+    }
+    for i in 0..num {
+        if ck.owner[i].is_some() {
+            report.used += 1;
+        }
+        else if ck.map[i] != FREE {
+            report.lost += 1;
+            if repair {
+                ck.changed.push((i, FREE));
+            }
+        }
+    }
+    if report.lost > 0 {
+        report.error.push(format!("{} lost blocks", report.lost));
+        if repair {
+            report.repaired += 1;
+        }
+    }
+    for (idx, val) in ck.changed {
+        disk.write(sp.map_addr(idx), &val.to_le_bytes());
+    }
+    Some(report)
+}
+
+fn read_u16(data : &[u8], st : usize)->u16 {
+    let mut b = [0;2];
+    b.copy_from_slice(&data[st..st + 2]);
+    u16::from_le_bytes(b)
+}
+
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use super::tianmu_disk::{DIR_ITEM_SIZE, DirItem, END, FREE, JOURNAL_BLOCKS, LINK_MAX, MAGIC, MAP_ITEM_SIZE,
+    SUPER_BLOCK_SIZE, SuperBlock, TYPE_DIRECTORY, TYPE_FILE, TYPE_SYMLINK, read_u32, read_u64, write_u32};
@@ -0,0 +1,323 @@
+//! # 天目文件系统日志
+//! 日志放在卷末尾的 JOURNAL_BLOCKS 个块中，这些块在块映射中被标记为已占用，由 mkfs 创建
+//! 事务期间对卷的写入只记录在内存中，提交时先把文件数据直接写回，元数据（超级块、块映射、目录项）
+//! 依次写入开始记录、数据记录、提交记录并写回，再写入缓冲并写回，最后清空日志
+//! 元数据超出日志区域时拆成多个事务依次提交。挂载时发现完整的事务就重新写入一遍
+//!
+//! 2021年6月17日 zg
+
+const JOURNAL_MAGIC : u32 = 0x4c4e_4d54;
+const RECORD_SIZE : usize = 32;
+static mut JOURNAL : Option<ContentMutex<Vec<Journal>>> = None;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    Empty = 0,
+    Begin = 1,
+    Data = 2,
+    Commit = 3,
+}
+
+/// ## 日志记录头
+/// 数据记录的 addr、len 为写入的位置与长度，其后紧跟内容
+/// 提交记录的 addr 为数据记录数量，len 为校验和
+struct Record {
+    kind : u32,
+    sequence : u64,
+    addr : u64,
+    len : u64,
+}
+
+impl Record {
+    fn new(kind : RecordKind, sequence : u64, addr : usize, len : usize)->Self {
+        Self {
+            kind : kind as u32,
+            sequence,
+            addr : addr as u64,
+            len : len as u64,
+        }
+    }
+
+    fn push_to(&self, buf : &mut Vec<u8>) {
+        buf.extend_from_slice(&JOURNAL_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&self.kind.to_le_bytes());
+        buf.extend_from_slice(&self.sequence.to_le_bytes());
+        buf.extend_from_slice(&self.addr.to_le_bytes());
+        buf.extend_from_slice(&self.len.to_le_bytes());
+    }
+
+    fn parse(data : &[u8])->Option<Self> {
+        let mut b4 = [0;4];
+        let mut b8 = [0;8];
+        b4.copy_from_slice(&data[0..4]);
+        if u32::from_le_bytes(b4) != JOURNAL_MAGIC {
+            return None;
+        }
+        b4.copy_from_slice(&data[4..8]);
+        let kind = u32::from_le_bytes(b4);
+        b8.copy_from_slice(&data[8..16]);
+        let sequence = u64::from_le_bytes(b8);
+        b8.copy_from_slice(&data[16..24]);
+        let addr = u64::from_le_bytes(b8);
+        b8.copy_from_slice(&data[24..32]);
+        let len = u64::from_le_bytes(b8);
+        Some(Self {
+            kind,
+            sequence,
+            addr,
+            len,
+        })
+    }
+
+    fn is(&self, kind : RecordKind)->bool {
+        self.kind == kind as u32
+    }
+}
+
+struct Journal {
+    volume : usize,
+    /// 日志区域在卷内的起始地址与长度
+    start : usize,
+    size : usize,
+    sequence : u64,
+    block_size : usize,
+    block_map_addr : usize,
+    block_num : usize,
+}
+
+impl Journal {
+    /// 检查卷末尾的块是否为日志保留，没有保留说明卷不带日志
+    fn new(tm : &TianMu)->Option<Self> {
+        let tm = &tm.0;
+        let total = tm.total_size / tm.block_size;
+        if total <= JOURNAL_BLOCKS {
+            return None;
+        }
+        let first = total - JOURNAL_BLOCKS;
+        let mut map = vec![0u8;JOURNAL_BLOCKS * 8];
+        sync_read_buffer(tm.device_id, &mut map[..], tm.block_map_addr + first * 8);
+        if map.chunks(8).any(|b| b.iter().all(|x| *x == 0)) {
+            return None;
+        }
+        let mut rt = Self {
+            volume : tm.device_id,
+            start : first * tm.block_size,
+            size : JOURNAL_BLOCKS * tm.block_size,
+            sequence : 0,
+            block_size : tm.block_size,
+            block_map_addr : tm.block_map_addr,
+            block_num : total,
+        };
+        if let Some(head) = rt.read_record(0) {
+            rt.sequence = head.sequence;
+        }
+        Some(rt)
+    }
+
+    fn read_record(&self, pos : usize)->Option<Record> {
+        if pos + RECORD_SIZE > self.size {
+            return None;
+        }
+        let mut buf = [0u8;RECORD_SIZE];
+        sync_read_buffer(self.volume, &mut buf, self.start + pos);
+        Record::parse(&buf)
+    }
+
+    /// ## 重放
+    /// 只有提交记录完整、校验和一致的事务才会被重新写入，否则丢弃
+    fn replay(&mut self) {
+        let head = if let Some(head) = self.read_record(0) { head } else { return; };
+        if !head.is(RecordKind::Begin) {
+            return;
+        }
+        let mut pos = RECORD_SIZE;
+        let mut writes = Vec::new();
+        let mut sum = 0;
+        loop {
+            let rec = if let Some(rec) = self.read_record(pos) { rec } else { break; };
+            if rec.sequence != head.sequence {
+                break;
+            }
+            pos += RECORD_SIZE;
+            if rec.is(RecordKind::Data) {
+                let len = rec.len as usize;
+                if pos + len > self.size {
+                    break;
+                }
+                let mut data = vec![0u8;len];
+                sync_read_buffer(self.volume, &mut data[..], self.start + pos);
+                sum = checksum(sum, rec.addr as usize, &data[..]);
+                writes.push((rec.addr as usize, data));
+                pos += len;
+            }
+            else if rec.is(RecordKind::Commit) {
+                if rec.addr as usize == writes.len() && rec.len == sum {
+                    println!("volume {} replay journal, {} writes", self.volume, writes.len());
+                    self.apply(writes);
+                    self.clear();
+                }
+                return;
+            }
+            else {
+                break;
+            }
+        }
+        println!("volume {} discard incomplete transaction", self.volume);
+        self.clear();
+    }
+
+    /// ## 提交
+    /// 文件数据不进入日志，先直接写回，之后元数据按日志能容纳的大小拆分，依次作为事务提交
+    fn commit(&mut self, data : Vec<(usize, Vec<u8>)>, meta : Vec<(usize, Vec<u8>)>) {
+        if data.len() > 0 {
+            self.apply(data);
+        }
+        // 开始、提交记录之外能容纳的数据记录
+        let limit = self.size - RECORD_SIZE * 2;
+        let piece_size = limit - RECORD_SIZE;
+        let mut part = Vec::new();
+        let mut len = 0;
+        let mut num = 0;
+        for (addr, buf) in meta {
+            for (i, piece) in buf.chunks(piece_size).enumerate() {
+                if len + RECORD_SIZE + piece.len() > limit {
+                    self.log(replace(&mut part, Vec::new()));
+                    len = 0;
+                    num += 1;
+                }
+                len += RECORD_SIZE + piece.len();
+                part.push((addr + i * piece_size, piece.to_vec()));
+            }
+        }
+        if num > 0 {
+            println!("volume {} transaction too large for journal, split into {} parts", self.volume, num + 1);
+        }
+        self.log(part);
+    }
+
+    /// 写入一个不超过日志区域的事务
+    fn log(&mut self, writes : Vec<(usize, Vec<u8>)>) {
+        if writes.len() == 0 {
+            return;
+        }
+        self.sequence += 1;
+        let mut log = Vec::new();
+        let mut sum = 0;
+        Record::new(RecordKind::Begin, self.sequence, 0, 0).push_to(&mut log);
+        for (addr, data) in writes.iter() {
+            Record::new(RecordKind::Data, self.sequence, *addr, data.len()).push_to(&mut log);
+            log.extend_from_slice(&data[..]);
+            sum = checksum(sum, *addr, &data[..]);
+        }
+        Record::new(RecordKind::Commit, self.sequence, writes.len(), sum as usize).push_to(&mut log);
+        sync_write_buffer(self.volume, &log[..], self.start);
+        flush(self.volume);
+        self.apply(writes);
+        self.clear();
+    }
+
+    /// 从 start 开始的块链，通过缓冲读取，事务中尚未提交的分配也能看到
+    fn chain(&self, start : usize)->Vec<usize> {
+        let mut rt = Vec::new();
+        let mut idx = start as u64;
+        let mut buf = [0u8;8];
+        while idx != FREE && idx != END && (idx as usize) < self.block_num && rt.len() < self.block_num {
+            rt.push(idx as usize);
+            sync_read_buffer(self.volume, &mut buf, self.block_map_addr + idx as usize * MAP_ITEM_SIZE);
+            idx = u64::from_le_bytes(buf);
+        }
+        rt
+    }
+
+    /// 写入范围内的块全部属于文件链时是文件数据
+    fn is_data(&self, chain : &BTreeSet<usize>, addr : usize, len : usize)->bool {
+        len > 0 && (addr / self.block_size..=(addr + len - 1) / self.block_size).all(|b| chain.contains(&b))
+    }
+
+    fn apply(&self, writes : Vec<(usize, Vec<u8>)>) {
+        for (addr, data) in writes {
+            sync_write_buffer(self.volume, &data[..], addr);
+        }
+        flush(self.volume);
+    }
+
+    /// 日志头改为空记录，保留序号。写回前崩溃只会重放一遍同样的内容
+    fn clear(&mut self) {
+        let mut buf = Vec::new();
+        Record::new(RecordKind::Empty, self.sequence, 0, 0).push_to(&mut buf);
+        sync_write_buffer(self.volume, &buf[..], self.start);
+    }
+}
+
+fn checksum(mut sum : u64, addr : usize, data : &[u8])->u64 {
+    sum = sum.rotate_left(7) ^ addr as u64;
+    for b in data {
+        sum = sum.rotate_left(5) ^ *b as u64;
+    }
+    sum
+}
+
+fn get_journal()->&'static mut ContentMutex<Vec<Journal>> {
+    unsafe {
+        if JOURNAL.is_none() {
+            JOURNAL = Some(ContentMutex::new(Vec::new(), true));
+        }
+        JOURNAL.as_mut().unwrap()
+    }
+}
+
+/// ## 挂载
+/// 在建立文件系统之前调用，卷带有日志时先重放未完成的事务
+pub fn mount(tm : &TianMu) {
+    if let Some(mut journal) = Journal::new(tm) {
+        journal.replay();
+        get_journal().lock().push(journal);
+    }
+    else {
+        println!("volume {} has no journal", tm.0.device_id);
+    }
+}
+
+/// ## 事务
+/// 文件系统 system 上的修改操作放在 f 中执行，带日志的卷整体提交，其它卷直接执行
+pub fn transaction<T, F : FnOnce()->T>(system : Option<usize>, f : F)->T {
+    let volume = system.and_then(system_volume);
+    let mut journals = get_journal().lock();
+    if let Some(journal) = journals.iter_mut().find(|j| Some(j.volume) == volume) {
+        begin_capture(journal.volume);
+        let rt = f();
+        let writes = end_capture(journal.volume);
+        journal.commit(Vec::new(), writes);
+        rt
+    }
+    else {
+        f()
+    }
+}
+
+/// ## 写入文件的事务
+/// f 返回结果与文件第一个块的块号，写入文件链上的块的内容是文件数据，不进入日志，其余与 transaction 相同
+pub fn write_transaction<T, F : FnOnce()->(T, usize)>(system : Option<usize>, f : F)->T {
+    let volume = system.and_then(system_volume);
+    let mut journals = get_journal().lock();
+    if let Some(journal) = journals.iter_mut().find(|j| Some(j.volume) == volume) {
+        begin_capture(journal.volume);
+        let (rt, start) = f();
+        let chain : BTreeSet<usize> = journal.chain(start).into_iter().collect();
+        let (data, meta) : (Vec<_>, Vec<_>) = end_capture(journal.volume).into_iter().partition(|(addr, buf)| {
+            journal.is_data(&chain, *addr, buf.len())
+        });
+        journal.commit(data, meta);
+        rt
+    }
+    else {
+        f().0
+    }
+}
+
+
+use core::mem::replace;
+use alloc::{prelude::v1::*, collections::BTreeSet};
+use tisu_sync::ContentMutex;
+use crate::{filesystem::system_volume, virtio::disk_cache::{begin_capture, end_capture, flush, sync_read_buffer, sync_write_buffer}};
+use super::{tianmu::TianMu, tianmu_disk::{END, FREE, JOURNAL_BLOCKS, MAP_ITEM_SIZE}};
@@ -0,0 +1,307 @@
+//! # 天目链接
+//! 符号链接是类型为 TYPE_SYMLINK 的目录项，数据链中保存目标路径
+//! 硬链接是指向同一数据链的多个文件目录项，目录项中的 links 记录数据链被引用的次数
+//! tisu_fs 查找路径时不认识符号链接，系统调用先在这里把路径中的符号链接展开
+//!
+//! 2021年6月21日 zg
+
+/// 展开符号链接的次数上限，超过认为成环
+const SYMLINK_MAX : usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkError {
+    NotFound,
+    Exist,
+    /// 符号链接展开次数过多
+    Loop,
+    /// 硬链接只能指向有数据的普通文件
+    NotFile,
+    InvalidName,
+    NoSpace,
+    /// 文件系统不支持链接
+    Unsupported,
+}
+
+/// 直接读写卷上的天目目录与块映射
+pub struct Volume {
+    id : usize,
+    sp : SuperBlock,
+}
+
+impl Volume {
+    pub fn new(tm : &TianMu)->Self {
+        let tm = &tm.0;
+        Self {
+            id : tm.device_id,
+            sp : SuperBlock {
+                total_size : tm.total_size,
+                block_size : tm.block_size,
+                block_map_addr : tm.block_map_addr,
+                root_idx : tm.root_idx as usize,
+            },
+        }
+    }
+
+    /// 块映射链，遇到空闲、越界或者超过总块数时停止
+    fn chain(&self, start : usize)->Vec<usize> {
+        let num = self.sp.block_num();
+        let mut rt = Vec::new();
+        let mut cur = start as u64;
+        let mut buf = [0u8;MAP_ITEM_SIZE];
+        while cur != FREE && cur != END && (cur as usize) < num && rt.len() < num {
+            rt.push(cur as usize);
+            sync_read_buffer(self.id, &mut buf, self.sp.map_addr(cur as usize));
+            cur = read_u64(&buf, 0);
+        }
+        rt
+    }
+
+    fn read_data(&self, start : usize, len : usize)->Vec<u8> {
+        let mut rt = vec![0u8;len];
+        for (i, block) in self.chain(start).iter().enumerate() {
+            let st = i * self.sp.block_size;
+            if st >= len {
+                break;
+            }
+            let ed = len.min(st + self.sp.block_size);
+            sync_read_buffer(self.id, &mut rt[st..ed], self.sp.block_addr(*block));
+        }
+        rt
+    }
+
+    /// 目录中所有非空目录项及其地址
+    fn items(&self, dir : usize)->Vec<(usize, DirItem)> {
+        let mut rt = Vec::new();
+        let mut data = vec![0u8;self.sp.block_size];
+        for block in self.chain(dir) {
+            let base = self.sp.block_addr(block);
+            sync_read_buffer(self.id, &mut data[..], base);
+            for (i, raw) in data.chunks(DIR_ITEM_SIZE).enumerate() {
+                let item = DirItem::parse(raw);
+                if !item.empty() {
+                    rt.push((base + i * DIR_ITEM_SIZE, item));
+                }
+            }
+        }
+        rt
+    }
+
+    fn lookup(&self, dir : usize, name : &str)->Option<(usize, DirItem)> {
+        self.items(dir).into_iter().find(|(_, item)| item.name() == name)
+    }
+
+    fn write_item(&self, addr : usize, item : &DirItem) {
+        sync_write_buffer(self.id, &item.to_bytes()[..], addr);
+    }
+
+    /// 分配 num 个空闲块并连成链，块映射一次读入
+    fn alloc(&self, num : usize)->Result<Vec<usize>, LinkError> {
+        let total = self.sp.block_num();
+        let mut raw = vec![0u8;total * MAP_ITEM_SIZE];
+        sync_read_buffer(self.id, &mut raw[..], self.sp.block_map_addr);
+        let chain : Vec<usize> = (1..total).filter(|i| read_u64(&raw[..], i * MAP_ITEM_SIZE) == FREE)
+            .take(num).collect();
+        if chain.len() < num {
+            return Err(LinkError::NoSpace);
+        }
+        for (i, block) in chain.iter().enumerate() {
+            let next = if i + 1 < chain.len() { chain[i + 1] as u64 } else { END };
+            sync_write_buffer(self.id, &next.to_le_bytes(), self.sp.map_addr(*block));
+        }
+        Ok(chain)
+    }
+
+    fn write_data(&self, chain : &[usize], data : &[u8]) {
+        let mut buf = vec![0u8;self.sp.block_size];
+        for (i, block) in chain.iter().enumerate() {
+            let st = (i * self.sp.block_size).min(data.len());
+            let ed = data.len().min(st + self.sp.block_size);
+            for b in buf.iter_mut() {
+                *b = 0;
+            }
+            buf[..ed - st].copy_from_slice(&data[st..ed]);
+            sync_write_buffer(self.id, &buf[..], self.sp.block_addr(*block));
+        }
+    }
+
+    /// 放入第一个空目录项，目录已满时在链尾加一个块
+    fn add_item(&self, dir : usize, item : &DirItem)->Result<(), LinkError> {
+        let chain = self.chain(dir);
+        let mut data = vec![0u8;self.sp.block_size];
+        for block in chain.iter() {
+            let base = self.sp.block_addr(*block);
+            sync_read_buffer(self.id, &mut data[..], base);
+            if let Some(i) = data.chunks(DIR_ITEM_SIZE).position(|raw| DirItem::parse(raw).empty()) {
+                self.write_item(base + i * DIR_ITEM_SIZE, item);
+                return Ok(());
+            }
+        }
+        let last = *chain.last().ok_or(LinkError::NotFound)?;
+        let block = self.alloc(1)?[0];
+        self.write_data(&[block], &[]);
+        sync_write_buffer(self.id, &(block as u64).to_le_bytes(), self.sp.map_addr(last));
+        self.write_item(self.sp.block_addr(block), item);
+        Ok(())
+    }
+
+    /// ## 展开符号链接
+    /// follow 为假时不展开最后一级，.. 按已展开的路径回退
+    /// 不存在的部分原样保留，交给 tisu_fs 报错或者新建
+    pub fn resolve(&self, path : &str, follow : bool)->Result<String, LinkError> {
+        let mut rest = split(path);
+        rest.reverse();
+        let mut done : Vec<String> = Vec::new();
+        let mut dirs = vec![self.sp.root_idx];
+        let mut hops = 0;
+        while let Some(name) = rest.pop() {
+            if name == ".." {
+                if done.pop().is_some() {
+                    dirs.pop();
+                }
+                continue;
+            }
+            let dir = *dirs.last().unwrap();
+            let item = if let Some((_, item)) = self.lookup(dir, &name) {
+                item
+            }
+            else {
+                done.push(name);
+                done.extend(rest.drain(..).rev());
+                break;
+            };
+            if item.is_symlink() && (rest.len() > 0 || follow) {
+                hops += 1;
+                if hops > SYMLINK_MAX {
+                    return Err(LinkError::Loop);
+                }
+                let target = self.read_data(item.start_block, item.length);
+                let target = String::from_utf8_lossy(&target[..]).to_string();
+                if target.starts_with('/') {
+                    done.clear();
+                    dirs.truncate(1);
+                }
+                let mut t = split(&target);
+                t.reverse();
+                rest.extend(t);
+                continue;
+            }
+            done.push(name);
+            if item.is_dir() {
+                dirs.push(item.start_block);
+            }
+            else {
+                done.extend(rest.drain(..).rev());
+                break;
+            }
+        }
+        Ok(format!("/{}", done.join("/")))
+    }
+
+    /// 找到不含符号链接的路径所在的目录与最后一级名字
+    fn parent(&self, path : &str)->Result<(usize, String), LinkError> {
+        let mut names = split(path);
+        let name = names.pop().ok_or(LinkError::InvalidName)?;
+        if name == ".." || name.len() >= NAME_LEN {
+            return Err(LinkError::InvalidName);
+        }
+        let mut dir = self.sp.root_idx;
+        for n in names {
+            match self.lookup(dir, &n) {
+                Some((_, item)) if item.is_dir() => dir = item.start_block,
+                _ => return Err(LinkError::NotFound),
+            }
+        }
+        Ok((dir, name))
+    }
+
+    /// 读取符号链接的目标，不展开最后一级
+    pub fn readlink(&self, path : &str)->Result<String, LinkError> {
+        let path = self.resolve(path, false)?;
+        let (dir, name) = self.parent(&path)?;
+        let (_, item) = self.lookup(dir, &name).ok_or(LinkError::NotFound)?;
+        if !item.is_symlink() {
+            return Err(LinkError::NotFile);
+        }
+        let target = self.read_data(item.start_block, item.length);
+        Ok(String::from_utf8_lossy(&target[..]).to_string())
+    }
+
+    /// 在 path 处新建指向 target 的符号链接，target 不必存在
+    pub fn symlink(&self, target : &str, path : &str)->Result<(), LinkError> {
+        if target.len() == 0 {
+            return Err(LinkError::InvalidName);
+        }
+        let path = self.resolve(path, false)?;
+        let (dir, name) = self.parent(&path)?;
+        if self.lookup(dir, &name).is_some() {
+            return Err(LinkError::Exist);
+        }
+        let num = (target.len() + self.sp.block_size - 1) / self.sp.block_size;
+        let chain = self.alloc(num)?;
+        self.write_data(&chain[..], target.as_bytes());
+        self.add_item(dir, &DirItem::new(&name, TYPE_SYMLINK, chain[0], target.len()))
+    }
+
+    /// ## 硬链接
+    /// 新目录项与 old 共用数据链，所有引用这条链的目录项的 links 同时加一
+    pub fn link(&self, old : &str, new : &str)->Result<(), LinkError> {
+        let old = self.resolve(old, true)?;
+        let (dir, name) = self.parent(&old)?;
+        let (_, item) = self.lookup(dir, &name).ok_or(LinkError::NotFound)?;
+        if item.dtype != TYPE_FILE || item.start_block == 0 {
+            return Err(LinkError::NotFile);
+        }
+        let new = self.resolve(new, false)?;
+        let (new_dir, new_name) = self.parent(&new)?;
+        if self.lookup(new_dir, &new_name).is_some() {
+            return Err(LinkError::Exist);
+        }
+        let links = item.link_count() + 1;
+        if links > LINK_MAX {
+            return Err(LinkError::NoSpace);
+        }
+        let mut linked = DirItem::new(&new_name, TYPE_FILE, item.start_block, item.length);
+        linked.links = links as u8;
+        self.add_item(new_dir, &linked)?;
+        self.update_links(item.start_block, |it| it.links = links as u8);
+        Ok(())
+    }
+
+    /// ## 同步硬链接的长度
+    /// tisu_fs 写入时只更新打开的目录项，数据链被多个目录项引用时把它们的长度都改为 length
+    pub fn set_length(&self, path : &str, length : usize) {
+        let item = self.parent(path).ok().and_then(|(dir, name)| self.lookup(dir, &name));
+        if let Some((_, item)) = item {
+            if item.dtype == TYPE_FILE && item.start_block != 0 && item.link_count() > 1 {
+                self.update_links(item.start_block, |it| it.length = length);
+            }
+        }
+    }
+
+    /// 遍历整棵目录树，修改所有引用数据链 start 的文件目录项
+    fn update_links<F : Fn(&mut DirItem)>(&self, start : usize, f : F) {
+        let mut stack = vec![self.sp.root_idx];
+        while let Some(dir) = stack.pop() {
+            for (addr, mut it) in self.items(dir) {
+                if it.is_dir() && it.start_block != 0 {
+                    stack.push(it.start_block);
+                }
+                else if it.dtype == TYPE_FILE && it.start_block == start {
+                    f(&mut it);
+                    self.write_item(addr, &it);
+                }
+            }
+        }
+    }
+}
+
+/// 拆成各级名字，忽略空的部分与 .
+fn split(path : &str)->Vec<String> {
+    path.split('/').filter(|s| s.len() > 0 && *s != ".").map(|s| s.to_string()).collect()
+}
+
+
+use alloc::prelude::v1::*;
+use crate::virtio::disk_cache::{sync_read_buffer, sync_write_buffer};
+use super::{tianmu::TianMu, tianmu_disk::{DIR_ITEM_SIZE, DirItem, END, FREE, LINK_MAX, MAP_ITEM_SIZE, NAME_LEN, SuperBlock,
+    TYPE_FILE, TYPE_SYMLINK, read_u64}};
@@ -12,7 +12,13 @@ use super::syscall_io::get_id_mgr;
 
 pub mod fat32;
 pub mod tianmu;
+pub mod ext2;
 pub mod elf;
+pub mod partition;
+pub mod journal;
+pub mod link;
+pub mod fsck;
+pub mod tianmu_disk;
 mod disk_type;
 
 pub use disk_type::*;
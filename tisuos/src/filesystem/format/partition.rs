@@ -0,0 +1,119 @@
+//! # 分区表
+//! 解析 MBR 与 GPT 分区表，得到每个分区在磁盘上的位置
+//! MBR 只支持四个主分区，扩展分区被忽略
+//!
+//! 2021年6月16日 zg
+
+const SECTOR_SIZE : usize = 512;
+const MBR_TABLE : usize = 446;
+const MBR_ENTRY_SIZE : usize = 16;
+const MBR_ENTRY_NUM : usize = 4;
+const MBR_SIGNATURE : [u8;2] = [0x55, 0xaa];
+/// 扩展分区类型
+const MBR_EXTENDED : [u8;3] = [0x05, 0x0f, 0x85];
+/// GPT 的保护分区类型
+const MBR_GPT : u8 = 0xee;
+const GPT_SIGNATURE : &[u8] = b"EFI PART";
+/// 分区项数量与大小的上限，防止损坏的表头
+const GPT_MAX_ENTRY : usize = 256;
+const GPT_MAX_ENTRY_SIZE : usize = 4096;
+/// 分区项大小必须是它的倍数
+const GPT_ENTRY_UNIT : usize = 128;
+
+/// 分区的位置，单位字节
+#[derive(Debug, Clone, Copy)]
+pub struct Partition {
+    pub start : usize,
+    pub size : usize,
+}
+
+/// 解析磁盘的分区表，没有分区表时返回空，超出磁盘的分区被丢弃
+pub fn parse(disk : usize)->Vec<Partition> {
+    let capacity = get_device().block_device[disk].capacity();
+    parse_table(disk).into_iter().filter(|p| {
+        let inside = p.start.checked_add(p.size).map_or(false, |ed| ed <= capacity);
+        if !inside {
+            println!("disk {} partition at {:x} exceeds the disk", disk, p.start);
+        }
+        inside
+    }).collect()
+}
+
+fn parse_table(disk : usize)->Vec<Partition> {
+    let mut mbr = vec![0u8;SECTOR_SIZE];
+    sync_read_buffer(disk, &mut mbr[..], 0);
+    if mbr[510..512] != MBR_SIGNATURE {
+        return Vec::new();
+    }
+    let mut rt = Vec::new();
+    for i in 0..MBR_ENTRY_NUM {
+        let entry = &mbr[MBR_TABLE + i * MBR_ENTRY_SIZE..MBR_TABLE + (i + 1) * MBR_ENTRY_SIZE];
+        let ptype = entry[4];
+        let start = read_u32(entry, 8) as usize;
+        let num = read_u32(entry, 12) as usize;
+        if ptype == MBR_GPT {
+            return parse_gpt(disk);
+        }
+        if ptype == 0 || MBR_EXTENDED.contains(&ptype) || num == 0 {
+            continue;
+        }
+        rt.push(Partition {
+            start : start * SECTOR_SIZE,
+            size : num * SECTOR_SIZE,
+        });
+    }
+    rt
+}
+
+/// GPT 表头在第一个扇区，分区项的起止扇区都包含在分区内
+fn parse_gpt(disk : usize)->Vec<Partition> {
+    let mut head = vec![0u8;SECTOR_SIZE];
+    sync_read_buffer(disk, &mut head[..], SECTOR_SIZE);
+    if &head[0..8] != GPT_SIGNATURE {
+        println!("disk {} gpt header broken", disk);
+        return Vec::new();
+    }
+    let entry_lba = read_u64(&head[..], 72) as usize;
+    let entry_num = (read_u32(&head[..], 80) as usize).min(GPT_MAX_ENTRY);
+    let entry_size = read_u32(&head[..], 84) as usize;
+    if entry_size < GPT_ENTRY_UNIT || entry_size % GPT_ENTRY_UNIT != 0 || entry_size > GPT_MAX_ENTRY_SIZE
+            || entry_lba >= usize::max_value() / SECTOR_SIZE {
+        println!("disk {} gpt header broken", disk);
+        return Vec::new();
+    }
+    let mut table = vec![0u8;entry_num * entry_size];
+    sync_read_buffer(disk, &mut table[..], entry_lba * SECTOR_SIZE);
+    let mut rt = Vec::new();
+    for i in 0..entry_num {
+        let entry = &table[i * entry_size..(i + 1) * entry_size];
+        if entry[0..16].iter().all(|b| *b == 0) {
+            continue;
+        }
+        let first = read_u64(entry, 32) as usize;
+        let last = read_u64(entry, 40) as usize;
+        if last < first || last >= usize::max_value() / SECTOR_SIZE {
+            continue;
+        }
+        rt.push(Partition {
+            start : first * SECTOR_SIZE,
+            size : (last - first + 1) * SECTOR_SIZE,
+        });
+    }
+    rt
+}
+
+fn read_u32(data : &[u8], st : usize)->u32 {
+    let mut b = [0;4];
+    b.copy_from_slice(&data[st..st + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn read_u64(data : &[u8], st : usize)->u64 {
+    let mut b = [0;8];
+    b.copy_from_slice(&data[st..st + 8]);
+    u64::from_le_bytes(b)
+}
+
+
+use alloc::prelude::v1::*;
+use crate::virtio::{device::get_device, disk_cache::sync_read_buffer};
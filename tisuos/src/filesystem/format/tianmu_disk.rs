@@ -0,0 +1,164 @@
+//! # 天目磁盘布局
+//! 超级块、块映射、目录项在磁盘上的位置，与 tianmu-fs 的 SuperBlock、DirItem 一致
+//! 只依赖 alloc，内核的 fsck 与主机上的 fsck、mkfs 共用
+//!
+//! 2021年6月18日 zg
+
+/// 超级块中魔数的位置，前 512 字节保留给引导
+pub const MAGIC_ADDR : usize = 512;
+pub const MAGIC : u32 = 0x2333;
+/// 超级块所占的字节
+pub const SUPER_BLOCK_SIZE : usize = 1024;
+/// 块映射表项，0 表示空闲
+pub const FREE : u64 = 0;
+/// 块映射表项，表示链的末尾
+pub const END : u64 = 0xffff_ffff_ffff_ffff;
+pub const MAP_ITEM_SIZE : usize = 8;
+pub const DIR_ITEM_SIZE : usize = 64;
+pub const NAME_LEN : usize = 46;
+pub const TYPE_EMPTY : u8 = 0;
+pub const TYPE_FILE : u8 = 1;
+pub const TYPE_DIRECTORY : u8 = 2;
+/// 符号链接，数据链中保存目标路径，length 为路径长度
+pub const TYPE_SYMLINK : u8 = 3;
+/// 目录项中链接数的上限
+pub const LINK_MAX : usize = 255;
+/// 卷末尾保留给日志的块数，这些块在块映射中标记为已占用
+pub const JOURNAL_BLOCKS : usize = 64;
+
+/// ## 超级块
+/// 地址、大小均以字节为单位，root_idx 为根目录第一个块的块号
+#[derive(Debug, Clone, Copy)]
+pub struct SuperBlock {
+    pub total_size : usize,
+    pub block_size : usize,
+    pub block_map_addr : usize,
+    pub root_idx : usize,
+}
+
+impl SuperBlock {
+    /// 解析前 SUPER_BLOCK_SIZE 字节，魔数不对返回 None
+    pub fn parse(data : &[u8])->Option<Self> {
+        if read_u32(data, MAGIC_ADDR) != MAGIC {
+            return None;
+        }
+        Some(Self {
+            total_size : read_u64(data, MAGIC_ADDR + 8) as usize,
+            block_size : read_u64(data, MAGIC_ADDR + 16) as usize,
+            block_map_addr : read_u64(data, MAGIC_ADDR + 24) as usize,
+            root_idx : read_u64(data, MAGIC_ADDR + 32) as usize,
+        })
+    }
+
+    pub fn to_bytes(&self)->Vec<u8> {
+        let mut rt = vec![0u8;SUPER_BLOCK_SIZE];
+        write_u32(&mut rt, MAGIC_ADDR, MAGIC);
+        write_u64(&mut rt, MAGIC_ADDR + 8, self.total_size as u64);
+        write_u64(&mut rt, MAGIC_ADDR + 16, self.block_size as u64);
+        write_u64(&mut rt, MAGIC_ADDR + 24, self.block_map_addr as u64);
+        write_u64(&mut rt, MAGIC_ADDR + 32, self.root_idx as u64);
+        rt
+    }
+
+    pub fn block_num(&self)->usize {
+        self.total_size / self.block_size
+    }
+
+    /// 块 idx 的表项在磁盘上的地址
+    pub fn map_addr(&self, idx : usize)->usize {
+        self.block_map_addr + idx * MAP_ITEM_SIZE
+    }
+
+    pub fn block_addr(&self, idx : usize)->usize {
+        idx * self.block_size
+    }
+}
+
+/// ## 目录项
+/// 名字以 0 结尾，start_block 为 0 表示没有分配块
+/// links 为数据链被多少个目录项引用，指向同一数据链的硬链接保持一致，旧镜像中为 0，按 1 处理
+#[derive(Debug, Clone)]
+pub struct DirItem {
+    pub name : Vec<u8>,
+    pub dtype : u8,
+    pub links : u8,
+    pub start_block : usize,
+    pub length : usize,
+}
+
+impl DirItem {
+    pub fn new(name : &str, dtype : u8, start_block : usize, length : usize)->Self {
+        let mut n : Vec<u8> = name.bytes().take(NAME_LEN - 1).collect();
+        n.resize(NAME_LEN, 0);
+        Self {
+            name : n,
+            dtype,
+            links : 1,
+            start_block,
+            length,
+        }
+    }
+
+    pub fn parse(data : &[u8])->Self {
+        Self {
+            name : data[0..NAME_LEN].to_vec(),
+            dtype : data[NAME_LEN],
+            links : data[NAME_LEN + 1],
+            start_block : read_u64(data, 48) as usize,
+            length : read_u64(data, 56) as usize,
+        }
+    }
+
+    pub fn to_bytes(&self)->Vec<u8> {
+        let mut rt = vec![0u8;DIR_ITEM_SIZE];
+        rt[0..NAME_LEN].copy_from_slice(&self.name[0..NAME_LEN]);
+        rt[NAME_LEN] = self.dtype;
+        rt[NAME_LEN + 1] = self.links;
+        write_u64(&mut rt, 48, self.start_block as u64);
+        write_u64(&mut rt, 56, self.length as u64);
+        rt
+    }
+
+    pub fn empty(&self)->bool {
+        self.dtype == TYPE_EMPTY
+    }
+
+    pub fn is_dir(&self)->bool {
+        self.dtype == TYPE_DIRECTORY
+    }
+
+    pub fn is_symlink(&self)->bool {
+        self.dtype == TYPE_SYMLINK
+    }
+
+    pub fn link_count(&self)->usize {
+        (self.links as usize).max(1)
+    }
+
+    pub fn name(&self)->String {
+        self.name.iter().take_while(|c| **c != 0).map(|c| *c as char).collect()
+    }
+}
+
+pub fn read_u32(data : &[u8], st : usize)->u32 {
+    let mut b = [0;4];
+    b.copy_from_slice(&data[st..st + 4]);
+    u32::from_le_bytes(b)
+}
+
+pub fn read_u64(data : &[u8], st : usize)->u64 {
+    let mut b = [0;8];
+    b.copy_from_slice(&data[st..st + 8]);
+    u64::from_le_bytes(b)
+}
+
+pub fn write_u32(data : &mut [u8], st : usize, val : u32) {
+    data[st..st + 4].copy_from_slice(&val.to_le_bytes());
+}
+
+pub fn write_u64(data : &mut [u8], st : usize, val : u64) {
+    data[st..st + 8].copy_from_slice(&val.to_le_bytes());
+}
+
+
+use alloc::{string::String, vec::Vec};
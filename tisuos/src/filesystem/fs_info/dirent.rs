@@ -0,0 +1,96 @@
+//! # 目录项记录
+//! GETDENTS 把目录项依次写入用户提供的缓冲，每条记录长度可变，按 8 字节对齐
+//! 布局：start_idx u64、size u64、cookie u64、reclen u16、dtype u8，之后是以 0 结尾的 UTF-8 名字
+//! cookie 为下一条记录的序号，传回 GETDENTS 即可从这条记录之后继续读取
+//!
+//! 2021年6月20日 zg
+
+pub const DIRENT_HEAD : usize = 27;
+pub const DIRENT_ALIGN : usize = 8;
+
+/// 解析后的目录项记录
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name : String,
+    pub dtype : usize,
+    pub size : usize,
+    pub start_idx : usize,
+    pub cookie : usize,
+}
+
+impl DirEntry {
+    /// 解析 GETDENTS 写入的前 len 字节
+    pub fn parse_all(data : &[u8])->Vec<Self> {
+        let mut rt = Vec::new();
+        let mut st = 0;
+        while st + DIRENT_HEAD <= data.len() {
+            let rec = &data[st..];
+            let reclen = rec[24] as usize | (rec[25] as usize) << 8;
+            if reclen < DIRENT_HEAD || st + reclen > data.len() {
+                break;
+            }
+            let name : Vec<u8> = rec[DIRENT_HEAD..reclen].iter().take_while(|c| **c != 0).cloned().collect();
+            rt.push(Self {
+                name : String::from_utf8_lossy(&name[..]).to_string(),
+                dtype : rec[26] as usize,
+                size : read_u64(rec, 8),
+                start_idx : read_u64(rec, 0),
+                cookie : read_u64(rec, 16),
+            });
+            st += reclen;
+        }
+        rt
+    }
+
+    pub fn is_dir(&self)->bool {
+        self.dtype == STAT_DIRECTORY
+    }
+}
+
+fn read_u64(data : &[u8], st : usize)->usize {
+    let mut b = [0;8];
+    b.copy_from_slice(&data[st..st + 8]);
+    u64::from_le_bytes(b) as usize
+}
+
+/// 记录的长度，名字后至少留一个 0
+pub fn dirent_len(name : &str)->usize {
+    (DIRENT_HEAD + name.len() + 1 + DIRENT_ALIGN - 1) / DIRENT_ALIGN * DIRENT_ALIGN
+}
+
+/// ## 填充目录项
+/// 从第 cookie 项开始尽量多地写入 buf，返回写入的字节数，已读完返回 0
+/// 缓冲区连一条记录都放不下时返回 None
+pub fn fill_dirent(items : &[Leaf], cookie : usize, buf : &mut [u8])->Option<usize> {
+    let mut len = 0;
+    for (idx, item) in items.iter().enumerate().skip(cookie) {
+        let reclen = dirent_len(&item.name);
+        if len + reclen > buf.len() {
+            if len == 0 {
+                return None;
+            }
+            break;
+        }
+        let rec = &mut buf[len..len + reclen];
+        for b in rec.iter_mut() {
+            *b = 0;
+        }
+        let dtype = match item.ltype {
+            LeafType::Directory => STAT_DIRECTORY,
+            _ => STAT_FILE,
+        };
+        rec[0..8].copy_from_slice(&(item.block_idx as u64).to_le_bytes());
+        rec[8..16].copy_from_slice(&(item.size as u64).to_le_bytes());
+        rec[16..24].copy_from_slice(&(idx as u64 + 1).to_le_bytes());
+        rec[24..26].copy_from_slice(&(reclen as u16).to_le_bytes());
+        rec[26] = dtype as u8;
+        rec[DIRENT_HEAD..DIRENT_HEAD + item.name.len()].copy_from_slice(item.name.as_bytes());
+        len += reclen;
+    }
+    Some(len)
+}
+
+
+use alloc::prelude::v1::*;
+use tisu_fs::{Leaf, LeafType};
+use super::{STAT_DIRECTORY, STAT_FILE};
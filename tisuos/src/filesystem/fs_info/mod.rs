@@ -4,7 +4,9 @@
 //! 2021年4月29日 zg
 
 mod file_info;
-mod directory_info;
+mod dirent;
+mod stat;
 
 pub use file_info::*;
-pub use directory_info::*;
\ No newline at end of file
+pub use dirent::*;
+pub use stat::*;
\ No newline at end of file
@@ -0,0 +1,26 @@
+//! # 文件状态
+//! STAT、FSTAT 写入用户提供的结构，布局与用户库一致
+//!
+//! 2021年6月19日 zg
+
+pub const STAT_FILE : usize = 1;
+pub const STAT_DIRECTORY : usize = 2;
+
+/// ## 文件状态
+/// 时间为 1970 年起的秒数，未知时为 0；mode 只包含 rwx 权限位
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    pub ftype : usize,
+    pub mode : usize,
+    pub size : usize,
+    /// 占用的块数，块大小为 block_size
+    pub blocks : usize,
+    pub block_size : usize,
+    pub create_time : usize,
+    pub modify_time : usize,
+    pub access_time : usize,
+    /// 文件系统编号，与路径开头的编号一致
+    pub device : usize,
+    pub start_idx : usize,
+}
@@ -3,11 +3,11 @@
 //! 2021年5月3日 zg
 
 use alloc::prelude::v1::*;
-use tisu_fs::{Directory, SystemOp};
+use tisu_fs::{Directory, FileFlag, SystemOp};
 
 use crate::{libs::str::convert_to_usize, memory::block::Block};
 
-use super::get_system;
+use super::{get_system, journal::transaction, metadata::opened, prepare_open, resolve, syscall_io::write_file};
 
 pub fn read(path:String)->Result<Block<u8>, ()> {
     let all_path = path;
@@ -20,6 +20,22 @@ pub fn read(path:String)->Result<Block<u8>, ()> {
     Ok(data)
 }
 
+/// ## 写入文件
+/// 以写方式打开 idx 号文件系统上的 path 并写入 data
+/// 打开前的准备与写入都和系统调用相同，不会绕过 ext2 的驱动
+pub fn write(idx : usize, path : &str, data : &[u8])->Result<usize, ()> {
+    let sys = get_system(idx).ok_or(())?;
+    let path = resolve(idx, path).ok_or(())?;
+    let id = transaction(Some(idx), || {
+        if !prepare_open(idx, &path, FileFlag::Write.val()) {
+            return None;
+        }
+        sys.open(path.clone(), FileFlag::Write).ok().map(|file| file.id)
+    }).ok_or(())?;
+    opened(id, idx, path);
+    write_file(id, data)
+}
+
 pub fn enter(path:String)->Result<Directory, ()> {
     let all_path = path;
     let idx = all_path.find("/").unwrap();
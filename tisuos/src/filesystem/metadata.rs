@@ -0,0 +1,257 @@
+//! # 文件元数据
+//! 为 STAT、FSTAT 收集类型、大小、时间与权限
+//! FAT32 的时间保存在短目录项中，写入文件时更新修改时间与访问日期
+//! 天目的目录项不带时间，只在内存中记录本次开机后的修改时间
+//! ext2 的时间与权限直接取自 inode，inode 的 ctime 作为创建时间
+//!
+//! 2021年6月19日 zg
+
+/// RTC 给出 UTC 时间，FAT32 中保存东八区的本地时间，与 rtc::Time 一致
+const TIME_ZONE : usize = 8 * 3600;
+const DAY : usize = 24 * 3600;
+const FAT_YEAR : usize = 1980;
+const FAT_DIR_ITEM_SIZE : usize = 32;
+const MODE_FILE : usize = 0o644;
+const MODE_READ_ONLY : usize = 0o444;
+const MODE_DIRECTORY : usize = 0o755;
+static mut METADATA : Option<ContentMutex<Metadata>> = None;
+
+struct Metadata {
+    /// 文件 id 对应的文件系统编号与路径，打开时记录，id 重新分配时覆盖
+    opened : BTreeMap<usize, (usize, String)>,
+    /// 天目文件的修改时间，键为文件系统编号与路径
+    modified : BTreeMap<(usize, String), usize>,
+}
+
+fn get_metadata()->&'static mut ContentMutex<Metadata> {
+    unsafe {
+        if METADATA.is_none() {
+            METADATA = Some(ContentMutex::new(Metadata {
+                opened : BTreeMap::new(),
+                modified : BTreeMap::new(),
+            }, true));
+        }
+        METADATA.as_mut().unwrap()
+    }
+}
+
+fn get_format(system : usize)->Option<&'static BlockType> {
+    unsafe {
+        FORMAT.as_ref()?.get(system)
+    }
+}
+
+/// ## 文件状态
+/// path 为去掉文件系统编号后的路径，文件不存在返回 None
+pub fn stat(system : usize, path : &str)->Option<Stat> {
+    let path = &resolve(system, path)?[..];
+    let sys = get_system(system)?;
+    let mut rt = Stat::default();
+    rt.device = system;
+    let file = sys.get_file(path.to_string()).ok().map(|file| (file.size, file.start_idx));
+    if let Some((size, start_idx)) = file {
+        rt.ftype = STAT_FILE;
+        rt.size = size;
+        rt.start_idx = start_idx;
+        rt.mode = MODE_FILE;
+    }
+    else {
+        let dir = sys.enter(path.to_string()).ok()?;
+        rt.ftype = STAT_DIRECTORY;
+        rt.start_idx = dir.block_idx;
+        rt.mode = MODE_DIRECTORY;
+    }
+    match get_format(system)? {
+        BlockType::FAT32(mgr) => {
+            rt.block_size = mgr.cluster_size;
+            if rt.start_idx >= 2 {
+                rt.blocks = mgr.get_block_chain(rt.start_idx).map(|c| c.len()).unwrap_or(0);
+            }
+            if let Some(item) = fat_locate(system, mgr, path).map(|addr| read_fat_item(mgr, addr)) {
+                rt.create_time = from_fat_time(item.create_date, item.create_time)
+                    + item.create_time_ext as usize / 100;
+                rt.modify_time = from_fat_time(item.last_change_date, item.last_change_time);
+                rt.access_time = from_fat_time(item.last_access_date, 0);
+                if item.attr & Attribute::ReadOnly.val() != 0 {
+                    rt.mode &= MODE_READ_ONLY;
+                }
+            }
+        }
+        BlockType::TianMu(tm) => {
+            rt.block_size = tm.0.block_size;
+            rt.blocks = tm.get_block_chain(rt.start_idx).map(|c| c.len()).unwrap_or(0);
+            let key = (system, normalize(path));
+            if let Some(time) = get_metadata().lock().modified.get(&key) {
+                rt.modify_time = *time;
+                rt.access_time = *time;
+            }
+        }
+        BlockType::Ext2(fs) => {
+            rt.block_size = fs.block_size;
+            rt.blocks = fs.get_block_chain(rt.start_idx).map(|c| c.len()).unwrap_or(0);
+            let inode = fs.inode(rt.start_idx)?;
+            rt.mode = inode.permission();
+            rt.create_time = inode.ctime;
+            rt.modify_time = inode.mtime;
+            rt.access_time = inode.atime;
+        }
+        BlockType::Unknown => return None,
+    }
+    Some(rt)
+}
+
+/// 已打开文件的状态，调用者检查文件是否属于自己
+pub fn fstat(id : usize)->Option<Stat> {
+    let (system, path) = get_metadata().lock().opened.get(&id)?.clone();
+    stat(system, &path)
+}
+
+/// 打开文件后记录其位置，供 fstat 与 modified 使用
+pub fn opened(id : usize, system : usize, path : String) {
+    get_metadata().lock().opened.insert(id, (system, normalize(&path)));
+}
+
+/// 已打开文件所在的文件系统与路径
+pub fn opened_path(id : usize)->Option<(usize, String)> {
+    get_metadata().lock().opened.get(&id).cloned()
+}
+
+/// ## 更新修改时间
+/// 写入文件成功后调用，放在写入所在的事务中
+pub fn modified(id : usize) {
+    let (system, path) = if let Some(v) = get_metadata().lock().opened.get(&id) {
+        v.clone()
+    }
+    else {
+        return;
+    };
+    let now = rtc::now();
+    match get_format(system) {
+        Some(BlockType::FAT32(mgr)) => {
+            if let Some(addr) = fat_locate(system, mgr, &path) {
+                let mut item = read_fat_item(mgr, addr);
+                let (date, time) = to_fat_time(now);
+                item.last_change_date = date;
+                item.last_change_time = time;
+                item.last_access_date = date;
+                let data = unsafe {
+                    &*slice_from_raw_parts(&item as *const FATShortDirItem as *const u8, FAT_DIR_ITEM_SIZE)
+                };
+                sync_write_buffer(mgr.block_idx, data, addr);
+            }
+        }
+        Some(BlockType::TianMu(_)) => {
+            get_metadata().lock().modified.insert((system, path), now);
+        }
+        _ => {}
+    }
+}
+
+/// 去掉末尾的 /，根目录为 /
+fn normalize(path : &str)->String {
+    let path = path.trim_end_matches('/');
+    if path.len() == 0 {
+        "/".to_string()
+    }
+    else {
+        path.to_string()
+    }
+}
+
+/// 找到路径对应的短目录项在卷内的地址，根目录没有目录项，FAT32 没有链接，路径不必展开
+fn fat_locate(system : usize, mgr : &FATManger, path : &str)->Option<usize> {
+    let path = normalize(path);
+    let idx = path.rfind('/')?;
+    let (parent, name) = path.split_at(idx);
+    let name = &name[1..];
+    if name.len() == 0 {
+        return None;
+    }
+    let parent = if parent.len() == 0 { "/" } else { parent };
+    let dir = get_system(system)?.enter(parent.to_string()).ok()?.block_idx;
+    let mut lfn = String::new();
+    let buf = Block::<u8>::new(mgr.cluster_size);
+    for cluster in mgr.get_block_chain(dir).ok()? {
+        let base = mgr.root_dir_cluster_addr + (cluster - 2) * mgr.cluster_size;
+        sync_read_buffer(mgr.block_idx, buf.to_array(0, mgr.cluster_size), base);
+        let items = buf.array::<FATShortDirItem>(0, mgr.cluster_size / FAT_DIR_ITEM_SIZE);
+        // 与 parse_dir 拼接长文件名的方式一致
+        for (i, item) in items.iter().enumerate() {
+            if item.is_empty() || item.is_delete() {
+                continue;
+            }
+            if item.is_long_dir() {
+                lfn = item.clone().to_long_item().get_name() + &lfn[..];
+                continue;
+            }
+            let item_name = if lfn.len() > 0 { lfn.clone() } else { item.to_dir_item(i).filename };
+            lfn.clear();
+            if item_name == name {
+                return Some(base + i * FAT_DIR_ITEM_SIZE);
+            }
+        }
+    }
+    None
+}
+
+fn read_fat_item(mgr : &FATManger, addr : usize)->FATShortDirItem {
+    let buf = Block::<u8>::new(FAT_DIR_ITEM_SIZE);
+    sync_read_buffer(mgr.block_idx, buf.to_array(0, FAT_DIR_ITEM_SIZE), addr);
+    buf.type_as::<FATShortDirItem>().clone()
+}
+
+/// FAT 日期：7 位年（1980 起）、4 位月、5 位日；时间：5 位时、6 位分、5 位秒（以 2 秒为单位）
+fn from_fat_time(date : u16, time : u16)->usize {
+    if date == 0 {
+        return 0;
+    }
+    let (date, time) = (date as usize, time as usize);
+    let days = days_from_civil(FAT_YEAR + (date >> 9), (date >> 5) & 0xf, date & 0x1f);
+    let local = days * DAY + (time >> 11) * 3600 + ((time >> 5) & 0x3f) * 60 + (time & 0x1f) * 2;
+    local.saturating_sub(TIME_ZONE)
+}
+
+fn to_fat_time(tm : usize)->(u16, u16) {
+    let local = tm + TIME_ZONE;
+    let (year, month, day) = civil_from_days(local / DAY);
+    if year < FAT_YEAR {
+        return (0, 0);
+    }
+    let sec = local % DAY;
+    let date = (year - FAT_YEAR) << 9 | month << 5 | day;
+    let time = sec / 3600 << 11 | sec / 60 % 60 << 5 | sec % 60 / 2;
+    (date as u16, time as u16)
+}
+
+/// 1970-01-01 起的天数，月份为 0 时按 1 月处理
+fn days_from_civil(year : usize, month : usize, day : usize)->usize {
+    let month = month.max(1).min(12);
+    let day = day.max(1);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    (era * 146097 + doe).saturating_sub(719468)
+}
+
+fn civil_from_days(days : usize)->(usize, usize, usize) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+
+use core::ptr::slice_from_raw_parts;
+use alloc::{collections::BTreeMap, prelude::v1::*};
+use tisu_fs::{Format, SystemOp};
+use tisu_sync::ContentMutex;
+use crate::{memory::block::Block, rtc, virtio::disk_cache::{sync_read_buffer, sync_write_buffer}};
+use super::{FORMAT, Stat, STAT_DIRECTORY, STAT_FILE, get_system, resolve, format::{BlockType, fat32::{Attribute, FATManger, FATShortDirItem}}};
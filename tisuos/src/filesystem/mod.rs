@@ -9,44 +9,59 @@ mod stdio;
 mod image_pool;
 pub mod syscall_io;
 pub mod io;
+pub mod metadata;
 
-use tisu_fs::{FileSystem, IdManager, SystemOp};
+use tisu_fs::{FileFlag, FileSystem, Format, IdManager, Leaf, SystemOp};
 pub use fs_info::*;
 pub use stdio::*;
 pub use format::elf;
+pub use format::journal;
+pub use format::fsck;
+pub use format::link::LinkError;
 pub use image_pool::request;
 
 /// 一个文件系统对应一个磁盘
 pub static mut SYSTEM : Option<Vec<FileSystem>> = None;
 pub static mut FORMAT : Option<Vec<BlockType>> = None;
 static mut ID_MANAGER : Option<IdManager> = None;
+/// 卸载之后查找文件系统都会失败，文件系统本身不释放，其它核上正在进行的操作仍持有引用
+static UNMOUNTED : AtomicBool = AtomicBool::new(false);
 
 /// 在此处获取所有磁盘信息并建立对应的文件系统
+/// 整个磁盘不是可识别的文件系统时解析分区表，每个分区作为一个卷分别识别
 pub fn init(){
     stdio::init();
     unsafe {
         ID_MANAGER = Some(IdManager::new());
         let mut ftype = Vec::new();
         let mut sys = Vec::new();
-        for idx in 0..get_device().block_device.len(){
+        for (idx, name) in volumes() {
             let info = DiskType::new(idx);
             match info.get_type() {
                 BlockType::FAT32(mgr) => {
                     let system = FATManger::to_system(mgr.clone());
                     sys.push(system);
-                    println!("disk {} is fat32, total size {}MB\t\t", idx, mgr.get_total_size() / 1024 / 1024);
+                    println!("{} is fat32, total size {}MB\t\t", name, mgr.get_total_size() / 1024 / 1024);
                     // println!("used size {}MB\t\t", mgr.get_used_size() / 1024 / 1024);
                     ftype.push(BlockType::FAT32(mgr));
                 }
                 BlockType::TianMu(tm) => {
+                    journal::mount(&tm);
                     let system = TianMu::to_system(tm.clone());
                     sys.push(system);
-                    println!("disk {} is tianmu, total size {}MB",
-                        idx, tm.0.total_size / 1024 / 1024);
+                    println!("{} is tianmu, total size {}MB",
+                        name, tm.0.total_size / 1024 / 1024);
                     ftype.push(BlockType::TianMu(tm));
                 }
+                BlockType::Ext2(fs) => {
+                    let system = Ext2::to_system(fs.clone());
+                    sys.push(system);
+                    println!("{} is ext2, total size {}MB{}", name, fs.total_size / 1024 / 1024,
+                        if fs.writable { "" } else { ", read only" });
+                    ftype.push(BlockType::Ext2(fs));
+                }
                 _ => {
-                    println!("unknown filesystem");
+                    println!("{} unknown filesystem", name);
                     continue;
                 }
             }
@@ -56,34 +71,191 @@ pub fn init(){
     }
 }
 
-
-pub fn get_system(idx : usize)->Option<&'static mut impl SystemOp> {
-    unsafe {
-        if let Some(sys) = &mut SYSTEM {
-            sys.get_mut(idx)
+/// 列出需要识别的卷及其名称
+fn volumes()->Vec<(usize, String)> {
+    let mut rt = Vec::new();
+    for idx in 0..get_device().block_device.len() {
+        let part = if DiskType::new(idx).is_known() { Vec::new() } else { partition::parse(idx) };
+        if part.len() == 0 {
+            rt.push((idx, format!("disk {}", idx)));
+            continue;
         }
-        else {
-            None
+        for (i, p) in part.iter().enumerate() {
+            if let Some(volume) = add_volume(idx, p.start, p.size) {
+                rt.push((volume, format!("disk {} partition {}", idx, i + 1)));
+            }
         }
     }
+    rt
 }
 
-pub fn search_system(id : usize)->Option<&'static mut impl SystemOp> {
+
+fn systems()->Option<&'static mut Vec<FileSystem>> {
+    if UNMOUNTED.load(Ordering::SeqCst) {
+        return None;
+    }
     unsafe {
-        if let Some(sys) = &mut SYSTEM {
-            for sys in sys.iter_mut() {
-                if sys.contain(id) {
-                    return Some(sys);
-                }
+        SYSTEM.as_mut()
+    }
+}
+
+fn formats()->Option<&'static Vec<BlockType>> {
+    if UNMOUNTED.load(Ordering::SeqCst) {
+        return None;
+    }
+    unsafe {
+        FORMAT.as_ref()
+    }
+}
+
+pub fn get_system(idx : usize)->Option<&'static mut impl SystemOp> {
+    if let Some(sys) = systems() {
+        sys.get_mut(idx)
+    }
+    else {
+        None
+    }
+}
+
+pub fn search_system(id : usize)->Option<&'static mut impl SystemOp> {
+    if let Some(sys) = systems() {
+        for sys in sys.iter_mut() {
+            if sys.contain(id) {
+                return Some(sys);
             }
-            None
-        }
-        else {
-            None
         }
+        None
+    }
+    else {
+        None
+    }
+}
+
+/// 文件系统所在的卷
+pub fn system_volume(idx : usize)->Option<usize> {
+    match formats()?.get(idx)? {
+        BlockType::FAT32(mgr) => Some(mgr.block_idx),
+        BlockType::TianMu(tm) => Some(tm.0.device_id),
+        BlockType::Ext2(fs) => Some(fs.device_id),
+        BlockType::Unknown => None,
+    }
+}
+
+/// 通过磁盘缓冲读写卷，供 fsck 使用
+struct VolumeDisk(usize);
+
+impl fsck::Disk for VolumeDisk {
+    fn read(&mut self, st : usize, data : &mut [u8]) {
+        sync_read_buffer(self.0, data, st);
+    }
+
+    fn write(&mut self, st : usize, data : &[u8]) {
+        sync_write_buffer(self.0, data, st);
+    }
+}
+
+/// ## 检查文件系统
+/// 编号对应的卷都已挂载，修复会与正在进行的写入冲突，只做检查。修复需要在主机上对镜像运行 fsck -r
+/// 不是可识别的文件系统时返回 None
+pub fn check(idx : usize)->Option<fsck::Report> {
+    let volume = system_volume(idx)?;
+    fsck::check(&mut VolumeDisk(volume), false)
+}
+
+/// 天目卷的链接操作视图，其它格式返回 None
+fn link_volume(idx : usize)->Option<link::Volume> {
+    match formats()?.get(idx)? {
+        BlockType::TianMu(tm) => Some(link::Volume::new(tm)),
+        _ => None,
+    }
+}
+
+/// ## 展开路径中的符号链接
+/// 只有天目支持链接，其它格式原样返回，符号链接成环时返回 None
+pub fn resolve(idx : usize, path : &str)->Option<String> {
+    if let Some(vol) = link_volume(idx) {
+        vol.resolve(path, true).ok()
+    }
+    else {
+        Some(path.to_string())
+    }
+}
+
+pub fn symlink(idx : usize, target : &str, path : &str)->Result<(), LinkError> {
+    let vol = link_volume(idx).ok_or(LinkError::Unsupported)?;
+    journal::transaction(Some(idx), || vol.symlink(target, path))
+}
+
+pub fn link(idx : usize, old : &str, new : &str)->Result<(), LinkError> {
+    let vol = link_volume(idx).ok_or(LinkError::Unsupported)?;
+    journal::transaction(Some(idx), || vol.link(old, new))
+}
+
+/// 写入改变了文件长度，天目上同步其它硬链接的目录项
+pub fn set_length(idx : usize, path : &str, length : usize) {
+    if let Some(vol) = link_volume(idx) {
+        vol.set_length(path, length);
     }
 }
 
-use crate::{filesystem::format::{fat32::FATManger, tianmu::TianMu}, virtio::device::get_device};
+pub fn readlink(idx : usize, path : &str)->Result<String, LinkError> {
+    link_volume(idx).ok_or(LinkError::Unsupported)?.readlink(path)
+}
+
+/// ## 读取目录
+/// 直接由格式解析目录的块，得到完整的长文件名、大小与起始块
+pub fn read_dir(idx : usize, path : &str)->Option<Vec<Leaf>> {
+    let path = resolve(idx, path)?;
+    let dir = get_system(idx)?.enter(path).ok()?.block_idx;
+    match formats()?.get(idx)? {
+        BlockType::FAT32(mgr) => mgr.parse_node(dir).ok(),
+        BlockType::TianMu(tm) => tm.parse_node(dir).ok(),
+        BlockType::Ext2(fs) => fs.parse_node(dir).ok(),
+        BlockType::Unknown => None,
+    }
+}
+
+/// ext2 卷的驱动，其它格式返回 None
+pub fn ext2_system(idx : usize)->Option<&'static Ext2> {
+    match formats()?.get(idx)? {
+        BlockType::Ext2(fs) => Some(fs),
+        _ => None,
+    }
+}
+
+/// ## 打开前的准备
+/// tisu_fs 把 ext2 当作天目处理，不能由它新建文件
+/// 以写方式打开 ext2 上不存在的文件时先由驱动在父目录中新建，失败时返回 false
+pub fn prepare_open(idx : usize, path : &str, flag : usize)->bool {
+    let fs = if let Some(fs) = ext2_system(idx) { fs } else { return true; };
+    let sys = if let Some(sys) = get_system(idx) { sys } else { return false; };
+    if flag & FileFlag::Write.val() == 0 || sys.get_file(path.to_string()).is_ok() {
+        return true;
+    }
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.split_at(path.rfind('/').map(|i| i + 1).unwrap_or(0));
+    let parent = if parent.len() == 0 { "/" } else { parent };
+    if let Ok(dir) = sys.enter(parent.to_string()) {
+        fs.create(dir.block_idx, name, false).is_some()
+    }
+    else {
+        false
+    }
+}
+
+/// 文件所在的磁盘，与 get_system 使用的编号一致
+pub fn search_disk(id : usize)->Option<usize> {
+    systems()?.iter().position(|sys| sys.contain(id))
+}
+
+/// ## 卸载所有文件系统
+/// 关机前调用，之后所有文件操作都会失败，缓冲中的脏块需要另外写回
+/// 只做标记不释放，其它核上的系统调用可能还在使用 get_system 返回的引用
+pub fn unmount() {
+    UNMOUNTED.store(true, Ordering::SeqCst);
+}
+
+use crate::{filesystem::format::{ext2::Ext2, fat32::FATManger, link, partition, tianmu::TianMu}, virtio::{device::get_device, disk_cache::{add_volume, sync_read_buffer, sync_write_buffer}}};
+use core::sync::atomic::{AtomicBool, Ordering};
 use alloc::prelude::v1::*;
 use self::{format::{BlockType, DiskType}};
\ No newline at end of file
@@ -2,10 +2,10 @@
 //!
 //! 2021年5月3日 zg
 
-use tisu_fs::{IdManager, SystemOp};
+use tisu_fs::{File, FileFlag, IdManager, SystemOp};
 
 use crate::task::get_task_mgr;
-use super::{ID_MANAGER, io_info::IoError, pop_task_in, push_output, push_task_in, search_system};
+use super::{ID_MANAGER, ext2_system, format::ext2::Ext2, io_info::IoError, journal::write_transaction, metadata::{modified, opened_path}, pop_task_in, push_output, push_task_in, search_disk, search_system, set_length};
 
 
 pub fn get_id_mgr()->&'static mut IdManager {
@@ -86,7 +86,7 @@ pub fn write(program_id:usize, file_id : usize, data : &[u8])->IoResult {
             if !file.is_own(program_id) {
                 return Err(IoError::NotOpen);
             }
-            if let Ok(len) = sys.write(file_id, data) {
+            if let Ok(len) = write_file(file_id, data) {
                 Ok(len)
             }
             else {
@@ -99,3 +99,40 @@ pub fn write(program_id:usize, file_id : usize, data : &[u8])->IoResult {
     }
 }
 
+/// ## 写入文件
+/// 系统调用与内核中对文件的写入都经过这里，ext2 由驱动写入，其它格式交给 tisu_fs
+/// 在同一个事务中更新修改时间、同步其它硬链接的长度，调用者检查文件是否属于自己
+/// 文件数据不进入日志，块映射与目录项的修改由日志保护
+pub fn write_file(file_id : usize, data : &[u8])->Result<usize, ()> {
+    let sys = search_system(file_id).ok_or(())?;
+    sys.file(file_id).ok_or(())?;
+    write_transaction(search_disk(file_id), || {
+        let size = sys.file(file_id).unwrap().size;
+        let rt = if let Some(fs) = search_disk(file_id).and_then(ext2_system) {
+            ext2_write(fs, sys.file(file_id).unwrap(), data)
+        }
+        else {
+            sys.write(file_id, data).map_err(|_| ())
+        };
+        if rt.is_ok() {
+            modified(file_id);
+            let new_size = sys.file(file_id).unwrap().size;
+            if new_size != size {
+                if let Some((system, path)) = opened_path(file_id) {
+                    set_length(system, &path, new_size);
+                }
+            }
+        }
+        (rt, sys.file(file_id).unwrap().start_idx)
+    })
+}
+
+/// ## 写入 ext2 文件
+/// tisu_fs 按天目的块映射分配，不能用于 ext2，由驱动追加到文件末尾后更新文件大小
+fn ext2_write(fs : &Ext2, file : &mut File, data : &[u8])->Result<usize, ()> {
+    if file.state.flag.val() & FileFlag::Write.val() == 0 {
+        return Err(());
+    }
+    file.size = fs.append(file.start_idx, data).ok_or(())?;
+    Ok(data.len())
+}
@@ -3,9 +3,8 @@
 //! 
 //! 2021年1月25日 zg
 
-// const TEST_FAIL         : u32 = 0x3333;
-const TEST_PASS         : u32 = 0x5555;
-// const TEST_RESET        : u32 = 0x7777;
+/// send 命令使用的端口，对方的 echo 服务会原样返回
+const TEST_PORT : u16 = 7;
 
 /// ## 维护一个循环队列
 pub struct ConsoleShell {
@@ -49,6 +48,22 @@ impl ConsoleShell {
     /// 大部分是测试用例
     pub fn do_command(&mut self, cmd : &String) {
         let s : Vec<&str> = cmd.split(' ').collect();
+        if s[0] == "ifconfig" {
+            self.ifconfig(&s[1..]);
+            return;
+        }
+        if s[0] == "pcap" {
+            self.pcap(&s[1..]);
+            return;
+        }
+        if s[0] == "fsck" {
+            self.fsck(&s[1..]);
+            return;
+        }
+        if s[0] == "exec" {
+            self.exec(&s[1..]);
+            return;
+        }
         if s.len() == 2{
             match s[0] {
                 "cd" => {
@@ -83,13 +98,6 @@ impl ConsoleShell {
                         free(addr as usize);
                     }
                 }
-                "exec" => {
-                    if let Some(dir) = &self.directory {
-                        let id = exec(
-                            dir.device_id.to_string() + &dir.path[..] + s[1]);
-                        wait(id);
-                    }
-                }
                 "cat" => {
                     if let Some(dir) = &self.directory {
                         let sys = get_system(dir.device_id).unwrap();
@@ -105,37 +113,60 @@ impl ConsoleShell {
                 }
                 "mkdir" => {
                 }
+                "ping" => {
+                    if let Some(ip) = Ipv4Address::parse(s[1]) {
+                        self.ping(ip);
+                    }
+                    else {
+                        console!("\ninvalid address {}", s[1]);
+                    }
+                }
                 _ =>{}
             }
         }
         else if s.len() == 1 {
             match s[0] {
                 "shutdown" => {
-                    unsafe {
-                        console!();
-                        const VIRT_TEST: *mut u32 = 0x10_0000 as *mut u32;
-                        VIRT_TEST.write_volatile(TEST_PASS);
-                    }
+                    console!();
+                    shutdown();
+                }
+                "sync" => {
+                    sync();
                 }
                 "time" => {
                     console!("\n{:?}", crate::rtc::Time::read());
                 }
                 "readdir" => {
-                    let ptr = directory_info("0/".to_string());
-                    let info = unsafe {&mut *(ptr as *mut filesystem::DirectoryInfo)};
-                    console!("get info {:?}", info);
-                    for i in 0..info.file_num {
-                        let offset = i * 15 * size_of::<char>() + size_of::<filesystem::DirectoryInfo>();
-                        console!("{}", from_ptr((ptr + offset) as *const char));
+                    let mut buf = vec![0u8;1024];
+                    let mut cookie = 0;
+                    loop {
+                        let len = getdents("0/".to_string(), &mut buf[..], cookie);
+                        if len <= 0 {
+                            break;
+                        }
+                        for entry in DirEntry::parse_all(&buf[..len as usize]) {
+                            console!("{}{} {}", entry.name, if entry.is_dir() { "/" } else { "" }, entry.size);
+                            cookie = entry.cookie;
+                        }
                     }
                 }
                 "help" => {
                     console!("
 # use cddisk # to enter disk first
+# use sync to write cached data back to disks
 # use readelf to read elf infomation
-# use exec to execute a binary
+# use exec file [virtio | timer | rtc | test | plic ...] to execute a binary with device access
 # use cat to watch one files content
 # use ls to see current directory's infomation
+# use lsm to see kernel object cache statistics
+# use lsswap to see swap statistics
+# use arp to see arp cache
+# use netstat to see interface counters and sockets
+# use send to send a test udp datagram to the gateway
+# use ping # to send icmp echo requests
+# use ifconfig [name [dhcp | ip netmask [gateway [dns]]]] to see or set interfaces
+# use pcap [start | stop | clear | hex | save file] to capture network frames
+# use fsck # to check a filesystem, repair images with the host fsck -r
                     ");
                 }
                 "draw" => {
@@ -176,16 +207,250 @@ impl ConsoleShell {
                     list_thread();
                 }
                 "lsm" => {
+                    console!("\nsize\tin use\tcached\tpages\tfragment");
+                    for info in slab_memory::statistics() {
+                        console!("\n{}\t{}\t{}\t{}\t{}%", info.object_size, info.in_use,
+                            info.cached, info.page_num, info.fragmentation);
+                    }
+                }
+                "netstat" => {
+                    self.netstat();
                 }
                 "send" => {
-                    console!("send {:?}", Ip::new());
-                    get_device().send();
+                    self.send();
+                }
+                "arp" => {
+                    net::interface_op(|ifaces| {
+                        for iface in ifaces.iter() {
+                            for (ip, entry) in iface.arp_entries() {
+                                console!("\n{}\t{}\t{}", iface.name, ip, entry.mac);
+                            }
+                        }
+                    });
+                }
+                "lsswap" => {
+                    if let Some(info) = swap_memory::statistics() {
+                        console!("\nswap disk {}, used {} / {} pages, swap out {}, swap in {}",
+                            info.device_id, info.used_slot, info.total_slot,
+                            info.swap_out, info.swap_in);
+                    }
+                    else {
+                        console!("no swap disk");
+                    }
                 }
                 _ =>{}
             }
         }
     }
 
+    /// 发送 4 个回应请求，每个等待 1 秒
+    fn ping(&self, ip : Ipv4Address) {
+        for seq in 0..4 {
+            if icmp::ping(ip, seq).is_err() {
+                console!("\nno route to {}", ip);
+                return;
+            }
+            let st = get_million_time();
+            loop {
+                if let Some((time, ttl)) = icmp::ping_result(seq) {
+                    console!("\nreply from {}: seq={} ttl={} time={}ms", ip, seq, ttl, time);
+                    break;
+                }
+                if get_million_time() - st > 1000 {
+                    icmp::ping_cancel(seq);
+                    console!("\nrequest timeout seq={}", seq);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// ### 执行程序
+    /// exec 文件 [设备...]，列出的设备授予新进程
+    fn exec(&self, args : &[&str]) {
+        let dir = if let Some(dir) = &self.directory { dir } else { return; };
+        let name = if let Some(name) = args.get(0) { *name } else {
+            console!("\nusage: exec file [device...]");
+            return;
+        };
+        let mut devices = 0;
+        for arg in args[1..].iter() {
+            if let Some(device) = MmioDevice::parse(arg) {
+                devices |= device.bit();
+            }
+            else {
+                console!("\nunknown device {}", arg);
+                return;
+            }
+        }
+        let id = exec(dir.device_id.to_string() + &dir.path[..] + name, devices);
+        if id == -1 as isize as usize {
+            console!("exec {} fail\n", name);
+        }
+        else {
+            wait(id);
+        }
+    }
+
+    /// ### 检查文件系统
+    /// fsck 编号，只做检查，修复需要在主机上对镜像运行 fsck -r
+    fn fsck(&self, args : &[&str]) {
+        let idx = if let Some(idx) = args.get(0) { convert_to_usize(&idx.to_string()) } else {
+            console!("\nusage: fsck #");
+            return;
+        };
+        if let Some(report) = filesystem::check(idx) {
+            for err in report.error.iter() {
+                console!("\n{}", err);
+            }
+            console!("\n{:?}: {} directories, {} files, {} used, {} lost",
+                report.fs, report.directory, report.file, report.used, report.lost);
+        }
+        else {
+            console!("\nfilesystem {} unknown", idx);
+        }
+    }
+
+    /// 向第一个配置了网关的接口的网关发送一个测试 UDP 数据报
+    fn send(&self) {
+        let rt = net::interface_op(|ifaces| {
+            let (src, gateway) = ifaces.iter().find(|iface| !iface.gateway.is_unspecified())
+                .map(|iface| (iface.ip, iface.gateway))?;
+            let datagram = udp::build(src, gateway, TEST_PORT, TEST_PORT, b"tisuos test");
+            let rt = ipv4::send(ifaces, gateway, PROTOCOL_UDP, &datagram).ok().map(|_| gateway);
+            net::flush(ifaces);
+            rt
+        }).flatten();
+        if let Some(gateway) = rt {
+            console!("\nsend test datagram to {}:{}", gateway, TEST_PORT);
+        }
+        else {
+            console!("\nno gateway to send");
+        }
+    }
+
+    /// 列出各接口的计数与所有套接字
+    fn netstat(&self) {
+        let mut idx = 0;
+        let mut config = InterfaceConfig::default();
+        let mut stats = InterfaceStats::default();
+        while ifconfig(idx, &mut config, 0) >= 0 && interface_stats(idx, &mut stats) >= 0 {
+            console!("\n{}\trx {} frames {} bytes, tx {} frames {} bytes", config.name(),
+                stats.rx_frames, stats.rx_bytes, stats.tx_frames, stats.tx_bytes);
+            console!("\n\tdrop rx {} tx {}, checksum error {}, arp {}",
+                stats.rx_dropped, stats.tx_dropped, stats.checksum_errors, stats.arp_entries);
+            idx += 1;
+        }
+        console!("\nproto\tlocal\t\t\tremote\t\t\tstate\t\towner\trecv-q\tsend-q");
+        let mut info = SocketInfo::default();
+        idx = 0;
+        while socket_info(idx, &mut info) >= 0 {
+            if info.socket_type == SOCK_STREAM {
+                console!("\ntcp\t{}:{}\t\t{}:{}\t\t{}\t{}\t{}\t{}", info.local_ip, info.local_port,
+                    info.remote_ip, info.remote_port, tcp::state_name(info.state), info.owner as isize,
+                    info.recv_queue, info.send_queue);
+            }
+            else {
+                console!("\nudp\t{}:{}\t\t*:*\t\t\t\t\t{}\t{}\t0", info.local_ip, info.local_port,
+                    info.owner as isize, info.recv_queue);
+            }
+            idx += 1;
+        }
+    }
+
+    /// ## 接口配置
+    /// 不带参数时列出所有接口，dhcp 重新获取地址，否则设置静态地址
+    fn ifconfig(&self, args : &[&str]) {
+        let mut configs = Vec::new();
+        loop {
+            let mut config = InterfaceConfig::default();
+            if ifconfig(configs.len(), &mut config, 0) < 0 {
+                break;
+            }
+            configs.push(config);
+        }
+        if args.len() == 0 {
+            for c in configs.iter() {
+                console!("\n{}\tmac {}\tdhcp {}\n\tinet {} netmask {} gateway {} dns {}",
+                    c.name(), MacAddress(c.mac), c.dhcp, c.ip, c.netmask, c.gateway, c.dns);
+            }
+            return;
+        }
+        let idx = if let Some(idx) = configs.iter().position(|c| c.name() == args[0]) {
+            idx
+        }
+        else {
+            console!("\nno interface {}", args[0]);
+            return;
+        };
+        let mut config = configs[idx];
+        if args.len() == 2 && args[1] == "dhcp" {
+            ifconfig(idx, &mut config, 2);
+            return;
+        }
+        if args.len() < 3 {
+            return;
+        }
+        let mut addr = [config.ip, config.netmask, Ipv4Address::UNSPECIFIED, Ipv4Address::UNSPECIFIED];
+        for (i, arg) in args[1..].iter().take(4).enumerate() {
+            if let Some(ip) = Ipv4Address::parse(arg) {
+                addr[i] = ip;
+            }
+            else {
+                console!("\ninvalid address {}", arg);
+                return;
+            }
+        }
+        config.ip = addr[0];
+        config.netmask = addr[1];
+        config.gateway = addr[2];
+        config.dns = addr[3];
+        ifconfig(idx, &mut config, 1);
+    }
+
+    /// ## 抓包
+    /// hex 以十六进制输出 pcap 文件，主机上用 xxd -r -p 还原；save 写入当前目录下已存在的文件
+    fn pcap(&self, args : &[&str]) {
+        match args.get(0).map(|s| *s).unwrap_or("status") {
+            "status" => {
+                let status = pcap::status();
+                console!("\ncapture {}, {} frames, {} bytes, {} overwritten",
+                    if status.enabled { "on" } else { "off" }, status.count,
+                    status.file_size, status.overwritten);
+            }
+            "start" => pcap::start(),
+            "stop" => pcap::stop(),
+            "clear" => pcap::clear(),
+            "hex" => {
+                console!("\n-----BEGIN PCAP-----");
+                for line in pcap::file().chunks(32) {
+                    console!("\n");
+                    for c in line {
+                        console!("{:02x}", c);
+                    }
+                }
+                console!("\n-----END PCAP-----");
+            }
+            "save" if args.len() == 2 => {
+                let dir = if let Some(dir) = &self.directory {
+                    dir
+                }
+                else {
+                    console!("\nnot in any filesystem, use cddisk to enter fs");
+                    return;
+                };
+                let path = dir.path.clone() + "/" + args[1];
+                if let Ok(len) = filesystem::io::write(dir.device_id, &path, &pcap::file()) {
+                    console!("\nsave {} bytes to {}", len, path);
+                }
+                else {
+                    console!("\nsave to {} fail", path);
+                }
+            }
+            _ => console!("\nunknown pcap command"),
+        }
+    }
+
     /// ### 获取命令行输入
     fn get_input(&mut self)->Option<char> {
         pop_input()
@@ -203,9 +468,8 @@ pub fn run() {
     }
 }
 
-use core::mem::size_of;
 
 use alloc::prelude::v1::*;
 use tisu_driver::{Pixel};
 use tisu_fs::{DirItemType, Directory, FileFlag, SystemOp};
-use crate::{console, filesystem::{self, FileInfo, elf::ELF, get_system, pop_input}, libs::{str::{convert_to_usize, from_ptr}, syscall::{directory_info, draw_rect, exec, file_info, free, list_thread, open, read, wait}}, memory::block::Block, virtio::{device::get_device, ip::Ip}};
+use crate::{console, filesystem::{self, DirEntry, FileInfo, elf::ELF, get_system, pop_input}, libs::{str::convert_to_usize, syscall::{draw_rect, exec, file_info, free, getdents, ifconfig, interface_stats, list_thread, open, read, shutdown, socket_info, sync, wait}}, interrupt::timer::get_million_time, memory::{MmioDevice, block::Block, slab_memory, swap_memory}, net::{self, SOCK_STREAM, SocketInfo, address::{Ipv4Address, MacAddress}, icmp, interface::{InterfaceConfig, InterfaceStats}, ipv4::{self, PROTOCOL_UDP}, pcap, tcp, udp}};
@@ -31,6 +31,21 @@ pub fn init(hartid : usize){
     }
 }
 
+/// ## 获取当前核心号
+/// 根据 sscratch 指向的环境推算，M、S 模式下都可以使用，不需要系统调用
+pub fn current_hartid()->usize {
+    unsafe {
+        let base = &ENVS as *const [Environment;4] as usize;
+        let addr = riscv64::sscratch::read();
+        if addr >= base && addr < base + size_of::<[Environment;4]>() {
+            (addr - base) / size_of::<Environment>()
+        }
+        else {
+            0
+        }
+    }
+}
+
 extern "C" {
     pub fn waiting();
 }
@@ -83,7 +98,7 @@ pub extern "C" fn s_trap(env:&mut Environment, cause:usize,
                         let mgr = get_task_mgr().unwrap();
                         env.epc += 4;
                         mgr.schedule(env);
-                        env.satp = 0;
+                        env.satp = kernel_satp();
                         env.epc = waiting as usize;
                     },
                     syscall::SyscallResult::Normal(rt) => {
@@ -120,8 +135,11 @@ pub extern "C" fn s_trap(env:&mut Environment, cause:usize,
 
 fn timer(env:&mut Environment) {
     // activate_hart();
+    if env.hartid == 0 {
+        net::tick();
+    }
     get_task_mgr().unwrap().schedule(env);
-    env.satp = 0;
+    env.satp = kernel_satp();
     env.epc = waiting as usize;
     trigger_timer();
 }
@@ -129,7 +147,7 @@ fn timer(env:&mut Environment) {
 fn software(env:&mut Environment) {
     trigger_software();
     get_task_mgr().unwrap().schedule(env);
-    env.satp = 0;
+    env.satp = kernel_satp();
     env.epc = waiting as usize;
 }
 
@@ -174,7 +192,7 @@ fn load_page_fault(status : usize, mtval : usize, env:&mut Environment) {
     println!("load page fault");
     let mgr = get_task_mgr().unwrap();
     let id =mgr.get_current_task(env.hartid).unwrap().0.tid;
-    if mgr.expand_stack(id).is_err() {
+    if mgr.page_fault(id, mtval).is_err() {
         println!("into s_trap hartid: {:x}, status: {:x}, epc: {:x}, sp: {:x} st:{:x} ed:{:x},
             satp {:x}, mscratch {:x}, mtval {:x}",
             env.hartid, status, env.epc, env.regs[Register::SP.val()],
@@ -194,7 +212,7 @@ fn store_page_fault(status : usize, mtval : usize, env:&mut Environment) {
     println!("store page fault");
     let mgr = get_task_mgr().unwrap();
     let id =mgr.get_current_task(env.hartid).unwrap().0.tid;
-    if mgr.expand_stack(id).is_err() {
+    if mgr.page_fault(id, mtval).is_err() {
         println!("into s_trap hartid: {:x}, status: {:x}, epc: {:x}, sp: {:x} st:{:x} ed:{:x},
             satp {:x}, mscratch {:x}, mtval {:x}",
             env.hartid, status, env.epc, env.regs[Register::SP.val()],
@@ -216,10 +234,11 @@ fn drop_task(id:usize, env:&mut Environment) {
     mgr.program_exit(id);
     activate_hart();
     mgr.schedule(env);
-    write_satp(0);
+    write_satp(kernel_satp());
     env.epc = waiting as usize;
 }
 
-use crate::{interrupt::{environment::Register, software}, libs::{cpu::write_satp, syscall::{trigger_software, trigger_timer}}, memory::{config::{KERNEL_STACK_END, KERNEL_STACK_START, PAGE_SIZE}, map::SATP}, task::get_task_mgr};
-use crate::{plic};
+use crate::{interrupt::{environment::Register, software}, libs::{cpu::write_satp, syscall::{trigger_software, trigger_timer}}, memory::{config::{KERNEL_STACK_END, KERNEL_STACK_START, PAGE_SIZE}, kernel_space::kernel_satp, map::SATP}, task::get_task_mgr};
+use core::mem::size_of;
+use crate::{net, plic};
 use super::{environment::Environment, syscall};
\ No newline at end of file
@@ -32,8 +32,6 @@ const GET_KEY_PRESS     : usize = 18;
 const GET_KEY_RELEASE   : usize = 19;
 const GET_MOUSE_SCROLL  : usize = 20;
 const GET_MOUSE_POS     : usize = 21;
-/// 获取目录信息，@id:usize->*const DirectoryInfo
-const DIRECTORY_INFO    : usize = 22;
 /// 关闭文件，打开后必须关闭，目前进程结束会自动关闭所有文件，@id:usize
 const CLOSE             : usize = 23;
 /// @id:usize
@@ -42,6 +40,7 @@ const KILL              : usize = 24;
 const CLOSE_TIMER       : usize = 25;
 /// 开启时钟、软件中断
 const OPEN_TIMER        : usize = 26;
+/// 写回所有缓冲、卸载文件系统后关机
 const SHUTDOWN          : usize = 27;
 /// 使目标线程睡眠，@id:usize
 const SLEEP             : usize = 28;
@@ -51,9 +50,85 @@ const WAKE              : usize = 29;
 const JOIN              : usize = 30;
 const GET_TID           : usize = 31;
 const NEXT              : usize = 32;
+/// 新建套接字，@type:usize->id:usize
+const SOCKET            : usize = 33;
+/// 绑定本地端口，端口为 0 时分配临时端口，@id:usize;@port:usize->port:usize
+const BIND              : usize = 34;
+/// @id:usize;@buf:*const u8;@len:usize;@addr:u32;@port:usize->len:usize
+const SEND_TO           : usize = 35;
+/// @id:usize;@buf:*mut u8;@len:usize;@flag:usize->len:usize，来源地址、端口放在 a1
+const RECV_FROM         : usize = 36;
+/// 关闭套接字，进程结束会自动关闭所有套接字，@id:usize
+const SOCKET_CLOSE      : usize = 37;
+/// @id:usize;@backlog:usize
+const LISTEN            : usize = 38;
+/// @id:usize;@flag:usize->id:usize，对端地址、端口放在 a1
+const ACCEPT            : usize = 39;
+/// 阻塞直到连接建立或失败，@id:usize;@addr:u32;@port:usize
+const CONNECT           : usize = 40;
+/// @id:usize;@buf:*const u8;@len:usize;@flag:usize->len:usize
+const SEND              : usize = 41;
+/// 返回 0 表示对端已关闭，@id:usize;@buf:*mut u8;@len:usize;@flag:usize->len:usize
+const RECV              : usize = 42;
+/// 查询或修改接口配置，@idx:usize;@config:*mut InterfaceConfig;@op:usize
+const IFCONFIG          : usize = 43;
+const IFCONFIG_GET      : usize = 0;
+/// 设置静态配置并停止 DHCP
+const IFCONFIG_SET      : usize = 1;
+/// 清除地址并重新通过 DHCP 获取
+const IFCONFIG_DHCP     : usize = 2;
+/// 解析主机名，阻塞直到得到结果，@name:*const u8;@len:usize->addr:u32
+const RESOLVE           : usize = 44;
+/// 控制抓包或者读取 pcap 文件，@op:usize;@buf:*mut u8;@len:usize->size:usize
+const PCAP              : usize = 45;
+const PCAP_START        : usize = 0;
+const PCAP_STOP         : usize = 1;
+const PCAP_CLEAR        : usize = 2;
+/// 复制 pcap 文件的前 len 字节，返回文件总长度
+const PCAP_READ         : usize = 3;
+/// 查询第 idx 个接口的计数或者第 idx 个套接字的信息，@op:usize;@idx:usize;@buf:*mut u8
+const NETSTAT           : usize = 46;
+/// 写入 InterfaceStats
+const NETSTAT_INTERFACE : usize = 0;
+/// 写入 SocketInfo
+const NETSTAT_SOCKET    : usize = 1;
+/// 把所有磁盘缓冲中的脏块写回
+const SYNC              : usize = 47;
+/// 把文件所在磁盘的脏块写回，@id:usize
+const FSYNC             : usize = 48;
+/// 等待块设备请求完成，@id:usize
+const IO_WAIT           : usize = 49;
+/// 获取文件或目录的状态，@path:str;@buf:*mut Stat
+const STAT              : usize = 50;
+/// 获取已打开文件的状态，@id:usize;@buf:*mut Stat
+const FSTAT             : usize = 51;
+/// 从第 cookie 项开始读取目录项到缓冲，返回写入的字节数，@path:str;@buf:*mut u8;@len:usize;@cookie:usize->len:usize
+const GETDENTS          : usize = 52;
+/// 新建符号链接，目标为同一文件系统内的路径，@target:str;@path:str
+const SYMLINK           : usize = 53;
+/// 新建硬链接，两个路径须在同一文件系统，@old:str;@new:str
+const LINK              : usize = 54;
+/// 读取符号链接的目标，返回目标长度，@path:str;@buf:*mut u8;@len:usize->len:usize
+const READLINK          : usize = 55;
+/// 收发时不阻塞
+const MSG_DONTWAIT      : usize = 1;
+/// 阻塞接收时的重试间隔，单位微秒
+const SOCKET_RETRY_TIME : usize = 10_000;
 
 static mut CLOSE_CNT : [usize;4] = [0;4];
 
+/// 用户地址转换为物理地址，页面换入失败时系统调用返回 fail
+macro_rules! user_ptr {
+    ($mgr:expr, $tid:expr, $va:expr, $fail:expr) => {
+        if let Some(pa) = $mgr.virt_to_phy($tid, $va) {
+            pa
+        }
+        else {
+            return $fail;
+        }
+    };
+}
+
 pub enum SyscallResult {
     Schedule(usize),
     Normal(usize),
@@ -75,6 +150,48 @@ pub fn handler(env : &mut Environment)->SyscallResult {
         NEXT => {
             rt = SyscallResult::Schedule(0);
         }
+        SOCKET => {
+            rt = SyscallResult::Normal(socket(env));
+        }
+        BIND => {
+            rt = SyscallResult::Normal(bind(env));
+        }
+        SEND_TO => {
+            rt = SyscallResult::Normal(send_to(env));
+        }
+        RECV_FROM => {
+            rt = recv_from(env);
+        }
+        SOCKET_CLOSE => {
+            socket_close(env);
+        }
+        LISTEN => {
+            rt = SyscallResult::Normal(listen(env));
+        }
+        ACCEPT => {
+            rt = accept(env);
+        }
+        CONNECT => {
+            rt = connect(env);
+        }
+        SEND => {
+            rt = send(env);
+        }
+        RECV => {
+            rt = recv(env);
+        }
+        IFCONFIG => {
+            rt = SyscallResult::Normal(ifconfig(env));
+        }
+        RESOLVE => {
+            rt = resolve(env);
+        }
+        PCAP => {
+            rt = SyscallResult::Normal(pcap(env));
+        }
+        NETSTAT => {
+            rt = SyscallResult::Normal(netstat(env));
+        }
         GET_TID => {
             let mgr = get_task_mgr().unwrap();
             let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
@@ -97,9 +214,25 @@ pub fn handler(env : &mut Environment)->SyscallResult {
             rt = SyscallResult::Schedule(0);
         }
         SHUTDOWN => {
-            unsafe {
-                const VIRT_TEST: *mut u32 = 0x10_0000 as *mut u32;
-                VIRT_TEST.write_volatile(0x5555);
+            shutdown();
+        }
+        SYNC => {
+            disk_cache::flush_all();
+        }
+        FSYNC => {
+            rt = SyscallResult::Normal(fsync(env));
+        }
+        STAT => {
+            rt = SyscallResult::Normal(stat(env));
+        }
+        FSTAT => {
+            rt = SyscallResult::Normal(fstat(env));
+        }
+        IO_WAIT => {
+            let mgr = get_task_mgr().unwrap();
+            let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+            if block_io::sleep_on(env.a1(), exec.tid, |tid| { mgr.sleep_task(tid, env).unwrap(); }) {
+                rt = SyscallResult::Schedule(0);
             }
         }
         OPEN_TIMER => {
@@ -131,8 +264,17 @@ pub fn handler(env : &mut Environment)->SyscallResult {
         CLOSE => {
             close(env);
         }
-        DIRECTORY_INFO => {
-            rt = SyscallResult::Normal(directory_info(env));
+        GETDENTS => {
+            rt = SyscallResult::Normal(getdents(env));
+        }
+        SYMLINK => {
+            rt = SyscallResult::Normal(symlink(env));
+        }
+        LINK => {
+            rt = SyscallResult::Normal(link(env));
+        }
+        READLINK => {
+            rt = SyscallResult::Normal(readlink(env));
         }
         GET_MOUSE_POS => {
             rt = SyscallResult::Normal(get_mouse_x());
@@ -164,7 +306,7 @@ pub fn handler(env : &mut Environment)->SyscallResult {
                 let (exec,_) = mgr.get_current_task(env.hartid).unwrap();
                 let id = env.regs[Register::A1.val()];
                 let addr = env.regs[Register::A2.val()];
-                let ptr = mgr.virt_to_phy(exec.tid, addr) as *const u8;
+                let ptr = user_ptr!(mgr, exec.tid, addr, SyscallResult::Normal(-1 as isize as usize)) as *const u8;
                 let data = unsafe{& *(slice_from_raw_parts(ptr, len))};
                 if let Ok(len) = write(exec.pid, id, data) {
                     rt = SyscallResult::Normal(len);
@@ -181,7 +323,7 @@ pub fn handler(env : &mut Environment)->SyscallResult {
                 let (exec,_) = mgr.get_current_task(env.hartid).unwrap();
                 let id = env.regs[Register::A1.val()];
                 let addr = env.regs[Register::A2.val()];
-                let ptr = mgr.virt_to_phy(exec.tid, addr) as *mut u8;
+                let ptr = user_ptr!(mgr, exec.tid, addr, SyscallResult::Normal(-1 as isize as usize)) as *mut u8;
                 let data = unsafe{&mut *(slice_from_raw_parts_mut(ptr, len))};
                 if let Ok(len) = read(exec.pid, id, data) {
                     rt = SyscallResult::Normal(len);
@@ -210,8 +352,13 @@ pub fn handler(env : &mut Environment)->SyscallResult {
         MALLOC => {
             let mgr = get_task_mgr().unwrap();
             let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
-            let t = mgr.alloc_heap(env.regs[Register::A1.val()], exec.tid);
-            rt = SyscallResult::Normal(t.0);
+            // 申请失败返回 -1，由用户程序处理
+            if let Ok((va, _)) = mgr.alloc_heap(env.regs[Register::A1.val()], exec.tid) {
+                rt = SyscallResult::Normal(va);
+            }
+            else {
+                rt = SyscallResult::Normal(-1 as isize as usize);
+            }
         }
         SET_TIMER => {
             let time = env.regs[Register::A1.val()];
@@ -274,59 +421,435 @@ fn close(env : &Environment) {
     }
 }
 
-fn directory_info(env : &Environment)->usize {
+fn socket(env : &Environment)->usize {
     let mgr = get_task_mgr().unwrap();
     let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
-    let ptr = mgr.virt_to_phy(exec.tid, env.a1());
-    let all_path = from_ptr(ptr as *mut char);
-    let idx = all_path.find("/").unwrap();
-    let (id, path) = all_path.split_at(idx);
-    let id = convert_to_usize(&id.to_string());
-    if let Some(sys) = get_system(id) {
-        let flag = env.a2();
-        if let Ok(dir) = sys.enter(path.to_string().clone()) {
-            let mut dir_num = 0;
-            let mut file_num = 0;
-            for item in dir.item.iter() {
-                if item.is_dir() {
-                    dir_num += 1;
-                }
-                else {
-                    file_num += 1;
-                }
-            }
-            let t = 15 * size_of::<char>();
-            let size = size_of::<DirectoryInfo>() + dir_num * t + file_num * t;
-            let (va, pa) = mgr.alloc_heap(size, exec.tid);
-            let ptr = pa as *mut u8 as *mut DirectoryInfo;
-            unsafe {(*ptr).replace(&dir, dir_num, file_num, 15)}
-            let ptr = (pa + size_of::<DirectoryInfo>()) as *mut char;
-            let mut idx = 0;
-            for file in dir.item.iter() {
-                if file.is_file() {
-                    let ptr = unsafe {ptr.add(idx)};
-                    write_str(ptr, &file.name, t);
-                    idx += 15;
-                }
-            }
-            for dir in dir.item.iter() {
-                if dir.is_dir() {
-                    let ptr = unsafe {ptr.add(idx)};
-                    write_str(ptr, &dir.name, t);
-                    idx += 15;
-                }
-            }
-            va
+    let id = match env.a1() {
+        SOCK_DGRAM => udp::socket(exec.pid),
+        SOCK_STREAM => tcp::socket(exec.pid),
+        _ => None,
+    };
+    if let Some(id) = id {
+        mgr.push_socket(exec.tid, id);
+        id
+    }
+    else {
+        -1 as isize as usize
+    }
+}
+
+fn bind(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    if env.a2() > u16::MAX as usize {
+        return -1 as isize as usize;
+    }
+    let rt = if udp::is_own(id, exec.pid) {
+        udp::bind(id, env.a2() as u16)
+    }
+    else if tcp::is_own(id, exec.pid) {
+        tcp::bind(id, env.a2() as u16)
+    }
+    else {
+        Err(())
+    };
+    match rt {
+        Ok(port) => port as usize,
+        Err(_) => -1 as isize as usize,
+    }
+}
+
+fn send_to(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    let len = env.a3();
+    if env.a5() > u16::MAX as usize || !udp::is_own(id, exec.pid) {
+        return -1 as isize as usize;
+    }
+    let ptr = user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize) as *const u8;
+    let data = unsafe {&*(slice_from_raw_parts(ptr, len))};
+    let dst = Ipv4Address::from_val(env.a4() as u32);
+    match udp::send_to(id, dst, env.a5() as u16, data) {
+        Ok(len) => len,
+        Err(_) => -1 as isize as usize,
+    }
+}
+
+/// ## 接收数据报
+/// 数据超过缓冲区时截断，套接字无效返回 -1。队列为空时，非阻塞返回 -2，阻塞则定时重试
+fn recv_from(env : &mut Environment)->SyscallResult {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    if !udp::is_own(id, exec.pid) {
+        return SyscallResult::Normal(-1 as isize as usize);
+    }
+    // 取出数据报之前转换缓冲区地址，失败时数据报留在队列中
+    let ptr = if env.a3() > 0 {
+        user_ptr!(mgr, exec.tid, env.a2(), SyscallResult::Normal(-1 as isize as usize)) as *mut u8
+    }
+    else {
+        null_mut()
+    };
+    if let Some(datagram) = udp::recv_from(id) {
+        let len = datagram.data.len().min(env.a3());
+        if len > 0 {
+            let buffer = unsafe {&mut *(slice_from_raw_parts_mut(ptr, len))};
+            buffer.copy_from_slice(&datagram.data[..len]);
+        }
+        env.regs[Register::A1.val()] = (datagram.src.val() as usize) << 16 | datagram.src_port as usize;
+        SyscallResult::Normal(len)
+    }
+    else if env.a4() & MSG_DONTWAIT != 0 {
+        SyscallResult::Normal(-2 as isize as usize)
+    }
+    else {
+        mgr.retry_timer(env, SOCKET_RETRY_TIME);
+        SyscallResult::Schedule(0)
+    }
+}
+
+fn socket_close(env : &Environment) {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    if is_socket_own(id, exec.pid) {
+        mgr.release_socket(exec.tid, id);
+        close_socket(id);
+    }
+}
+
+fn listen(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    if tcp::is_own(id, exec.pid) && tcp::listen(id, env.a2()).is_ok() {
+        0
+    }
+    else {
+        -1 as isize as usize
+    }
+}
+
+/// ## 接受连接
+/// 新连接加入调用者的资源表。没有连接时，非阻塞返回 -2，阻塞则定时重试
+fn accept(env : &mut Environment)->SyscallResult {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    if !tcp::is_own(id, exec.pid) {
+        return SyscallResult::Normal(-1 as isize as usize);
+    }
+    match tcp::accept(id) {
+        Ok(Some((child, addr, port))) => {
+            mgr.push_socket(exec.tid, child);
+            env.regs[Register::A1.val()] = (addr.val() as usize) << 16 | port as usize;
+            SyscallResult::Normal(child)
+        }
+        Ok(None) if env.a2() & MSG_DONTWAIT != 0 => SyscallResult::Normal(-2 as isize as usize),
+        Ok(None) => {
+            mgr.retry_timer(env, SOCKET_RETRY_TIME);
+            SyscallResult::Schedule(0)
+        }
+        Err(_) => SyscallResult::Normal(-1 as isize as usize),
+    }
+}
+
+/// 第一次执行时发送 SYN，之后每次重试查询连接状态
+fn connect(env : &mut Environment)->SyscallResult {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    if env.a3() > u16::MAX as usize || !tcp::is_own(id, exec.pid) {
+        return SyscallResult::Normal(-1 as isize as usize);
+    }
+    match tcp::connect(id, Ipv4Address::from_val(env.a2() as u32), env.a3() as u16) {
+        Ok(true) => SyscallResult::Normal(0),
+        Ok(false) => {
+            mgr.retry_timer(env, SOCKET_RETRY_TIME);
+            SyscallResult::Schedule(0)
         }
-        else {
-            println!("file info open err path {}, flag {}", path, flag);
-            0
+        Err(_) => SyscallResult::Normal(-1 as isize as usize),
+    }
+}
+
+/// 发送缓冲区已满时，非阻塞返回 -2，阻塞则定时重试
+fn send(env : &mut Environment)->SyscallResult {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    let len = env.a3();
+    if !tcp::is_own(id, exec.pid) {
+        return SyscallResult::Normal(-1 as isize as usize);
+    }
+    if len == 0 {
+        return SyscallResult::Normal(0);
+    }
+    let ptr = user_ptr!(mgr, exec.tid, env.a2(), SyscallResult::Normal(-1 as isize as usize)) as *const u8;
+    let data = unsafe {&*(slice_from_raw_parts(ptr, len))};
+    match tcp::send(id, data) {
+        Ok(0) if env.a4() & MSG_DONTWAIT != 0 => SyscallResult::Normal(-2 as isize as usize),
+        Ok(0) => {
+            mgr.retry_timer(env, SOCKET_RETRY_TIME);
+            SyscallResult::Schedule(0)
+        }
+        Ok(len) => SyscallResult::Normal(len),
+        Err(_) => SyscallResult::Normal(-1 as isize as usize),
+    }
+}
+
+/// 没有数据时，非阻塞返回 -2，阻塞则定时重试
+fn recv(env : &mut Environment)->SyscallResult {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    let len = env.a3();
+    if !tcp::is_own(id, exec.pid) {
+        return SyscallResult::Normal(-1 as isize as usize);
+    }
+    if len == 0 {
+        return SyscallResult::Normal(0);
+    }
+    let ptr = user_ptr!(mgr, exec.tid, env.a2(), SyscallResult::Normal(-1 as isize as usize)) as *mut u8;
+    let data = unsafe {&mut *(slice_from_raw_parts_mut(ptr, len))};
+    match tcp::recv(id, data) {
+        Ok(Some(len)) => SyscallResult::Normal(len),
+        Ok(None) if env.a4() & MSG_DONTWAIT != 0 => SyscallResult::Normal(-2 as isize as usize),
+        Ok(None) => {
+            mgr.retry_timer(env, SOCKET_RETRY_TIME);
+            SyscallResult::Schedule(0)
+        }
+        Err(_) => SyscallResult::Normal(-1 as isize as usize),
+    }
+}
+
+/// 接口不存在、操作未知或者对回环接口启用 DHCP 时返回 -1
+fn ifconfig(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let idx = env.a1();
+    let op = env.a3();
+    let config = unsafe {&mut *(user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize) as *mut InterfaceConfig)};
+    let rt = net::interface_op(|ifaces| {
+        let iface = ifaces.get_mut(idx).ok_or(())?;
+        match op {
+            IFCONFIG_GET => *config = iface.config(),
+            IFCONFIG_SET => iface.set_config(config),
+            IFCONFIG_DHCP if !iface.is_loopback() => dhcp::start(iface),
+            _ => return Err(()),
+        }
+        iface.flush();
+        Ok(())
+    });
+    if let Some(Ok(_)) = rt {
+        0
+    }
+    else {
+        -1 as isize as usize
+    }
+}
+
+/// 查询进行中时定时重试，名字不存在或者查询失败返回 -1
+fn resolve(env : &mut Environment)->SyscallResult {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let ptr = user_ptr!(mgr, exec.tid, env.a1(), SyscallResult::Normal(-1 as isize as usize)) as *const u8;
+    let name = unsafe {&*(slice_from_raw_parts(ptr, env.a2()))};
+    let name = if let Ok(name) = core::str::from_utf8(name) {
+        name
+    }
+    else {
+        return SyscallResult::Normal(-1 as isize as usize);
+    };
+    match dns::resolve(name) {
+        Ok(Some(addr)) => SyscallResult::Normal(addr.val() as usize),
+        Ok(None) => {
+            mgr.retry_timer(env, SOCKET_RETRY_TIME);
+            SyscallResult::Schedule(0)
+        }
+        Err(_) => SyscallResult::Normal(-1 as isize as usize),
+    }
+}
+
+/// 操作未知时返回 -1
+fn pcap(env : &Environment)->usize {
+    match env.a1() {
+        PCAP_START => pcap::start(),
+        PCAP_STOP => pcap::stop(),
+        PCAP_CLEAR => pcap::clear(),
+        PCAP_READ => {
+            let mgr = get_task_mgr().unwrap();
+            let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+            let file = pcap::file();
+            let len = env.a3().min(file.len());
+            if len > 0 {
+                let ptr = user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize) as *mut u8;
+                let data = unsafe {&mut *(slice_from_raw_parts_mut(ptr, len))};
+                data.copy_from_slice(&file[..len]);
+            }
+            return file.len();
         }
+        _ => return -1 as isize as usize,
+    }
+    0
+}
+
+/// 文件未打开时返回 -1
+fn fsync(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    let disk = search_system(id).and_then(|sys| {
+        if sys.file(id)?.is_own(exec.pid) { search_disk(id).and_then(filesystem::system_volume) } else { None }
+    });
+    if let Some(disk) = disk {
+        disk_cache::flush(disk);
+        0
+    }
+    else {
+        -1 as isize as usize
+    }
+}
+
+/// 路径不存在时返回 -1
+fn stat(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let all_path = from_ptr(user_ptr!(mgr, exec.tid, env.a1(), -1 as isize as usize) as *mut char);
+    let rt = split_system(&all_path).and_then(|(id, path)| metadata::stat(id, path));
+    write_stat(user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize), rt)
+}
+
+/// 文件未打开时返回 -1
+fn fstat(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let id = env.a1();
+    let own = search_system(id).and_then(|sys| sys.file(id)).map(|file| file.is_own(exec.pid));
+    let rt = if own == Some(true) { metadata::fstat(id) } else { None };
+    write_stat(user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize), rt)
+}
+
+fn write_stat(ptr : usize, stat : Option<Stat>)->usize {
+    if let Some(stat) = stat {
+        unsafe {*(ptr as *mut Stat) = stat;}
+        0
     }
     else {
-        println!("file info device err {}", idx);
+        -1 as isize as usize
+    }
+}
+
+/// 拆出路径开头的文件系统编号
+fn split_system(all_path : &str)->Option<(usize, &str)> {
+    let idx = all_path.find("/")?;
+    let (id, path) = all_path.split_at(idx);
+    Some((convert_to_usize(&id.to_string()), path))
+}
+
+/// 链接操作失败返回 -1
+fn link_result(rt : Result<usize, LinkError>)->usize {
+    match rt {
+        Ok(rt) => rt,
+        Err(_) => -1 as isize as usize,
+    }
+}
+
+fn symlink(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let target = from_ptr(user_ptr!(mgr, exec.tid, env.a1(), -1 as isize as usize) as *mut char);
+    let all_path = from_ptr(user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize) as *mut char);
+    let rt = split_system(&all_path).ok_or(LinkError::NotFound)
+        .and_then(|(id, path)| filesystem::symlink(id, &target, path));
+    link_result(rt.map(|_| 0))
+}
+
+fn link(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let old = from_ptr(user_ptr!(mgr, exec.tid, env.a1(), -1 as isize as usize) as *mut char);
+    let new = from_ptr(user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize) as *mut char);
+    let rt = match (split_system(&old), split_system(&new)) {
+        (Some((id, old)), Some((new_id, new))) if id == new_id => filesystem::link(id, old, new),
+        (Some(_), Some(_)) => Err(LinkError::Unsupported),
+        _ => Err(LinkError::NotFound),
+    };
+    link_result(rt.map(|_| 0))
+}
+
+/// 目标超过缓冲时只复制前 len 字节，返回目标的完整长度
+fn readlink(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let all_path = from_ptr(user_ptr!(mgr, exec.tid, env.a1(), -1 as isize as usize) as *mut char);
+    let rt = split_system(&all_path).ok_or(LinkError::NotFound)
+        .and_then(|(id, path)| filesystem::readlink(id, path));
+    link_result(rt.map(|target| {
+        let len = env.a3().min(target.len());
+        if len > 0 {
+            let ptr = user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize) as *mut u8;
+            let data = unsafe {&mut *(slice_from_raw_parts_mut(ptr, len))};
+            data.copy_from_slice(&target.as_bytes()[..len]);
+        }
+        target.len()
+    }))
+}
+
+/// 写回缓冲、卸载文件系统，再通过 QEMU 测试设备关机
+fn shutdown() {
+    filesystem::unmount();
+    disk_cache::flush_all();
+    unsafe {
+        const VIRT_TEST: *mut u32 = 0x10_0000 as *mut u32;
+        VIRT_TEST.write_volatile(0x5555);
+    }
+}
+
+/// 接口或套接字不存在、操作未知时返回 -1
+fn netstat(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let idx = env.a2();
+    let ptr = user_ptr!(mgr, exec.tid, env.a3(), -1 as isize as usize);
+    let rt = match env.a1() {
+        NETSTAT_INTERFACE => net::interface_op(|ifaces| {
+            let stats = ifaces.get(idx)?.stats();
+            unsafe {*(ptr as *mut InterfaceStats) = stats;}
+            Some(())
+        }).flatten(),
+        NETSTAT_SOCKET => net::sockets().get(idx).map(|info| {
+            unsafe {*(ptr as *mut SocketInfo) = *info;}
+        }),
+        _ => None,
+    };
+    if rt.is_some() {
         0
     }
+    else {
+        -1 as isize as usize
+    }
+}
+
+/// 目录不存在或者缓冲区放不下一条记录时返回 -1，读完返回 0
+fn getdents(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
+    let all_path = from_ptr(user_ptr!(mgr, exec.tid, env.a1(), -1 as isize as usize) as *mut char);
+    let ptr = user_ptr!(mgr, exec.tid, env.a2(), -1 as isize as usize) as *mut u8;
+    let buf = unsafe {&mut *(slice_from_raw_parts_mut(ptr, env.a3()))};
+    let rt = split_system(&all_path).and_then(|(id, path)| {
+        let items = filesystem::read_dir(id, path)?;
+        fill_dirent(&items[..], env.a4(), buf)
+    });
+    if let Some(len) = rt {
+        len
+    }
+    else {
+        -1 as isize as usize
+    }
 }
 
 fn draw_rect(env : &Environment) {
@@ -340,7 +863,7 @@ fn draw_rect(env : &Environment) {
         y2:env.a4() as u32};
     let mgr = get_task_mgr().unwrap();
     let (exec,_) = mgr.get_current_task(env.hartid).unwrap();
-    let data = mgr.virt_to_phy(exec.tid, env.a5()) as *const Pixel;
+    let data = user_ptr!(mgr, exec.tid, env.a5(), ()) as *const Pixel;
     let len = (rect.x2 - rect.x1) * (rect.y2 - rect.y1);
     let buffer = unsafe {&*(slice_from_raw_parts(data, len as usize))};
     get_device().draw_rect_override(0, rect, buffer);
@@ -350,14 +873,12 @@ fn draw_rect(env : &Environment) {
 fn file_info(env : &Environment)->usize {
     let mgr = get_task_mgr().unwrap();
     let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
-    let ptr = mgr.virt_to_phy(exec.tid, env.a1());
+    let ptr = user_ptr!(mgr, exec.tid, env.a1(), 0);
     let all_path = from_ptr(ptr as *mut char);
-    let idx = all_path.find("/").unwrap();
-    let (id, path) = all_path.split_at(idx);
-    let id = convert_to_usize(&id.to_string());
+    let (id, path) = if let Some(p) = split_system(&all_path) { p } else { return 0; };
     if let Some(sys) = get_system(id) {
         let flag = env.a2();
-        if let Ok(file) = sys.get_file(path.to_string().clone()) {
+        if let Ok(file) = sys.get_file(filesystem::resolve(id, path).unwrap_or_default()) {
             let file_info = FileInfo::new(
                 file.id,
                 file.device_id,
@@ -365,7 +886,10 @@ fn file_info(env : &Environment)->usize {
                 file.state.flag.val(),
                 file.size
             );
-            let (p, ptr) = mgr.alloc_heap(size_of::<FileInfo>(), exec.tid);
+            let (p, ptr) = match mgr.alloc_heap(size_of::<FileInfo>(), exec.tid) {
+                Ok(addr) => addr,
+                Err(_) => return 0,
+            };
             let ptr = ptr as *mut FileInfo;
             unsafe {
                 ptr.write_volatile(file_info);
@@ -378,7 +902,7 @@ fn file_info(env : &Environment)->usize {
         }
     }
     else {
-        println!("file info device err {}", idx);
+        println!("file info device err {}", id);
         0
     }
 }
@@ -386,16 +910,29 @@ fn file_info(env : &Environment)->usize {
 fn open(env : &Environment)->isize {
     let mgr = get_task_mgr().unwrap();
     let (exec, _) = mgr.get_current_task(env.hartid).unwrap();
-    let all_path = from_ptr(mgr.virt_to_phy(exec.tid, env.a1()) as *mut char);
-    let idx = all_path.find("/").unwrap();
-    let (id, path) = all_path.split_at(idx);
-    let id = convert_to_usize(&id.to_string());
+    let all_path = from_ptr(user_ptr!(mgr, exec.tid, env.a1(), -1) as *mut char);
+    let (id, path) = if let Some(p) = split_system(&all_path) { p } else { return -1; };
+    let path = if let Some(path) = filesystem::resolve(id, path) {
+        path
+    }
+    else {
+        println!("open err path {}, symlink loop", path);
+        return -1;
+    };
     if let Some(sys) = get_system(id) {
         let flag = env.a2();
-        if let Ok(file) = sys.open(path.to_string().clone(), FileFlag::from(flag).unwrap()) {
+        let file = journal::transaction(Some(id), || {
+            if !filesystem::prepare_open(id, &path, flag) {
+                return None;
+            }
+            let file = sys.open(path.clone(), FileFlag::from(flag).unwrap()).ok()?;
             file.own(exec.pid);
-            mgr.push_file(exec.tid, file.id);
-            file.id as isize
+            Some(file.id)
+        });
+        if let Some(file) = file {
+            metadata::opened(file, id, path.clone());
+            mgr.push_file(exec.tid, file);
+            file as isize
         }
         else {
             println!("open err path {}, flag {}", path, flag);
@@ -403,54 +940,82 @@ fn open(env : &Environment)->isize {
         }
     }
     else {
-        println!("device err {}", idx);
+        println!("device err {}", id);
         -1
     }
 }
 
 fn fork(env : &Environment)->usize {
-    get_task_mgr().unwrap().fork_task(env)
+    if let Some(id) = get_task_mgr().unwrap().fork_task(env) {
+        id
+    }
+    else {
+        -1 as isize as usize
+    }
 }
 
+/// a3 为栈大小（字节），0 表示默认大小，失败返回 -1
 fn branch(env : &Environment)->usize {
     let mgr = get_task_mgr().unwrap();
-    mgr.branch(env).unwrap()
+    if let Some(id) = mgr.branch(env, env.a3()) {
+        id
+    }
+    else {
+        -1 as isize as usize
+    }
 }
 
+/// a4 为授予新进程的设备掩码，用户进程只能授予自己拥有的设备
 fn exec(env : &Environment)->usize {
+    let mgr = get_task_mgr().unwrap();
+    let (_, caller) = mgr.get_current_task(env.hartid).unwrap();
+    let devices = env.a4();
+    if !caller.is_kernel && devices & !caller.devices != 0 {
+        return -1 as isize as usize;
+    }
     let ptr = env.regs[Register::A1.val()] as *mut char;
     let len = env.regs[Register::A2.val()];
     let path = unsafe {&*(slice_from_raw_parts(ptr, len))};
     let path = char_to_str(path);
     let is_kernel = env.regs[Register::A3.val()] != 0;
-    let idx = path.find("/").unwrap();
-    let (id, p) = path.split_at(idx);
-    let id = convert_to_usize(&id.to_string());
-    let sys = get_system(id).unwrap();
-    let file = sys.open(p.to_string(), tisu_fs::FileFlag::Read).unwrap().clone();
+    let (id, p) = if let Some(rt) = split_system(&path) { rt } else { return -1 as isize as usize; };
+    let sys = if let Some(sys) = get_system(id) { sys } else { return -1 as isize as usize; };
+    let p = if let Some(p) = filesystem::resolve(id, p) { p } else { return -1 as isize as usize; };
+    let file = if let Ok(file) = sys.open(p, tisu_fs::FileFlag::Read) { file.clone() } else { return -1 as isize as usize; };
     let data = Block::<u8>::new(file.size);
-    sys.read(file.id, data.to_array(0, file.size)).unwrap();
+    if sys.read(file.id, data.to_array(0, file.size)).is_err() {
+        return -1 as isize as usize;
+    }
     let elf = data.type_as::<ELF>();
     if !elf.is_elf() {
-        return 0;
+        return -1 as isize as usize;
     }
     let mut elf = ElfManager::new(elf);
-    let mgr = get_task_mgr().unwrap();
     let mut program = ProgramArea::new(elf.entry(), is_kernel);
-    program.push_elf(&mut elf);
-    let task_id = mgr.create_task(program, env).unwrap();
-    mgr.wake_task(task_id);
-    task_id
+    if program.push_elf(&mut elf).is_err() {
+        return -1 as isize as usize;
+    }
+    for device in MmioDevice::from_mask(devices) {
+        program.grant_device(device);
+    }
+    if let Some(task_id) = mgr.create_task(program, env) {
+        mgr.wake_task(task_id);
+        task_id
+    }
+    else {
+        -1 as isize as usize
+    }
 }
 
 
-use core::{mem::size_of, ptr::{slice_from_raw_parts, slice_from_raw_parts_mut}};
+use core::{mem::size_of, ptr::{null_mut, slice_from_raw_parts, slice_from_raw_parts_mut}};
 
 use alloc::prelude::v1::*;
 use tisu_driver::{Pixel, Rect};
 use tisu_fs::{FileFlag, SystemOp};
-use crate::{filesystem::{DirectoryInfo, FileInfo, elf::{ELF, ElfManager}, get_system, search_system, syscall_io::{read, write}}, libs::{str::{char_to_str, convert_to_usize, from_ptr, write_str}}, memory::{ProgramArea, block::Block}, virtio::{device::{get_device, gpu_support, invalid},
+use crate::{filesystem::{self, FileInfo, LinkError, Stat, fill_dirent, elf::{ELF, ElfManager}, get_system, journal, metadata, search_disk, search_system, syscall_io::{read, write}}, libs::{str::{char_to_str, convert_to_usize, from_ptr}}, memory::{MmioDevice, ProgramArea, block::Block}, virtio::{block_io, disk_cache, device::{get_device, gpu_support, invalid},
     input_buffer::{get_key_press, get_key_release, get_mouse_x, get_mouse_y, get_scroll}}};
 use crate::task::get_task_mgr;
+use crate::net::{self, SOCK_DGRAM, SOCK_STREAM, SocketInfo, address::Ipv4Address, close_socket, dhcp, dns, interface::{InterfaceConfig, InterfaceStats}, is_socket_own, pcap, tcp, udp};
 
 use super::{environment::{Environment, Register}, timer};
\ No newline at end of file
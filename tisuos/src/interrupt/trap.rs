@@ -167,7 +167,7 @@ fn load_page_fault(status : usize, mtval : usize, env:&mut Environment) {
     println!("load page fault");
     let mgr = get_task_mgr().unwrap();
     let id =mgr.get_current_task(env.hartid).unwrap().0.tid;
-    if mgr.expand_stack(id).is_err() {
+    if mgr.page_fault(id, mtval).is_err() {
         println!("into m_trap hartid: {:x}, status: {:x}, epc: {:x}, sp: {:x} st:{:x} ed:{:x},
             satp {:x}, mscratch {:x}, mtval {:x} sscratch {:x}",
             env.hartid, status, env.epc, env.regs[Register::SP.val()],
@@ -187,7 +187,7 @@ fn store_page_fault(status : usize, mtval : usize, env:&mut Environment) {
     println!("store page fault");
     let mgr = get_task_mgr().unwrap();
     let id =mgr.get_current_task(env.hartid).unwrap().0.tid;
-    if mgr.expand_stack(id).is_err() {
+    if mgr.page_fault(id, mtval).is_err() {
         println!("into m_trap hartid: {:x}, status: {:x}, epc: {:x}, sp: {:x} st:{:x} ed:{:x},
             satp {:x}, mscratch {:x}, mtval {:x}",
             env.hartid, status, env.epc, env.regs[Register::SP.val()],
@@ -209,10 +209,10 @@ fn drop_task(id:usize, env:&mut Environment) {
     mgr.program_exit(id);
     activate_hart();
     mgr.schedule(env);
-    write_satp(0);
+    write_satp(kernel_satp());
     env.epc = waiting as usize;
 }
 
-use crate::{interrupt::{environment::Register, msyscall, software}, libs::cpu::write_satp, memory::{config::{KERNEL_STACK_END, KERNEL_STACK_START, PAGE_SIZE}, map::SATP}, task::get_task_mgr};
+use crate::{interrupt::{environment::Register, msyscall, software}, libs::cpu::write_satp, memory::{config::{KERNEL_STACK_END, KERNEL_STACK_START, PAGE_SIZE}, kernel_space::kernel_satp, map::SATP}, task::get_task_mgr};
 use crate::{plic, cpu};
 use super::{environment::Environment};
\ No newline at end of file
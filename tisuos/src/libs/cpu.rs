@@ -20,6 +20,26 @@ pub fn add_epc(n : usize) {
     }
 }
 
+/// 修改页表项后刷新 TLB
+pub fn flush_tlb() {
+    unsafe {
+        asm!("sfence.vma");
+    }
+}
+
+/// ## S 模式中断是否打开
+/// 内核线程运行时打开，进入陷入处理后关闭，可以据此判断能否睡眠
+pub fn interrupt_enabled()->bool {
+    let status : usize;
+    unsafe {
+        asm!(
+            "csrr {status}, sstatus",
+            status = out(reg) status
+        );
+    }
+    status & (1 << 1) != 0
+}
+
 pub fn write_satp(satp : usize) {
     unsafe {
         asm!(
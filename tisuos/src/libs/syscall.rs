@@ -5,6 +5,7 @@
 use alloc::prelude::v1::*;
 use tisu_driver::Pixel;
 
+use crate::net::{SocketInfo, interface::{InterfaceConfig, InterfaceStats}};
 use super::str::to_char_slice;
 
 extern "C" {
@@ -32,11 +33,15 @@ const GET_KEY_PRESS     : usize = 18;
 const GET_KEY_RELEASE   : usize = 19;
 const GET_MOUSE_SCROLL  : usize = 20;
 const GET_MOUSE_POS     : usize = 21;
-const DIRECTORY_INFO    : usize = 22;
 const CLOSE             : usize = 23;
 const KILL              : usize = 24;
 const SHUTDOWN          : usize = 27;
 const SLEEP             : usize = 28;
+const IFCONFIG          : usize = 43;
+const NETSTAT           : usize = 46;
+const SYNC              : usize = 47;
+const IO_WAIT           : usize = 49;
+const GETDENTS          : usize = 52;
 
 fn syscall(num : usize, arg1 : usize, arg2 : usize, arg3 : usize, arg4 : usize)->usize {
     unsafe {
@@ -70,6 +75,16 @@ pub fn shutdown() {
     syscall(SHUTDOWN, 0, 0, 0, 0);
 }
 
+/// 把所有磁盘缓冲中的脏块写回
+pub fn sync() {
+    syscall(SYNC, 0, 0, 0, 0);
+}
+
+/// 等待块设备请求完成，未完成时睡眠
+pub fn io_wait(id : usize) {
+    syscall(IO_WAIT, id, 0, 0, 0);
+}
+
 pub fn kill(id : usize) {
     syscall(KILL, id, 0, 0, 0);
 }
@@ -78,10 +93,27 @@ pub fn close(id : usize) {
     syscall(CLOSE, id, 0, 0, 0);
 }
 
-pub fn directory_info(path : String)->usize {
+/// 查询或修改接口配置，op 为 0 查询、1 设置静态配置、2 重新 DHCP，失败返回 -1
+pub fn ifconfig(idx : usize, config : &mut InterfaceConfig, op : usize)->isize {
+    syscall(IFCONFIG, idx, config as *mut InterfaceConfig as usize, op, 0) as isize
+}
+
+/// 查询第 idx 个接口的计数，接口不存在时返回 -1
+pub fn interface_stats(idx : usize, stats : &mut InterfaceStats)->isize {
+    syscall(NETSTAT, 0, idx, stats as *mut InterfaceStats as usize, 0) as isize
+}
+
+/// 查询第 idx 个套接字的信息，套接字不存在时返回 -1
+pub fn socket_info(idx : usize, info : &mut SocketInfo)->isize {
+    syscall(NETSTAT, 1, idx, info as *mut SocketInfo as usize, 0) as isize
+}
+
+/// 从第 cookie 项开始读取目录项，返回写入的字节数，读完返回 0，失败返回 -1
+pub fn getdents(path : String, buf : &mut [u8], cookie : usize)->isize {
     let path = to_char_slice(&path);
     let p = path.as_slice() as *const [char] as *const char as usize;
-    syscall(DIRECTORY_INFO, p, 0, 0, 0)
+    let ptr = buf as *mut [u8] as *mut u8 as usize;
+    syscall(GETDENTS, p, ptr, buf.len(), cookie) as isize
 }
 
 pub fn get_time()->usize {
@@ -104,29 +136,32 @@ pub fn file_info(path : String)->usize {
     syscall(FILE_INFO, p, 0, 0, 0)
 }
 
+/// 申请失败时返回 0
 pub fn malloc(size : usize)->usize {
-    syscall(MALLOC, size, 0, 0, 0)
+    let rt = syscall(MALLOC, size, 0, 0, 0);
+    if rt == -1 as isize as usize { 0 } else { rt }
 }
 
 pub fn fork()->usize{
     syscall(FORK, 0, 0, 0, 0)
 }
 
-pub fn exec(path : String)->usize {
+/// devices 为授予新进程的设备掩码，见 MmioDevice::bit，失败返回 -1
+pub fn exec(path : String, devices : usize)->usize {
     let mut c = Vec::<char>::new();
     for ch in path.as_bytes() {
         c.push(*ch as char);
     }
     let addr = c.as_slice() as *const [char] as *const char as usize;
-    syscall(EXEC, addr, path.len(), 0, 0)
+    syscall(EXEC, addr, path.len(), 0, devices)
 }
 
 pub fn list_thread(){
     syscall(PRINT_TASK, 0, 0, 0, 0);
 }
 #[allow(dead_code)]
-pub fn branch(func : usize, a0 : usize, a1 : usize)->usize {
-    syscall(BRANCH, func, a0, a1, 0)
+pub fn branch(func : usize, a0 : usize, stack_size : usize)->usize {
+    syscall(BRANCH, func, a0, stack_size, 0)
 }
 
 pub fn wait(target: usize) {
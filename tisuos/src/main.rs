@@ -30,11 +30,13 @@ extern "C" fn kernel_init(){
     trap::init(0);
     strap::init(0);
     memory::init();
+    memory::kernel_space::init();
     // memory::test();
     plic::init();
     task::init();
     input_buffer::init();
     virtio::init();
+    net::init();
 
     process::start_init_process();
 }
@@ -60,6 +62,7 @@ mod graphic;
 mod panic;
 mod desktop;
 mod rtc;
+mod net;
 use interrupt::{strap, trap};
 use task::process;
 use tisu_sync::SpinMutex;
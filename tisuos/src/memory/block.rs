@@ -13,12 +13,17 @@ pub struct Block<T>{
 #[allow(dead_code)]
 impl<T1:Copy> Block<T1> {
     pub fn new(size : usize)->Block<T1>{
-        let addr = get_manager().alloc_memory(
-            size * size_of::<T1>(), true).unwrap() as *mut T1;
-        Block {
+        Self::try_new(size).unwrap()
+    }
+
+    /// 内存不足时返回 None
+    pub fn try_new(size : usize)->Option<Block<T1>>{
+        let addr = alloc_kernel_memory(
+            size * size_of::<T1>())? as *mut T1;
+        Some(Block {
             addr,
             size,
-        }
+        })
     }
 
     pub fn get(&self, idx : usize)->Option<T1>{
@@ -111,4 +116,4 @@ impl<T> Drop for Block<T>{
 use core::{cmp::min, mem::size_of, ptr::slice_from_raw_parts};
 use tisu_memory::MemoryOp;
 
-use super::get_manager;
\ No newline at end of file
+use super::{get_manager, oom::alloc_kernel_memory};
\ No newline at end of file
@@ -19,6 +19,10 @@ extern "C" {
     pub static MEMORY_START: usize;
 	pub static MEMORY_END: usize;
 	pub static TRAP_STACK_END : usize;
+	pub static TRAMPOLINE_START : usize;
+	pub static TRAMPOLINE_END : usize;
+	pub static USER_FUNC_START : usize;
+	pub static USER_FUNC_END : usize;
 }
 
 pub const PAGE_SIZE : usize = 4096;
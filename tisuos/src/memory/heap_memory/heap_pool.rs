@@ -5,7 +5,7 @@
 #![allow(dead_code)]
 
 use tisu_memory::MemoryOp;
-use crate::memory::{block::Block, config::PAGE_SIZE, get_manager, map::SATP};
+use crate::{libs::cpu::flush_tlb, memory::{MemoryError, block::Block, config::PAGE_SIZE, get_manager, map::SATP, swap_memory}};
 
 /// 16 KB 内使用内存池
 pub const MAX_BLOCK_SIZE : usize = 1024 * 16;
@@ -24,6 +24,8 @@ pub struct HeapPool {
     block_num : usize,
     inner : Block<bool>,
     is_kernel : bool,
+    /// 被换出时记录所在的起始槽位，此时 physic_addr 无效
+    swap_slot : Option<usize>,
 }
 
 fn align(n : usize)->usize {
@@ -35,23 +37,25 @@ fn align(n : usize)->usize {
 }
 
 impl HeapPool {
-    pub fn new(virtual_addr : usize, block_size : usize, is_kernel : bool)->Self {
+    /// 物理页面不足时返回错误，由调用者决定如何处理
+    pub fn new(virtual_addr : usize, block_size : usize, is_kernel : bool)->Result<Self, MemoryError> {
         let block_size = align(block_size);
         let size;
         if block_size <= MAX_BLOCK_SIZE { size = block_size * POOL_RATE; }
         else { size = block_size; }
         let page_num = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let block_num = PAGE_SIZE * page_num / block_size;
+        let inner = Block::try_new(block_num).ok_or(MemoryError::OutOfMemory)?;
         let physic_addr;
         if is_kernel {
-            physic_addr = get_manager().kernel_page(page_num).unwrap();
+            physic_addr = get_manager().kernel_page(page_num);
         }
         else {
-            physic_addr = get_manager().user_page(page_num).unwrap();
+            physic_addr = get_manager().user_page(page_num);
         }
-        let block_num = PAGE_SIZE * page_num / block_size;
-        let inner = Block::new(block_num);
+        let physic_addr = physic_addr.ok_or(MemoryError::OutOfMemory)?;
         let total_size = PAGE_SIZE * page_num;
-        Self {
+        Ok(Self {
             physic_addr : physic_addr as usize,
             virtual_base : virtual_addr,
             virtual_top : total_size + virtual_addr,
@@ -62,8 +66,9 @@ impl HeapPool {
             use_num : 0,
             block_num,
             inner,
-            is_kernel
-        }
+            is_kernel,
+            swap_slot : None,
+        })
     }
 
     /// 给出虚拟地址
@@ -102,6 +107,10 @@ impl HeapPool {
         }
     }
 
+    pub fn page_num(&self)->usize {
+        self.page_num
+    }
+
     pub fn full(&self)->bool {
         self.use_num == self.block_num
     }
@@ -111,13 +120,67 @@ impl HeapPool {
     }
 
     pub fn virt_to_phy(&self, va : usize)->usize {
+        assert!(!self.is_swapped());
         self.physic_addr + va - self.virtual_base
     }
+
+    pub fn is_swapped(&self)->bool {
+        self.swap_slot.is_some()
+    }
+
+    /// ## 检查访问位
+    /// 任意页面被访问过即返回 true，同时清除所有页面的访问位
+    /// 清除后刷新 TLB，否则缓存的表项不会再次设置访问位
+    pub fn test_and_clear_accessed(&self, satp : &SATP)->bool {
+        let mut rt = false;
+        for i in 0..self.page_num {
+            if satp.test_and_clear_accessed(self.virtual_base + i * PAGE_SIZE) {
+                rt = true;
+            }
+        }
+        flush_tlb();
+        rt
+    }
+
+    /// ## 换出
+    /// 写入交换区后取消映射并释放物理页面，交换区不足时返回错误
+    pub fn swap_out(&mut self, satp : &SATP)->Result<(), ()> {
+        if self.is_kernel || self.is_swapped() {
+            return Err(());
+        }
+        let slot = swap_memory::swap_out(self.physic_addr, self.page_num).ok_or(())?;
+        for i in 0..self.page_num {
+            satp.unmap(self.virtual_base + i * PAGE_SIZE);
+        }
+        flush_tlb();
+        get_manager().free_page(self.physic_addr as *mut u8);
+        self.physic_addr = 0;
+        self.swap_slot = Some(slot);
+        Ok(())
+    }
+
+    /// 重新申请物理页面，读回内容并映射
+    pub fn swap_in(&mut self, satp : &SATP)->Result<(), MemoryError> {
+        if let Some(slot) = self.swap_slot {
+            let physic_addr = get_manager().user_page(self.page_num)
+                .ok_or(MemoryError::OutOfMemory)? as usize;
+            swap_memory::swap_in(slot, physic_addr, self.page_num);
+            self.physic_addr = physic_addr;
+            self.swap_slot = None;
+            self.map(satp);
+        }
+        Ok(())
+    }
 }
 
 
 impl Drop for HeapPool {
     fn drop(&mut self) {
-        get_manager().free_page(self.physic_addr as *mut u8);
+        if let Some(slot) = self.swap_slot {
+            swap_memory::release(slot, self.page_num);
+        }
+        else {
+            get_manager().free_page(self.physic_addr as *mut u8);
+        }
     }
 }
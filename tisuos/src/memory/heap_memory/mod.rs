@@ -1,4 +1,5 @@
 mod task_heap;
 mod heap_pool;
 
-pub use task_heap::TaskHeap;
\ No newline at end of file
+pub use task_heap::TaskHeap;
+pub use heap_pool::{MAX_BLOCK_SIZE, POOL_RATE};
\ No newline at end of file
@@ -6,7 +6,7 @@
 
 use alloc::prelude::v1::*;
 
-use crate::{memory::map::SATP, task::process::MAX_HEAP_SIZE};
+use crate::{memory::{MemoryError, map::SATP}, task::process::MAX_HEAP_SIZE};
 
 use super::heap_pool::HeapPool;
 
@@ -33,18 +33,20 @@ impl TaskHeap {
         }
     }
 
-    pub fn alloc(&mut self, size : usize, satp : &SATP)->(usize, usize) {
+    /// 返回（虚拟地址，物理地址），页面不足或超出堆范围时返回错误
+    pub fn alloc(&mut self, size : usize, satp : &SATP)->Result<(usize, usize), MemoryError> {
         if let Some(pool) = self.memory_area.iter_mut().find(|pool| {
             pool.block_size >= size && !pool.full()
         }) {
-            pool.alloc().expect("task heap pool alloc err")
+            pool.swap_in(satp)?;
+            pool.alloc().ok_or(MemoryError::OutOfMemory)
         }
         else {
-            self.expand(size, satp);
+            self.expand(size, satp)?;
             let pool = self.memory_area.iter_mut().find(|pool| {
                 pool.block_size >= size && !pool.full()
-            }).expect("heap pool find err");
-            pool.alloc().expect("task heap pool alloc err after expand")
+            }).ok_or(MemoryError::OutOfMemory)?;
+            pool.alloc().ok_or(MemoryError::OutOfMemory)
         }
     }
 
@@ -58,23 +60,70 @@ impl TaskHeap {
         pool.unwrap().free(va);
     }
 
-    pub fn virt_to_phy(&self, va : usize)->usize {
-        let pool = self.memory_area.iter().find(|pool| {
+    /// 内核要访问被换出的内存时先换回
+    pub fn virt_to_phy(&mut self, va : usize, satp : &SATP)->Result<usize, MemoryError> {
+        let pool = self.memory_area.iter_mut().find(|pool| {
             pool.contain(va)
         }).unwrap();
-        pool.virt_to_phy(va)
+        pool.swap_in(satp)?;
+        Ok(pool.virt_to_phy(va))
+    }
+
+    /// 堆实际占用的物理页面数，不包括已换出的
+    pub fn page_num(&self)->usize {
+        self.memory_area.iter().filter(|pool| !pool.is_swapped())
+            .map(|pool| pool.page_num()).sum()
+    }
+
+    /// ## 缺页时换入
+    /// 地址不属于已换出的内存池时返回 Ok(false)
+    pub fn swap_in(&mut self, va : usize, satp : &SATP)->Result<bool, MemoryError> {
+        if let Some(pool) = self.memory_area.iter_mut().find(|pool| {
+            pool.contain(va) && pool.is_swapped()
+        }) {
+            pool.swap_in(satp)?;
+            Ok(true)
+        }
+        else {
+            Ok(false)
+        }
     }
 
-    fn expand(&mut self, size : usize, satp : &SATP) {
+    /// ## 时钟算法回收
+    /// 从 hand 处开始扫描内存池，被访问过的清除访问位，未被访问的换出
+    /// 返回换出的页面数与下一次扫描的位置
+    pub fn reclaim(&mut self, hand : usize, page_num : usize, satp : &SATP)->(usize, usize) {
+        let len = self.memory_area.len();
+        let mut freed = 0;
+        let mut idx = hand;
+        for _ in 0..len * 2 {
+            if freed >= page_num {
+                break;
+            }
+            idx %= len;
+            let pool = &mut self.memory_area[idx];
+            if !pool.is_swapped() && !pool.test_and_clear_accessed(satp)
+                && pool.swap_out(satp).is_ok() {
+                freed += pool.page_num();
+            }
+            idx += 1;
+        }
+        (freed, idx)
+    }
+
+    fn expand(&mut self, size : usize, satp : &SATP)->Result<(), MemoryError> {
         let pool = HeapPool::new(
-            self.virtual_heap_top, size, self.is_kernel);
+            self.virtual_heap_top, size, self.is_kernel)?;
+        if self.virtual_heap_top + pool.total_size - self.virtual_heap_start > MAX_HEAP_SIZE {
+            return Err(MemoryError::ExceedLimit);
+        }
         pool.map(satp);
         self.virtual_heap_top += pool.total_size;
         self.memory_area.push(pool);
         self.memory_area.sort_by(|a, b| {
             a.block_size.cmp(&b.block_size)
         });
-        assert!(self.virtual_heap_top - self.virtual_heap_start <= MAX_HEAP_SIZE);
+        Ok(())
     }
 }
 
@@ -0,0 +1,32 @@
+//! # 内核地址空间
+//! 陷入处理时使用的内核页表，恒等映射内核代码、全部内存以及内核用到的设备
+//! 用户进程的页表只包含自身区域与跳板页面，陷入后由跳板切换到这里
+//!
+//! 2021年6月8日 zg
+
+use super::{Area, MmioDevice, ProgramArea, map::SATP};
+
+/// 跳板代码中读取，值为 0 时陷入处理以关闭地址转换的方式运行
+#[no_mangle]
+pub static mut KERNEL_SATP : usize = 0;
+
+/// 在内存管理初始化之后调用
+pub fn init() {
+    let satp = SATP::new();
+    let mut program = ProgramArea::new(0, true);
+    program.push_area(Area::kernel_code());
+    program.push_area(Area::kernel_memory());
+    program.grant_device(MmioDevice::Virtio);
+    program.grant_device(MmioDevice::Timer);
+    program.grant_device(MmioDevice::Rtc);
+    program.grant_device(MmioDevice::Test);
+    program.grant_device(MmioDevice::Plic);
+    program.map(&satp);
+    unsafe {
+        KERNEL_SATP = satp.val();
+    }
+}
+
+pub fn kernel_satp()->usize {
+    unsafe {KERNEL_SATP}
+}
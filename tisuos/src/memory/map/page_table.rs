@@ -32,6 +32,12 @@ impl PTE {
     pub fn set_valid(&mut self){
         self.flag |= PageBit::Valid.val();
     }
+    pub fn is_accessed(&self)->bool {
+        self.flag & PageBit::Access.val() != 0
+    }
+    pub fn clear_accessed(&mut self) {
+        self.flag &= !PageBit::Access.val();
+    }
 }
 
 
@@ -95,6 +101,33 @@ impl PageTable {
         (pte_final.flag as usize >> 10) << 12
     }
 
+    /// 查找叶子页表项，中间页表不存在时返回 None
+    pub fn find_pte(&mut self, virtual_addr : usize)->Option<&mut PTE> {
+        let vpn = [
+            (virtual_addr >> 30) & 0x1ff,
+            (virtual_addr >> 21) & 0x1ff,
+            (virtual_addr >> 12) & 0x1ff
+        ];
+        let pte_first = &self.entry[vpn[0]];
+        if !pte_first.is_valid() {
+            return None;
+        }
+        let table_mid = unsafe {&mut *(pte_first.get_ppn() as *mut Self)};
+        let pte_mid = &table_mid.entry[vpn[1]];
+        if !pte_mid.is_valid() {
+            return None;
+        }
+        let table_final = unsafe {&mut *(pte_mid.get_ppn() as *mut Self)};
+        Some(&mut table_final.entry[vpn[2]])
+    }
+
+    /// 取消映射，中间页表保留
+    pub fn unmap(&mut self, virtual_addr : usize) {
+        if let Some(pte) = self.find_pte(virtual_addr) {
+            pte.flag = 0;
+        }
+    }
+
     fn map(&mut self, virtual_addr : usize, physic_addr : usize, flag : u64){
         let vpn = [
             (virtual_addr >> 30) & 0x1ff,
@@ -95,6 +95,22 @@ impl SATP {
         }
     }
 
+    pub fn unmap(&self, va:usize) {
+        self.get_page_table().unmap(va);
+    }
+
+    /// 读取并清除访问位，用于页面置换
+    pub fn test_and_clear_accessed(&self, va:usize)->bool {
+        if let Some(pte) = self.get_page_table().find_pte(va) {
+            let rt = pte.is_valid() && pte.is_accessed();
+            pte.clear_accessed();
+            rt
+        }
+        else {
+            false
+        }
+    }
+
     pub fn free_page_table(&self){
         if self.is_map(){
             let pt = self.get_page_table();
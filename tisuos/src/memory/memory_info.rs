@@ -0,0 +1,12 @@
+//! # 内存错误信息
+//! 页面、堆内存申请失败时向上层返回的错误
+//!
+//! 2021年6月2日 zg
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryError {
+    /// 物理页面耗尽
+    OutOfMemory,
+    /// 超出进程允许的虚拟地址范围
+    ExceedLimit,
+}
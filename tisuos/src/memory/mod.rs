@@ -9,15 +9,21 @@ use self::{
 	config::{HEAP_START, KERNEL_PAGE_NUM, MEMORY_END, PAGE_SIZE},
 };
 use tisu_memory::{MemoryOp, PageManager, Heap};
-use core::alloc::{GlobalAlloc, Layout};
+use core::{alloc::{GlobalAlloc, Layout}, ptr::null_mut};
 
 pub mod block;
 pub mod heap_memory;
+pub mod kernel_space;
 pub mod config;
 pub mod map;
+pub mod oom;
+pub mod slab_memory;
+pub mod swap_memory;
+mod memory_info;
 mod program_memory;
 mod stack_memory;
 
+pub use memory_info::*;
 pub use program_memory::*;
 pub use stack_memory::*;
 
@@ -72,18 +78,33 @@ pub fn print() {
 
 struct OSGlobalAlloc;
 unsafe impl GlobalAlloc for OSGlobalAlloc {
+    /// 小对象使用 slab 缓存，释放时同样根据 layout 区分，所以失败时不能退回内存管理器
     unsafe fn alloc(&self, layout : Layout) -> *mut u8{
-        get_manager().alloc_memory(layout.size(), true).unwrap()
+        let addr = if layout.size().max(layout.align()) <= slab_memory::MAX_OBJECT_SIZE {
+            slab_memory::alloc(&layout)
+        }
+        else {
+            oom::alloc_kernel_memory(layout.size())
+        };
+        if let Some(addr) = addr {
+            addr
+        }
+        else {
+            null_mut()
+        }
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        get_manager().free_memory(ptr);
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !slab_memory::free(ptr, &layout) {
+            get_manager().free_memory(ptr);
+        }
     }
 }
 
 #[global_allocator]
 static GA: OSGlobalAlloc = OSGlobalAlloc{};
 
+/// 内核申请失败，当前核能结束进程时已经结束过，持有任务池的锁时只记录下来
 #[alloc_error_handler]
 pub fn alloc_error(layout : Layout) -> !{
     panic!("Fail to alloc {} bytes with {} bytes alignment", layout.size(), layout.align());
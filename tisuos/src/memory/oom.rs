@@ -0,0 +1,85 @@
+//! # 内存耗尽处理
+//! 内核自身的内存申请失败时，如果当前核没有持有任务池的锁，就地结束占用页面最多的用户进程后重试
+//! 申请可能发生在持有任务池锁的时候（例如扩展堆、栈），此时不能结束进程，只记录缺少的内存，返回失败
+//! 调度时不持有任务池的锁，在那里处理记录下的内存耗尽，之后的申请可以成功
+//!
+//! 2021年6月2日 zg
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tisu_memory::MemoryOp;
+use crate::{interrupt::strap::current_hartid, task::get_task_mgr};
+use super::{get_manager, slab_memory::MAX_HART};
+
+/// 申请失败时缺少的字节数，0 表示没有待处理的内存耗尽
+static PENDING : AtomicUsize = AtomicUsize::new(0);
+/// 每个核持有任务池锁的层数
+static HOLDING : [AtomicUsize;MAX_HART] = [
+    AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0),
+];
+/// 每个核是否正在结束进程，结束进程时的申请失败不再重入
+static KILLING : [AtomicBool;MAX_HART] = [
+    AtomicBool::new(false), AtomicBool::new(false), AtomicBool::new(false), AtomicBool::new(false),
+];
+
+/// ## 申请内核内存
+/// 失败时尽量结束受害进程后重试，不能结束时记录下来，等待调度时处理
+pub fn alloc_kernel_memory(size : usize)->Option<*mut u8> {
+    retry(size, || get_manager().alloc_memory(size, true))
+}
+
+/// ## 申请内核页面
+/// 与 alloc_kernel_memory 相同
+pub fn alloc_kernel_page(num : usize)->Option<*mut u8> {
+    retry(num * super::config::PAGE_SIZE, || get_manager().kernel_page(num))
+}
+
+/// 加上任务池的锁之前调用
+pub fn hold() {
+    HOLDING[current_hartid() % MAX_HART].fetch_add(1, Ordering::SeqCst);
+}
+
+/// 释放任务池的锁之后调用
+pub fn release() {
+    HOLDING[current_hartid() % MAX_HART].fetch_sub(1, Ordering::SeqCst);
+}
+
+fn retry<F : Fn()->Option<*mut u8>>(size : usize, f : F)->Option<*mut u8> {
+    loop {
+        if let Some(addr) = f() {
+            return Some(addr);
+        }
+        if !kill(size) {
+            record(size);
+            return None;
+        }
+    }
+}
+
+/// 当前核持有任务池的锁或者已经在结束进程时返回 false，否则结束一个进程，返回是否成功
+fn kill(size : usize)->bool {
+    let hart = current_hartid() % MAX_HART;
+    if HOLDING[hart].load(Ordering::SeqCst) > 0 || KILLING[hart].swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let rt = get_task_mgr().map(|mgr| mgr.oom_kill(size)).unwrap_or(false);
+    KILLING[hart].store(false, Ordering::SeqCst);
+    rt
+}
+
+/// 记录一次失败的申请，用户内存换入失败时也从这里交给调度处理
+pub fn record(size : usize) {
+    if size > PENDING.load(Ordering::SeqCst) {
+        PENDING.store(size, Ordering::SeqCst);
+    }
+}
+
+/// 取出待处理的内存耗尽，返回失败的申请中最大的字节数
+pub fn pending()->Option<usize> {
+    let size = PENDING.swap(0, Ordering::SeqCst);
+    if size > 0 {
+        Some(size)
+    }
+    else {
+        None
+    }
+}
@@ -2,19 +2,10 @@
 //!
 //! 2021年4月29日 zg
 
-use core::cmp::{max, min};
-
-use crate::{filesystem::elf::ElfManager, memory::{config::{DATA_START, HEAP_START, KERNEL_PAGE_NUM, MEMORY_START, PAGE_SIZE, RODATA_END}, get_manager, map::SATP}};
+use crate::{filesystem::elf::ElfManager, memory::{MemoryError, config::{DATA_START, HEAP_START, KERNEL_PAGE_NUM, MEMORY_END, MEMORY_START, PAGE_SIZE, RODATA_END, TRAMPOLINE_END, TRAMPOLINE_START, USER_FUNC_END, USER_FUNC_START}, get_manager, map::SATP, oom::alloc_kernel_page}};
 use alloc::prelude::v1::*;
 use tisu_memory::MemoryOp;
 
-extern "C" {
-    fn thread_exit();
-    fn process_exit();
-    fn waiting();
-    fn s_trap_vector();
-}
-
 #[derive(Debug)]
 pub struct Area {
     vst : usize,
@@ -24,6 +15,61 @@ pub struct Area {
     atype : AreaType,
 }
 
+/// ## 内存映射设备
+/// 进程只有被授予设备后才会映射对应的 MMIO 区域，见 ProgramArea::grant_device
+/// 用户进程由 exec 的设备掩码授予，只能授予调用者自己拥有的设备
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MmioDevice {
+    /// 串口与 virtio 设备
+    Virtio,
+    Timer,
+    Rtc,
+    Test,
+    Plic,
+}
+
+impl MmioDevice {
+    /// 设备在授权掩码中对应的位
+    pub fn bit(&self)->usize {
+        1 << (*self as usize)
+    }
+
+    pub fn parse(name : &str)->Option<Self> {
+        match name {
+            "virtio" => Some(MmioDevice::Virtio),
+            "timer" => Some(MmioDevice::Timer),
+            "rtc" => Some(MmioDevice::Rtc),
+            "test" => Some(MmioDevice::Test),
+            "plic" => Some(MmioDevice::Plic),
+            _ => None,
+        }
+    }
+
+    /// 掩码中包含的设备，未知的位被忽略
+    pub fn from_mask(mask : usize)->Vec<Self> {
+        [MmioDevice::Virtio, MmioDevice::Timer, MmioDevice::Rtc, MmioDevice::Test, MmioDevice::Plic]
+            .iter().filter(|device| mask & device.bit() != 0).copied().collect()
+    }
+
+    pub fn area(&self)->Area {
+        let (st, ed) = match self {
+            MmioDevice::Virtio => (0x1000_0000, 0x1000_9000),
+            MmioDevice::Timer => (0x200_0000, 0x200_C000),
+            MmioDevice::Rtc => (crate::rtc::BASE_ADDR / PAGE_SIZE * PAGE_SIZE,
+                (crate::rtc::BASE_ADDR + PAGE_SIZE) / PAGE_SIZE * PAGE_SIZE),
+            MmioDevice::Test => (0x10_0000, 0x10_1000),
+            MmioDevice::Plic => (0x0c00_0000, 0x0c21_0000),
+        };
+        Area {
+            vst : st,
+            ved : ed,
+            pst : st,
+            ped : ed,
+            atype : AreaType::Data,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AreaType {
     Code,
@@ -70,60 +116,23 @@ impl Area {
         }
     }
 
-    pub fn virtio_area()->Self {
-        let vst = 0x1000_0000;
-        let ved = 0x1000_9000;
+    /// 内核数据之后的全部内存，只用于内核页表
+    pub fn kernel_memory()->Self {
+        let vst = unsafe {DATA_START} / PAGE_SIZE * PAGE_SIZE;
+        let ved = unsafe {MEMORY_END} / PAGE_SIZE * PAGE_SIZE;
         Self {
             vst,
             ved,
             pst : vst,
-            ped:ved,
-            atype : AreaType::Data
-        }
-    }
-
-    pub fn rtc_area()->Self {
-        let st = crate::rtc::BASE_ADDR / PAGE_SIZE * PAGE_SIZE;
-        let ed = (crate::rtc::BASE_ADDR + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
-        Self {
-            vst : st,
-            pst : st,
-            ved : ed,
-            ped : ed,
-            atype : AreaType::Data,
-        }
-    }
-
-    pub fn test_area()->Self {
-        Self {
-            vst : 0x10_0000,
-            ved : 0x10_1000,
-            ped : 0x10_1000,
-            pst : 0x10_0000,
-            atype : AreaType::Data,
-        }
-    }
-
-    pub fn timer_area()->Self {
-        let vst = 0x200_0000;
-        let ved = 0x200_C000;
-        Self {
-            vst,
-            ved,
-            pst : vst,
-            ped:ved,
+            ped : ved,
             atype : AreaType::Data
         }
     }
 
-    /// 内核中用户程序会用到的函数
+    /// 内核中用户程序会用到的函数，链接时单独放在 .text.user_func 页面中
     pub fn user_func()->Self {
-        let st = min(process_exit as usize, thread_exit as usize);
-        let st = min(st, waiting as usize);
-        let ed = max(process_exit as usize, thread_exit as usize);
-        let ed = max(ed, waiting as usize);
-        let ed = (ed + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
-        let st = st / PAGE_SIZE * PAGE_SIZE;
+        let st = unsafe {USER_FUNC_START};
+        let ed = unsafe {USER_FUNC_END};
         Self {
             vst:st,
             ved:ed,
@@ -145,6 +154,8 @@ impl Area {
 pub struct ProgramArea {
     entry : usize,
     area : Vec<Area>,
+    /// 已授予的设备，MmioDevice::bit 的掩码
+    devices : usize,
     pub is_kernel : bool
 }
 
@@ -153,6 +164,7 @@ impl ProgramArea {
         Self {
             entry,
             area : Vec::new(),
+            devices : 0,
             is_kernel
         }
     }
@@ -165,7 +177,21 @@ impl ProgramArea {
         self.area.push(area);
     }
 
-    pub fn push_elf(&mut self, elf : &mut ElfManager) {
+    /// 授予进程访问设备的权限，映射时加入对应的 MMIO 区域，已授予的设备不重复加入
+    pub fn grant_device(&mut self, device : MmioDevice) {
+        if self.devices & device.bit() != 0 {
+            return;
+        }
+        self.devices |= device.bit();
+        self.push_area(device.area());
+    }
+
+    pub fn devices(&self)->usize {
+        self.devices
+    }
+
+    /// 为可加载段申请页面并拷贝，页面不足时返回错误，已申请的页面随 ProgramArea 释放
+    pub fn push_elf(&mut self, elf : &mut ElfManager)->Result<(), MemoryError> {
         elf.reset();
         let mgr = get_manager();
         while let Some(ph) = elf.next_ph() {
@@ -175,8 +201,8 @@ impl ProgramArea {
             let va = ph.va();
             let offset = ph.va() % PAGE_SIZE;
             let num = (ph.size() + offset + PAGE_SIZE - 1) / PAGE_SIZE;
-            let pa = if self.is_kernel{mgr.kernel_page(num)}else{mgr.user_page(num)};
-            let pa = pa.unwrap();
+            let pa = if self.is_kernel{alloc_kernel_page(num)}else{mgr.user_page(num)};
+            let pa = pa.ok_or(MemoryError::OutOfMemory)?;
             unsafe {pa.add(offset).copy_from(elf.get_addr(ph.offset()), ph.size())}
             let pa = pa as usize;
             let vst = va;
@@ -188,12 +214,20 @@ impl ProgramArea {
         if self.is_kernel {
             self.push_area(Area::kernel_code());
             self.push_area(Area::kernel_data());
-            self.push_area(Area::virtio_area());
-            self.push_area(Area::timer_area());
+            self.grant_device(MmioDevice::Virtio);
+            self.grant_device(MmioDevice::Timer);
         }
         else {
             self.push_area(Area::user_func());
         }
+        Ok(())
+    }
+
+    /// 程序实际申请的物理页面数，不包括直接映射的内核区域
+    pub fn page_num(&self)->usize {
+        self.area.iter().filter(|area| {
+            area.pst >= unsafe {HEAP_START}
+        }).map(|area| (area.ped - area.pst) / PAGE_SIZE).sum()
     }
 
     pub fn map(&self, satp : &SATP) {
@@ -212,9 +246,14 @@ impl ProgramArea {
         }
     }
 
+    /// ## 映射跳板页面
+    /// 只有内核可以访问，用户进程页表中除此之外没有任何内核页面
     pub fn map_kernel_trap(&self, satp : &SATP) {
-        let vst = s_trap_vector as usize;
-        satp.map_code(vst, vst, true);
+        let mut va = unsafe {TRAMPOLINE_START};
+        while va < unsafe {TRAMPOLINE_END} {
+            satp.map_code(va, va, true);
+            va += PAGE_SIZE;
+        }
     }
 
     pub fn virt_to_phy(&self, va:usize)->usize {
@@ -0,0 +1,71 @@
+//! # 内核对象缓存
+//! 在页面管理之上为小对象建立按大小分级的 slab 缓存
+//! 内核 GlobalAlloc 中不超过 MAX_OBJECT_SIZE 的申请走这里，更大的仍交给内存管理器
+//!
+//! 2021年6月5日 zg
+
+mod slab;
+mod object_cache;
+
+pub use object_cache::{MAX_HART, SlabInfo};
+
+use core::alloc::Layout;
+use alloc::prelude::v1::*;
+use crate::interrupt::strap::current_hartid;
+use self::object_cache::ObjectCache;
+
+pub const MIN_OBJECT_SIZE : usize = 16;
+pub const MAX_OBJECT_SIZE : usize = 1024;
+const CLASS_NUM : usize = 7;
+
+static mut CACHES : [ObjectCache;CLASS_NUM] = [
+    ObjectCache::new(16),
+    ObjectCache::new(32),
+    ObjectCache::new(64),
+    ObjectCache::new(128),
+    ObjectCache::new(256),
+    ObjectCache::new(512),
+    ObjectCache::new(1024),
+];
+
+/// 大小与对齐取较大者，向上取 2 的幂
+fn class_idx(layout : &Layout)->Option<usize> {
+    let size = layout.size().max(layout.align());
+    if size > MAX_OBJECT_SIZE {
+        return None;
+    }
+    let mut idx = 0;
+    let mut class_size = MIN_OBJECT_SIZE;
+    while class_size < size {
+        class_size <<= 1;
+        idx += 1;
+    }
+    Some(idx)
+}
+
+/// 对象大小超出范围或者页面耗尽时返回 None
+pub fn alloc(layout : &Layout)->Option<*mut u8> {
+    let idx = class_idx(layout)?;
+    unsafe {
+        CACHES[idx].alloc(current_hartid() % MAX_HART)
+    }
+}
+
+/// 对象来自 slab 时返回 true
+pub fn free(ptr : *mut u8, layout : &Layout)->bool {
+    if let Some(idx) = class_idx(layout) {
+        unsafe {
+            CACHES[idx].free(current_hartid() % MAX_HART, ptr);
+        }
+        true
+    }
+    else {
+        false
+    }
+}
+
+pub fn statistics()->Vec<SlabInfo> {
+    unsafe {
+        CACHES.iter().map(|cache| cache.info()).collect()
+    }
+}
@@ -0,0 +1,241 @@
+//! # 对象缓存
+//! 同一大小对象的 slab 集合，外加每个核心的空闲链表
+//! 核心优先从自己的链表取放对象，链表为空时从 slab 批量取出，过长时批量归还
+//!
+//! 2021年6月5日 zg
+
+use core::ptr::null_mut;
+use tisu_memory::MemoryOp;
+use tisu_sync::SpinMutex;
+use crate::memory::{config::PAGE_SIZE, get_manager, oom::alloc_kernel_page};
+use super::slab::{FreeObject, Slab};
+
+pub const MAX_HART : usize = 4;
+/// 每次与 slab 交换的对象数
+const BATCH : usize = 16;
+/// 核心链表超过此长度时归还一批
+const MAX_LOCAL : usize = BATCH * 2;
+
+/// 核心私有的空闲链表
+#[derive(Clone, Copy)]
+struct HartList {
+    head : *mut FreeObject,
+    count : usize,
+}
+
+/// ## 缓存统计信息
+/// fragmentation 为持有页面中未交给使用者的比例（百分比）
+#[derive(Debug, Clone, Copy)]
+pub struct SlabInfo {
+    pub object_size : usize,
+    pub in_use : usize,
+    pub cached : usize,
+    pub page_num : usize,
+    pub fragmentation : usize,
+}
+
+pub struct ObjectCache {
+    pub object_size : usize,
+    /// 还有空闲对象的 slab，已满的 slab 不在链表中
+    partial : *mut Slab,
+    slab_num : usize,
+    /// 从 slab 中取出的对象数，包括停留在核心链表中的
+    in_use : usize,
+    mutex : SpinMutex,
+    hart : [HartList;MAX_HART],
+    hart_mutex : [SpinMutex;MAX_HART],
+}
+
+impl ObjectCache {
+    pub const fn new(object_size : usize)->Self {
+        Self {
+            object_size,
+            partial : null_mut(),
+            slab_num : 0,
+            in_use : 0,
+            mutex : SpinMutex::new(),
+            hart : [HartList{head : null_mut(), count : 0};MAX_HART],
+            hart_mutex : [SpinMutex::new(), SpinMutex::new(), SpinMutex::new(), SpinMutex::new()],
+        }
+    }
+
+    pub fn alloc(&mut self, hartid : usize)->Option<*mut u8> {
+        loop {
+            if let Some(obj) = self.pop_local(hartid) {
+                return Some(obj);
+            }
+            let (list, num) = self.take(BATCH);
+            if num == 0 {
+                if !self.grow() {
+                    return None;
+                }
+                continue;
+            }
+            self.push_local(hartid, list, num);
+        }
+    }
+
+    pub fn free(&mut self, hartid : usize, ptr : *mut u8) {
+        let obj = ptr as *mut FreeObject;
+        unsafe {(*obj).next = null_mut();}
+        if self.push_local(hartid, obj, 1) > MAX_LOCAL {
+            let (list, num) = self.drain_local(hartid, BATCH);
+            self.give_back(list, num);
+        }
+    }
+
+    pub fn info(&self)->SlabInfo {
+        let cached : usize = self.hart.iter().map(|list| list.count).sum();
+        let in_use = self.in_use - cached;
+        let total = self.slab_num * PAGE_SIZE;
+        let fragmentation = if total == 0 {
+            0
+        }
+        else {
+            (total - in_use * self.object_size) * 100 / total
+        };
+        SlabInfo {
+            object_size : self.object_size,
+            in_use,
+            cached,
+            page_num : self.slab_num,
+            fragmentation,
+        }
+    }
+
+    fn pop_local(&mut self, hartid : usize)->Option<*mut u8> {
+        self.hart_mutex[hartid].lock_no_int();
+        let list = &mut self.hart[hartid];
+        let rt = if list.head.is_null() {
+            None
+        }
+        else {
+            let obj = list.head;
+            list.head = unsafe {(*obj).next};
+            list.count -= 1;
+            Some(obj as *mut u8)
+        };
+        self.hart_mutex[hartid].unlock_no_int();
+        rt
+    }
+
+    /// 把一条以空指针结尾的链表接到核心链表前面，返回链表长度
+    fn push_local(&mut self, hartid : usize, head : *mut FreeObject, num : usize)->usize {
+        let mut tail = head;
+        unsafe {
+            while !(*tail).next.is_null() {
+                tail = (*tail).next;
+            }
+        }
+        self.hart_mutex[hartid].lock_no_int();
+        let list = &mut self.hart[hartid];
+        unsafe {(*tail).next = list.head;}
+        list.head = head;
+        list.count += num;
+        let rt = list.count;
+        self.hart_mutex[hartid].unlock_no_int();
+        rt
+    }
+
+    fn drain_local(&mut self, hartid : usize, num : usize)->(*mut FreeObject, usize) {
+        self.hart_mutex[hartid].lock_no_int();
+        let list = &mut self.hart[hartid];
+        let head = list.head;
+        let mut tail = null_mut::<FreeObject>();
+        let mut cnt = 0;
+        while cnt < num && !list.head.is_null() {
+            tail = list.head;
+            list.head = unsafe {(*tail).next};
+            cnt += 1;
+        }
+        if !tail.is_null() {
+            unsafe {(*tail).next = null_mut();}
+        }
+        list.count -= cnt;
+        self.hart_mutex[hartid].unlock_no_int();
+        (head, cnt)
+    }
+
+    /// 从 slab 中取出最多 num 个对象
+    fn take(&mut self, num : usize)->(*mut FreeObject, usize) {
+        self.mutex.lock_no_int();
+        let mut head = null_mut::<FreeObject>();
+        let mut cnt = 0;
+        while cnt < num && !self.partial.is_null() {
+            let slab = unsafe {&mut *self.partial};
+            if let Some(obj) = slab.alloc() {
+                let obj = obj as *mut FreeObject;
+                unsafe {(*obj).next = head;}
+                head = obj;
+                cnt += 1;
+            }
+            if slab.is_full() {
+                self.unlink(slab);
+            }
+        }
+        self.in_use += cnt;
+        self.mutex.unlock_no_int();
+        (head, cnt)
+    }
+
+    /// 归还对象，空出来的 slab 只保留一个，其余的页面释放
+    fn give_back(&mut self, head : *mut FreeObject, num : usize) {
+        self.mutex.lock_no_int();
+        let mut obj = head;
+        while !obj.is_null() {
+            let next = unsafe {(*obj).next};
+            let slab = Slab::from_object(obj as *mut u8);
+            let was_full = slab.is_full();
+            slab.free(obj as *mut u8);
+            if was_full {
+                self.link(slab);
+            }
+            if slab.is_empty() && self.partial != slab as *mut Slab {
+                self.unlink(slab);
+                self.slab_num -= 1;
+                get_manager().free_page(slab.page());
+            }
+            obj = next;
+        }
+        self.in_use -= num;
+        self.mutex.unlock_no_int();
+    }
+
+    /// 申请新页面时不持有锁，内存耗尽处理可能会释放对象回到这里
+    fn grow(&mut self)->bool {
+        if let Some(page) = alloc_kernel_page(1) {
+            let slab = Slab::init(page, self.object_size);
+            self.mutex.lock_no_int();
+            self.link(slab);
+            self.slab_num += 1;
+            self.mutex.unlock_no_int();
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn link(&mut self, slab : &mut Slab) {
+        slab.prev = null_mut();
+        slab.next = self.partial;
+        if !self.partial.is_null() {
+            unsafe {(*self.partial).prev = slab;}
+        }
+        self.partial = slab;
+    }
+
+    fn unlink(&mut self, slab : &mut Slab) {
+        if !slab.prev.is_null() {
+            unsafe {(*slab.prev).next = slab.next;}
+        }
+        else {
+            self.partial = slab.next;
+        }
+        if !slab.next.is_null() {
+            unsafe {(*slab.next).prev = slab.prev;}
+        }
+        slab.prev = null_mut();
+        slab.next = null_mut();
+    }
+}
@@ -0,0 +1,84 @@
+//! # Slab
+//! 一个页面切分成若干个同样大小的对象，页面开头存放 slab 头部
+//! 空闲对象本身保存下一个空闲对象的地址，形成链表
+//!
+//! 2021年6月5日 zg
+
+use core::{mem::size_of, ptr::null_mut};
+use crate::memory::config::PAGE_SIZE;
+
+/// 空闲对象，复用对象自身的内存保存链表
+pub struct FreeObject {
+    pub next : *mut FreeObject,
+}
+
+/// ## Slab 头部
+/// 位于页面起始处，通过对象地址按页对齐即可找到
+#[repr(C)]
+pub struct Slab {
+    pub prev : *mut Slab,
+    pub next : *mut Slab,
+    free : *mut FreeObject,
+    pub in_use : usize,
+    pub total : usize,
+    pub object_size : usize,
+}
+
+impl Slab {
+    /// ## 在页面上建立 slab
+    /// 头部占据的空间向上对齐到对象大小，保证对象按自身大小对齐
+    pub fn init(page : *mut u8, object_size : usize)->&'static mut Slab {
+        let head = (size_of::<Slab>() + object_size - 1) / object_size * object_size;
+        let total = (PAGE_SIZE - head) / object_size;
+        let slab = unsafe {&mut *(page as *mut Slab)};
+        slab.prev = null_mut();
+        slab.next = null_mut();
+        slab.free = null_mut();
+        slab.in_use = 0;
+        slab.total = total;
+        slab.object_size = object_size;
+        for i in (0..total).rev() {
+            let obj = (page as usize + head + i * object_size) as *mut FreeObject;
+            unsafe {(*obj).next = slab.free;}
+            slab.free = obj;
+        }
+        slab
+    }
+
+    /// 根据对象地址找到所在的 slab
+    pub fn from_object(ptr : *mut u8)->&'static mut Slab {
+        let page = ptr as usize / PAGE_SIZE * PAGE_SIZE;
+        unsafe {&mut *(page as *mut Slab)}
+    }
+
+    pub fn alloc(&mut self)->Option<*mut u8> {
+        if self.free.is_null() {
+            None
+        }
+        else {
+            let obj = self.free;
+            self.free = unsafe {(*obj).next};
+            self.in_use += 1;
+            Some(obj as *mut u8)
+        }
+    }
+
+    pub fn free(&mut self, ptr : *mut u8) {
+        let obj = ptr as *mut FreeObject;
+        unsafe {(*obj).next = self.free;}
+        self.free = obj;
+        self.in_use -= 1;
+    }
+
+    pub fn is_full(&self)->bool {
+        self.in_use == self.total
+    }
+
+    pub fn is_empty(&self)->bool {
+        self.in_use == 0
+    }
+
+    pub fn page(&mut self)->*mut u8 {
+        self as *mut Slab as *mut u8
+    }
+}
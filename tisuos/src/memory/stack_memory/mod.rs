@@ -1,3 +1,5 @@
 mod task_stack;
+mod stack_space;
 
-pub use task_stack::*;
\ No newline at end of file
+pub use task_stack::*;
+pub use stack_space::StackSpace;
\ No newline at end of file
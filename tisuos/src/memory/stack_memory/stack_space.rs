@@ -0,0 +1,63 @@
+//! # 栈地址空间
+//! 管理进程中所有线程栈的虚拟地址范围，线程退出时回收
+//! 每个栈范围的最低一页作为保护页，永远不映射，栈溢出时必然触发缺页
+//!
+//! 2021年6月7日 zg
+
+use alloc::prelude::v1::*;
+use crate::memory::config::PAGE_SIZE;
+
+/// 空闲的虚拟地址范围，按起始地址排序
+#[derive(Debug, Clone, Copy)]
+struct FreeRange {
+    start : usize,
+    page_num : usize,
+}
+
+pub struct StackSpace {
+    free : Vec<FreeRange>,
+}
+
+impl StackSpace {
+    pub fn new(start : usize, size : usize)->Self {
+        let mut free = Vec::new();
+        free.push(FreeRange{start, page_num : size / PAGE_SIZE});
+        Self {
+            free,
+        }
+    }
+
+    /// ## 申请栈范围
+    /// 额外占用一页保护页，返回栈顶（最高地址），首次适应
+    pub fn alloc(&mut self, page_num : usize)->Option<usize> {
+        let total = page_num + 1;
+        let idx = self.free.iter().position(|range| range.page_num >= total)?;
+        let range = &mut self.free[idx];
+        let start = range.start;
+        range.start += total * PAGE_SIZE;
+        range.page_num -= total;
+        if range.page_num == 0 {
+            self.free.remove(idx);
+        }
+        Some(start + total * PAGE_SIZE)
+    }
+
+    /// 归还栈范围，与相邻的空闲范围合并
+    pub fn free(&mut self, stack_top : usize, page_num : usize) {
+        let total = page_num + 1;
+        let start = stack_top - total * PAGE_SIZE;
+        let idx = self.free.iter().position(|range| range.start > start)
+            .unwrap_or(self.free.len());
+        self.free.insert(idx, FreeRange{start, page_num : total});
+        if idx + 1 < self.free.len() &&
+            self.free[idx].start + self.free[idx].page_num * PAGE_SIZE == self.free[idx + 1].start {
+            self.free[idx].page_num += self.free[idx + 1].page_num;
+            self.free.remove(idx + 1);
+        }
+        if idx > 0 &&
+            self.free[idx - 1].start + self.free[idx - 1].page_num * PAGE_SIZE == self.free[idx].start {
+            self.free[idx - 1].page_num += self.free[idx].page_num;
+            self.free.remove(idx);
+        }
+    }
+}
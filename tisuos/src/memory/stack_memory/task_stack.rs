@@ -6,7 +6,7 @@
 use alloc::prelude::v1::*;
 use tisu_memory::MemoryOp;
 
-use crate::memory::{config::PAGE_SIZE, get_manager, map::SATP};
+use crate::memory::{MemoryError, config::PAGE_SIZE, get_manager, map::SATP};
 
 #[derive(Debug, Clone, Copy)]
 pub struct StackArea {
@@ -41,6 +41,8 @@ pub struct TaskStack {
     stack_bottom : usize,
     /// 栈剩余可使用的页面
     last_page : usize,
+    /// 栈最多可以使用的页面，不包括下方的保护页
+    max_page : usize,
     is_kernel : bool,
     area : Vec<StackArea>,
     tid : usize,
@@ -53,6 +55,7 @@ impl TaskStack {
             stack_bottom : virtual_stack_top,
             is_kernel,
             last_page : max_page,
+            max_page,
             area : Vec::new(),
             tid,
         }
@@ -91,8 +94,34 @@ impl TaskStack {
         }
     }
 
+    /// ## 缺页时扩展栈
+    /// 地址位于保留范围内时至少扩展 min_page 页，并保证覆盖该地址
+    pub fn expand_to(&mut self, va : usize, min_page : usize, satp : &SATP)->Result<(), ()> {
+        if va >= self.stack_bottom || va < self.stack_limit() {
+            return Err(());
+        }
+        let need = (self.stack_bottom - va + PAGE_SIZE - 1) / PAGE_SIZE;
+        let num = need.max(min_page).min(self.last_page);
+        self.expand(num, satp)
+    }
+
+    /// 地址位于保护页时说明栈溢出
+    pub fn is_guard(&self, va : usize)->bool {
+        let limit = self.stack_limit();
+        limit - PAGE_SIZE <= va && va < limit
+    }
+
+    pub fn max_page(&self)->usize {
+        self.max_page
+    }
+
+    /// 栈可以到达的最低地址
+    fn stack_limit(&self)->usize {
+        self.stack_top - self.max_page * PAGE_SIZE
+    }
+
     /// 拷贝另一个栈，包括栈的大小及内容
-    pub fn copy(&mut self, other : &Self, satp : &SATP) {
+    pub fn copy(&mut self, other : &Self, satp : &SATP)->Result<(), MemoryError> {
         let mgr = get_manager();
         self.stack_bottom = self.stack_top - (other.stack_top - other.stack_bottom);
         self.last_page -= (self.stack_top - self.stack_bottom) / PAGE_SIZE;
@@ -102,12 +131,13 @@ impl TaskStack {
             a.vst = vst;
             a.ved = vst + (area.ved - area.vst);
             let num = (area.ved - area.vst) / PAGE_SIZE;
-            if self.is_kernel {
-                a.pst = mgr.kernel_page(num).unwrap() as usize;
+            let pst = if self.is_kernel {
+                mgr.kernel_page(num)
             }
             else {
-                a.pst = mgr.user_page(num).unwrap() as usize;
-            }
+                mgr.user_page(num)
+            };
+            a.pst = pst.ok_or(MemoryError::OutOfMemory)? as usize;
             unsafe {
                 (a.pst as *mut u8).copy_from(area.pst as *mut u8, area.ped - area.pst);
             }
@@ -120,6 +150,12 @@ impl TaskStack {
             a.ped = pst;
             self.area.push(a);
         }
+        Ok(())
+    }
+
+    /// 栈实际占用的物理页面数
+    pub fn page_num(&self)->usize {
+        self.area.iter().map(|area| (area.ped - area.pst) / PAGE_SIZE).sum()
     }
 
     pub fn virt_to_phy(&self, va:usize)->usize {
@@ -0,0 +1,60 @@
+//! # 页面交换
+//! 物理页面不足时把用户进程的堆内存池写出到交换区，访问时通过缺页异常换回
+//! 换出以内存池为单位，保证内存池的物理页面始终连续，内核可以直接按物理地址读写
+//! 换出对象的选取采用时钟算法，页表项的访问位给予第二次机会，见 task_pool 中的 reclaim
+//!
+//! 2021年6月6日 zg
+
+mod swap_area;
+
+pub use swap_area::SwapInfo;
+
+use tisu_sync::ContentMutex;
+use crate::virtio::device::get_device;
+use self::swap_area::SwapArea;
+
+static mut SWAP_AREA : Option<ContentMutex<SwapArea>> = None;
+
+/// 在磁盘缓冲初始化之后调用，使用第一个带有交换区头部的磁盘
+pub fn init() {
+    for idx in 0..get_device().block_device.len() {
+        if let Some(area) = SwapArea::probe(idx) {
+            let info = area.info();
+            println!("disk {} is swap, total {} pages", idx, info.total_slot);
+            unsafe {
+                SWAP_AREA = Some(ContentMutex::new(area, true));
+            }
+            return;
+        }
+    }
+}
+
+pub fn enabled()->bool {
+    unsafe {SWAP_AREA.is_some()}
+}
+
+/// 返回起始槽位
+pub fn swap_out(physic_addr : usize, page_num : usize)->Option<usize> {
+    unsafe {
+        SWAP_AREA.as_mut()?.lock().write_out(physic_addr, page_num)
+    }
+}
+
+pub fn swap_in(slot : usize, physic_addr : usize, page_num : usize) {
+    unsafe {
+        SWAP_AREA.as_mut().unwrap().lock().read_in(slot, physic_addr, page_num);
+    }
+}
+
+/// 进程结束时释放仍在交换区中的槽位
+pub fn release(slot : usize, page_num : usize) {
+    unsafe {
+        SWAP_AREA.as_mut().unwrap().lock().release(slot, page_num);
+    }
+}
+
+pub fn statistics()->Option<SwapInfo> {
+    unsafe {
+        Some(SWAP_AREA.as_mut()?.lock().info())
+    }
+}
@@ -0,0 +1,121 @@
+//! # 交换区
+//! 交换区位于一个单独的块设备上，格式与 mkswap 生成的相同
+//! 第 0 页为头部，其后每一页为一个槽位，连续的页面占用连续的槽位
+//!
+//! 2021年6月6日 zg
+
+use alloc::prelude::v1::*;
+use core::slice;
+use crate::{memory::config::PAGE_SIZE, virtio::disk_cache::{sync_read_buffer, sync_write_buffer}};
+
+const MAGIC : &[u8] = b"SWAPSPACE2";
+/// 头部中 last_page 字段的偏移
+const LAST_PAGE_OFFSET : usize = 1024 + 4;
+
+/// ## 交换区统计信息
+#[derive(Debug, Clone, Copy)]
+pub struct SwapInfo {
+    pub device_id : usize,
+    pub total_slot : usize,
+    pub used_slot : usize,
+    pub swap_out : usize,
+    pub swap_in : usize,
+}
+
+pub struct SwapArea {
+    device_id : usize,
+    /// 槽位 0 对应头部，始终占用
+    slot : Vec<bool>,
+    used_slot : usize,
+    swap_out : usize,
+    swap_in : usize,
+}
+
+impl SwapArea {
+    /// 读取头部检查是否为交换区
+    pub fn probe(device_id : usize)->Option<Self> {
+        let mut head = vec![0u8;PAGE_SIZE];
+        sync_read_buffer(device_id, &mut head, 0);
+        if &head[PAGE_SIZE - MAGIC.len()..] != MAGIC {
+            return None;
+        }
+        let last_page = unsafe {
+            (head.as_ptr().add(LAST_PAGE_OFFSET) as *const u32).read_unaligned()
+        } as usize;
+        if last_page == 0 {
+            return None;
+        }
+        let mut slot = vec![false;last_page + 1];
+        slot[0] = true;
+        Some(Self {
+            device_id,
+            slot,
+            used_slot : 0,
+            swap_out : 0,
+            swap_in : 0,
+        })
+    }
+
+    /// ## 写出连续的物理页面
+    /// 返回起始槽位，没有足够的连续槽位时返回 None
+    pub fn write_out(&mut self, physic_addr : usize, page_num : usize)->Option<usize> {
+        let st = self.find_slot(page_num)?;
+        for i in 0..page_num {
+            self.slot[st + i] = true;
+            let data = unsafe {
+                slice::from_raw_parts((physic_addr + i * PAGE_SIZE) as *const u8, PAGE_SIZE)
+            };
+            sync_write_buffer(self.device_id, data, (st + i) * PAGE_SIZE);
+        }
+        self.used_slot += page_num;
+        self.swap_out += page_num;
+        Some(st)
+    }
+
+    /// 读回页面并释放槽位
+    pub fn read_in(&mut self, slot : usize, physic_addr : usize, page_num : usize) {
+        for i in 0..page_num {
+            let data = unsafe {
+                slice::from_raw_parts_mut((physic_addr + i * PAGE_SIZE) as *mut u8, PAGE_SIZE)
+            };
+            sync_read_buffer(self.device_id, data, (slot + i) * PAGE_SIZE);
+        }
+        self.release(slot, page_num);
+        self.swap_in += page_num;
+    }
+
+    pub fn release(&mut self, slot : usize, page_num : usize) {
+        for i in slot..slot + page_num {
+            assert!(self.slot[i]);
+            self.slot[i] = false;
+        }
+        self.used_slot -= page_num;
+    }
+
+    pub fn info(&self)->SwapInfo {
+        SwapInfo {
+            device_id : self.device_id,
+            total_slot : self.slot.len() - 1,
+            used_slot : self.used_slot,
+            swap_out : self.swap_out,
+            swap_in : self.swap_in,
+        }
+    }
+
+    /// 首次适应
+    fn find_slot(&self, page_num : usize)->Option<usize> {
+        let mut cnt = 0;
+        for (idx, used) in self.slot.iter().enumerate() {
+            if *used {
+                cnt = 0;
+            }
+            else {
+                cnt += 1;
+                if cnt == page_num {
+                    return Some(idx + 1 - page_num);
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,114 @@
+//! # 网络地址
+//! 以太网 MAC 地址与 IPv4 地址，均按网络字节序保存
+//!
+//! 2021年6月9日 zg
+
+use core::fmt::{Display, Formatter, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MacAddress(pub [u8;6]);
+
+impl MacAddress {
+    pub const BROADCAST : Self = Self([0xff;6]);
+    pub const ZERO : Self = Self([0;6]);
+
+    /// 设备给出的 MAC 以小端整数保存，最低字节在前
+    pub fn from_val(val : usize)->Self {
+        let mut rt = [0;6];
+        for i in 0..6 {
+            rt[i] = (val >> (i * 8)) as u8;
+        }
+        Self(rt)
+    }
+
+    pub fn from_slice(s : &[u8])->Self {
+        let mut rt = [0;6];
+        rt.copy_from_slice(&s[..6]);
+        Self(rt)
+    }
+
+    pub fn is_broadcast(&self)->bool {
+        *self == Self::BROADCAST
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let a = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a[0], a[1], a[2], a[3], a[4], a[5])
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Ipv4Address(pub [u8;4]);
+
+impl Ipv4Address {
+    pub const UNSPECIFIED : Self = Self([0;4]);
+    pub const BROADCAST : Self = Self([255;4]);
+
+    pub const fn new(a : u8, b : u8, c : u8, d : u8)->Self {
+        Self([a, b, c, d])
+    }
+
+    pub fn from_slice(s : &[u8])->Self {
+        let mut rt = [0;4];
+        rt.copy_from_slice(&s[..4]);
+        Self(rt)
+    }
+
+    pub fn from_val(val : u32)->Self {
+        Self(val.to_be_bytes())
+    }
+
+    pub fn val(&self)->u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    pub fn is_unspecified(&self)->bool {
+        *self == Self::UNSPECIFIED
+    }
+
+    /// 解析点分十进制字符串
+    pub fn parse(s : &str)->Option<Self> {
+        let mut rt = [0u8;4];
+        let mut cnt = 0;
+        for part in s.split('.') {
+            if cnt >= 4 || part.len() == 0 {
+                return None;
+            }
+            let mut val : usize = 0;
+            for c in part.chars() {
+                val = val * 10 + c.to_digit(10)? as usize;
+                if val > 255 {
+                    return None;
+                }
+            }
+            rt[cnt] = val as u8;
+            cnt += 1;
+        }
+        if cnt == 4 {
+            Some(Self(rt))
+        }
+        else {
+            None
+        }
+    }
+
+    /// 判断两个地址是否在同一子网
+    pub fn same_subnet(&self, other : &Self, netmask : &Self)->bool {
+        self.val() & netmask.val() == other.val() & netmask.val()
+    }
+
+    /// 子网的广播地址
+    pub fn subnet_broadcast(&self, netmask : &Self)->Self {
+        Self::from_val(self.val() | !netmask.val())
+    }
+}
+
+impl Display for Ipv4Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let a = self.0;
+        write!(f, "{}.{}.{}.{}", a[0], a[1], a[2], a[3])
+    }
+}
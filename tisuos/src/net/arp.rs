@@ -0,0 +1,163 @@
+//! # ARP
+//! 地址解析：报文的解析与构建，以及带超时的 ARP 缓存
+//! 解析中的地址每隔 REQUEST_INTERVAL 重发请求，超过 MAX_RETRY 次后放弃，等待该地址的帧一并丢弃
+//!
+//! 2021年6月9日 zg
+
+use alloc::{collections::BTreeMap, prelude::v1::*};
+use super::address::{Ipv4Address, MacAddress};
+
+pub const OPER_REQUEST : u16 = 1;
+pub const OPER_REPLY : u16 = 2;
+const HTYPE_ETHERNET : u16 = 1;
+const PTYPE_IPV4 : u16 = 0x0800;
+const PACKET_LEN : usize = 28;
+
+/// 缓存有效期，单位毫秒
+const ENTRY_TIMEOUT : usize = 60_000;
+const REQUEST_INTERVAL : usize = 1000;
+const MAX_RETRY : usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ArpPacket {
+    pub oper : u16,
+    pub sender_mac : MacAddress,
+    pub sender_ip : Ipv4Address,
+    pub target_mac : MacAddress,
+    pub target_ip : Ipv4Address,
+}
+
+impl ArpPacket {
+    /// 只接受以太网与 IPv4 的组合
+    pub fn parse(data : &[u8])->Option<Self> {
+        if data.len() < PACKET_LEN {
+            return None;
+        }
+        let htype = u16::from_be_bytes([data[0], data[1]]);
+        let ptype = u16::from_be_bytes([data[2], data[3]]);
+        if htype != HTYPE_ETHERNET || ptype != PTYPE_IPV4 || data[4] != 6 || data[5] != 4 {
+            return None;
+        }
+        Some(Self {
+            oper : u16::from_be_bytes([data[6], data[7]]),
+            sender_mac : MacAddress::from_slice(&data[8..14]),
+            sender_ip : Ipv4Address::from_slice(&data[14..18]),
+            target_mac : MacAddress::from_slice(&data[18..24]),
+            target_ip : Ipv4Address::from_slice(&data[24..28]),
+        })
+    }
+
+    pub fn build(&self)->Vec<u8> {
+        let mut rt = Vec::with_capacity(PACKET_LEN);
+        rt.extend_from_slice(&HTYPE_ETHERNET.to_be_bytes());
+        rt.extend_from_slice(&PTYPE_IPV4.to_be_bytes());
+        rt.push(6);
+        rt.push(4);
+        rt.extend_from_slice(&self.oper.to_be_bytes());
+        rt.extend_from_slice(&self.sender_mac.0);
+        rt.extend_from_slice(&self.sender_ip.0);
+        rt.extend_from_slice(&self.target_mac.0);
+        rt.extend_from_slice(&self.target_ip.0);
+        rt
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ArpEntry {
+    pub mac : MacAddress,
+    /// 过期时间
+    pub expire : usize,
+}
+
+/// 正在解析的地址
+struct Resolving {
+    last_request : usize,
+    retry : usize,
+    /// 等待发送的帧：（以太网类型，负载）
+    waiting : Vec<(u16, Vec<u8>)>,
+}
+
+pub struct ArpCache {
+    entry : BTreeMap<Ipv4Address, ArpEntry>,
+    resolving : BTreeMap<Ipv4Address, Resolving>,
+}
+
+impl ArpCache {
+    pub fn new()->Self {
+        Self {
+            entry : BTreeMap::new(),
+            resolving : BTreeMap::new(),
+        }
+    }
+
+    pub fn lookup(&self, ip : Ipv4Address, now : usize)->Option<MacAddress> {
+        let entry = self.entry.get(&ip)?;
+        if entry.expire > now {
+            Some(entry.mac)
+        }
+        else {
+            None
+        }
+    }
+
+    /// ## 更新缓存
+    /// 返回之前等待该地址的帧
+    pub fn update(&mut self, ip : Ipv4Address, mac : MacAddress, now : usize)->Vec<(u16, Vec<u8>)> {
+        self.entry.insert(ip, ArpEntry{mac, expire : now + ENTRY_TIMEOUT});
+        if let Some(r) = self.resolving.remove(&ip) {
+            r.waiting
+        }
+        else {
+            Vec::new()
+        }
+    }
+
+    /// ## 挂起等待解析的帧
+    /// 需要立即发送请求时返回 true
+    pub fn wait(&mut self, ip : Ipv4Address, ethertype : u16, payload : Vec<u8>, now : usize)->bool {
+        if let Some(r) = self.resolving.get_mut(&ip) {
+            r.waiting.push((ethertype, payload));
+            false
+        }
+        else {
+            let mut waiting = Vec::new();
+            waiting.push((ethertype, payload));
+            self.resolving.insert(ip, Resolving{last_request : now, retry : 0, waiting});
+            true
+        }
+    }
+
+    /// ## 定时处理
+    /// 清除过期的缓存，返回需要重发请求的地址
+    pub fn tick(&mut self, now : usize)->Vec<Ipv4Address> {
+        let expired : Vec<Ipv4Address> = self.entry.iter()
+            .filter(|(_, e)| e.expire <= now).map(|(ip, _)| *ip).collect();
+        for ip in expired {
+            self.entry.remove(&ip);
+        }
+        let mut rt = Vec::new();
+        let mut give_up = Vec::new();
+        for (ip, r) in self.resolving.iter_mut() {
+            if now < r.last_request + REQUEST_INTERVAL {
+                continue;
+            }
+            if r.retry >= MAX_RETRY {
+                give_up.push(*ip);
+            }
+            else {
+                r.retry += 1;
+                r.last_request = now;
+                rt.push(*ip);
+            }
+        }
+        for ip in give_up {
+            let r = self.resolving.remove(&ip).unwrap();
+            println!("arp resolve {} timeout, drop {} frames", ip, r.waiting.len());
+        }
+        rt
+    }
+
+    pub fn entries(&self)->Vec<(Ipv4Address, ArpEntry)> {
+        self.entry.iter().map(|(ip, e)| (*ip, *e)).collect()
+    }
+}
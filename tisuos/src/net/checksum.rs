@@ -0,0 +1,40 @@
+//! # 校验和
+//! IPv4 头部、ICMP、UDP、TCP 共用的反码求和校验
+//!
+//! 2021年6月10日 zg
+
+use super::address::Ipv4Address;
+
+/// 累加 16 位大端字，奇数长度时末尾补零
+pub fn sum(initial : u32, data : &[u8])->u32 {
+    let mut rt = initial;
+    let mut i = 0;
+    while i + 1 < data.len() {
+        rt += u16::from_be_bytes([data[i], data[i + 1]]) as u32;
+        i += 2;
+    }
+    if i < data.len() {
+        rt += (data[i] as u32) << 8;
+    }
+    rt
+}
+
+/// 折叠进位并取反
+pub fn finish(mut sum : u32)->u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub fn checksum(data : &[u8])->u16 {
+    finish(sum(0, data))
+}
+
+
+/// UDP、TCP 校验和所用的伪头部：源地址、目的地址、协议号与上层长度
+pub fn pseudo_header(src : Ipv4Address, dst : Ipv4Address, protocol : u8, len : usize)->u32 {
+    let mut rt = sum(0, &src.0);
+    rt = sum(rt, &dst.0);
+    rt + protocol as u32 + len as u32
+}
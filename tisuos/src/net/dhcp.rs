@@ -0,0 +1,296 @@
+//! # DHCP
+//! 每个接口一个客户端：广播 DISCOVER，收到 OFFER 后 REQUEST，收到 ACK 后写入接口配置
+//! 租期过半时重新请求续租，租期结束仍未续上则重新获取。多次没有应答时退回 QEMU 用户网络的默认地址
+//!
+//! 2021年6月13日 zg
+
+use alloc::prelude::v1::*;
+use crate::interrupt::timer::get_million_time;
+use super::{address::Ipv4Address, interface::Interface, ipv4::{self, PROTOCOL_UDP}, udp};
+
+pub const CLIENT_PORT : u16 = 68;
+const SERVER_PORT : u16 = 67;
+const OP_REQUEST : u8 = 1;
+const OP_REPLY : u8 = 2;
+const HTYPE_ETHERNET : u8 = 1;
+const FLAG_BROADCAST : u16 = 0x8000;
+const MAGIC_COOKIE : [u8;4] = [99, 130, 83, 99];
+/// 固定部分长度，包括魔数
+const FIXED_LEN : usize = 240;
+
+const OPTION_PAD : u8 = 0;
+const OPTION_SUBNET_MASK : u8 = 1;
+const OPTION_ROUTER : u8 = 3;
+const OPTION_DNS : u8 = 6;
+const OPTION_REQUESTED_IP : u8 = 50;
+const OPTION_LEASE_TIME : u8 = 51;
+const OPTION_MESSAGE_TYPE : u8 = 53;
+const OPTION_SERVER_ID : u8 = 54;
+const OPTION_PARAMETER_LIST : u8 = 55;
+const OPTION_END : u8 = 255;
+
+const DHCP_DISCOVER : u8 = 1;
+const DHCP_OFFER : u8 = 2;
+const DHCP_REQUEST : u8 = 3;
+const DHCP_ACK : u8 = 5;
+const DHCP_NAK : u8 = 6;
+
+/// 重发间隔，单位毫秒
+const RETRY_INTERVAL : usize = 2000;
+const MAX_RETRIES : usize = 4;
+
+/// 获取失败时使用的 QEMU 用户网络（slirp）默认配置
+const DEFAULT_IP : Ipv4Address = Ipv4Address::new(10, 0, 2, 15);
+const DEFAULT_NETMASK : Ipv4Address = Ipv4Address::new(255, 255, 255, 0);
+const DEFAULT_GATEWAY : Ipv4Address = Ipv4Address::new(10, 0, 2, 2);
+const DEFAULT_DNS : Ipv4Address = Ipv4Address::new(10, 0, 2, 3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpState {
+    /// 使用静态配置
+    Disabled,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+}
+
+pub struct DhcpClient {
+    pub state : DhcpState,
+    xid : u32,
+    /// 下一次重发或者续租的时间
+    timer : usize,
+    retries : usize,
+    lease_end : usize,
+    offer : Ipv4Address,
+    pub server : Ipv4Address,
+}
+
+impl DhcpClient {
+    pub fn new()->Self {
+        Self {
+            state : DhcpState::Disabled,
+            xid : 0,
+            timer : 0,
+            retries : 0,
+            lease_end : 0,
+            offer : Ipv4Address::UNSPECIFIED,
+            server : Ipv4Address::UNSPECIFIED,
+        }
+    }
+}
+
+/// 收到的 DHCP 报文中关心的内容
+struct Reply {
+    xid : u32,
+    yiaddr : Ipv4Address,
+    message_type : u8,
+    netmask : Option<Ipv4Address>,
+    router : Option<Ipv4Address>,
+    dns : Option<Ipv4Address>,
+    lease_time : Option<usize>,
+    server : Option<Ipv4Address>,
+}
+
+impl Reply {
+    fn parse(data : &[u8], mac : &[u8])->Option<Self> {
+        if data.len() < FIXED_LEN || data[0] != OP_REPLY || data[1] != HTYPE_ETHERNET
+            || &data[28..34] != mac || data[236..240] != MAGIC_COOKIE {
+            return None;
+        }
+        let mut rt = Self {
+            xid : u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            yiaddr : Ipv4Address::from_slice(&data[16..20]),
+            message_type : 0,
+            netmask : None,
+            router : None,
+            dns : None,
+            lease_time : None,
+            server : None,
+        };
+        let option = &data[FIXED_LEN..];
+        let mut i = 0;
+        while i < option.len() {
+            let code = option[i];
+            if code == OPTION_END {
+                break;
+            }
+            if code == OPTION_PAD {
+                i += 1;
+                continue;
+            }
+            if i + 1 >= option.len() || i + 2 + option[i + 1] as usize > option.len() {
+                break;
+            }
+            let val = &option[i + 2..i + 2 + option[i + 1] as usize];
+            match code {
+                OPTION_MESSAGE_TYPE if val.len() >= 1 => rt.message_type = val[0],
+                OPTION_SUBNET_MASK if val.len() >= 4 => rt.netmask = Some(Ipv4Address::from_slice(val)),
+                OPTION_ROUTER if val.len() >= 4 => rt.router = Some(Ipv4Address::from_slice(val)),
+                OPTION_DNS if val.len() >= 4 => rt.dns = Some(Ipv4Address::from_slice(val)),
+                OPTION_SERVER_ID if val.len() >= 4 => rt.server = Some(Ipv4Address::from_slice(val)),
+                OPTION_LEASE_TIME if val.len() >= 4 => {
+                    rt.lease_time = Some(u32::from_be_bytes([val[0], val[1], val[2], val[3]]) as usize);
+                }
+                _ => {}
+            }
+            i += 2 + val.len();
+        }
+        Some(rt)
+    }
+}
+
+fn build(iface : &Interface, message_type : u8, ciaddr : Ipv4Address, requested : Option<(Ipv4Address, Ipv4Address)>)->Vec<u8> {
+    let mut rt = vec![0u8; FIXED_LEN];
+    rt[0] = OP_REQUEST;
+    rt[1] = HTYPE_ETHERNET;
+    rt[2] = 6;
+    rt[4..8].copy_from_slice(&iface.dhcp.xid.to_be_bytes());
+    rt[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+    rt[12..16].copy_from_slice(&ciaddr.0);
+    rt[28..34].copy_from_slice(&iface.mac.0);
+    rt[236..240].copy_from_slice(&MAGIC_COOKIE);
+    rt.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, message_type]);
+    if let Some((ip, server)) = requested {
+        rt.extend_from_slice(&[OPTION_REQUESTED_IP, 4]);
+        rt.extend_from_slice(&ip.0);
+        rt.extend_from_slice(&[OPTION_SERVER_ID, 4]);
+        rt.extend_from_slice(&server.0);
+    }
+    rt.extend_from_slice(&[OPTION_PARAMETER_LIST, 4, OPTION_SUBNET_MASK, OPTION_ROUTER, OPTION_DNS, OPTION_LEASE_TIME]);
+    rt.push(OPTION_END);
+    rt
+}
+
+/// 报文都以广播发出，续租时带上当前地址
+fn send(iface : &mut Interface, message : Vec<u8>) {
+    let datagram = udp::build(iface.ip, Ipv4Address::BROADCAST, CLIENT_PORT, SERVER_PORT, &message);
+    ipv4::send_via(iface, Ipv4Address::BROADCAST, Ipv4Address::BROADCAST, PROTOCOL_UDP, &datagram);
+}
+
+fn new_xid(iface : &Interface)->u32 {
+    let mac = iface.mac.0;
+    u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]]) ^ get_million_time() as u32
+}
+
+fn discover(iface : &mut Interface, now : usize) {
+    let message = build(iface, DHCP_DISCOVER, Ipv4Address::UNSPECIFIED, None);
+    send(iface, message);
+    iface.dhcp.timer = now + RETRY_INTERVAL;
+}
+
+fn request(iface : &mut Interface, now : usize) {
+    let message = if iface.dhcp.state == DhcpState::Renewing {
+        build(iface, DHCP_REQUEST, iface.ip, None)
+    }
+    else {
+        build(iface, DHCP_REQUEST, Ipv4Address::UNSPECIFIED, Some((iface.dhcp.offer, iface.dhcp.server)))
+    };
+    send(iface, message);
+    iface.dhcp.timer = now + RETRY_INTERVAL;
+}
+
+/// 清除当前地址，开始获取
+pub fn start(iface : &mut Interface) {
+    let now = get_million_time();
+    iface.ip = Ipv4Address::UNSPECIFIED;
+    iface.netmask = Ipv4Address::UNSPECIFIED;
+    iface.gateway = Ipv4Address::UNSPECIFIED;
+    iface.dns = Ipv4Address::UNSPECIFIED;
+    iface.dhcp.state = DhcpState::Selecting;
+    iface.dhcp.xid = new_xid(iface);
+    iface.dhcp.retries = 0;
+    discover(iface, now);
+}
+
+/// 停止获取，之后使用静态配置
+pub fn stop(iface : &mut Interface) {
+    iface.dhcp.state = DhcpState::Disabled;
+}
+
+fn fallback(iface : &mut Interface) {
+    iface.dhcp.state = DhcpState::Disabled;
+    iface.ip = DEFAULT_IP;
+    iface.netmask = DEFAULT_NETMASK;
+    iface.gateway = DEFAULT_GATEWAY;
+    iface.dns = DEFAULT_DNS;
+    println!("{} dhcp no reply, use {}", iface.name, iface.ip);
+}
+
+/// 收到发往客户端端口的报文
+pub fn receive(iface : &mut Interface, data : &[u8]) {
+    let reply = if let Some(r) = Reply::parse(data, &iface.mac.0) { r } else { return; };
+    if reply.xid != iface.dhcp.xid {
+        return;
+    }
+    let now = get_million_time();
+    match (iface.dhcp.state, reply.message_type) {
+        (DhcpState::Selecting, DHCP_OFFER) => {
+            let server = if let Some(s) = reply.server { s } else { return; };
+            iface.dhcp.offer = reply.yiaddr;
+            iface.dhcp.server = server;
+            iface.dhcp.state = DhcpState::Requesting;
+            iface.dhcp.retries = 0;
+            request(iface, now);
+        }
+        (DhcpState::Requesting, DHCP_ACK) | (DhcpState::Renewing, DHCP_ACK) => {
+            let lease = reply.lease_time.unwrap_or(3600) * 1000;
+            iface.ip = reply.yiaddr;
+            iface.netmask = reply.netmask.unwrap_or(DEFAULT_NETMASK);
+            iface.gateway = reply.router.unwrap_or(Ipv4Address::UNSPECIFIED);
+            iface.dns = reply.dns.unwrap_or(Ipv4Address::UNSPECIFIED);
+            if let Some(server) = reply.server {
+                iface.dhcp.server = server;
+            }
+            if iface.dhcp.state == DhcpState::Requesting {
+                println!("{} dhcp bound {} netmask {} gateway {} dns {} lease {}s", iface.name,
+                    iface.ip, iface.netmask, iface.gateway, iface.dns, lease / 1000);
+            }
+            iface.dhcp.state = DhcpState::Bound;
+            iface.dhcp.lease_end = now + lease;
+            iface.dhcp.timer = now + lease / 2;
+        }
+        (DhcpState::Requesting, DHCP_NAK) | (DhcpState::Renewing, DHCP_NAK) => {
+            start(iface);
+        }
+        _ => {}
+    }
+}
+
+/// 由定时器中断调用，负责重发与续租
+pub fn tick(iface : &mut Interface) {
+    let now = get_million_time();
+    let dhcp = &mut iface.dhcp;
+    if dhcp.state == DhcpState::Disabled || dhcp.timer > now {
+        return;
+    }
+    match dhcp.state {
+        DhcpState::Selecting | DhcpState::Requesting => {
+            dhcp.retries += 1;
+            if dhcp.retries > MAX_RETRIES {
+                fallback(iface);
+            }
+            else if dhcp.state == DhcpState::Selecting {
+                discover(iface, now);
+            }
+            else {
+                request(iface, now);
+            }
+        }
+        DhcpState::Bound => {
+            dhcp.state = DhcpState::Renewing;
+            dhcp.xid = dhcp.xid.wrapping_add(1);
+            request(iface, now);
+        }
+        DhcpState::Renewing => {
+            if dhcp.lease_end <= now {
+                println!("{} dhcp lease expired", iface.name);
+                start(iface);
+            }
+            else {
+                request(iface, now);
+            }
+        }
+        DhcpState::Disabled => {}
+    }
+}
@@ -0,0 +1,299 @@
+//! # DNS
+//! 存根解析器：先查磁盘上的 hosts 文件，再查缓存，最后向接口配置的 DNS 服务器查询 A 记录
+//! 应答按 TTL 缓存，不存在的名字与超时也短暂缓存，避免反复查询
+//! 查询通过内核持有的 UDP 套接字发送，应答在解析与时钟中断时取出
+//!
+//! 2021年6月14日 zg
+
+use alloc::{collections::BTreeMap, prelude::v1::*};
+use tisu_fs::{FileFlag, SystemOp};
+use tisu_sync::ContentMutex;
+use crate::{filesystem::get_system, interrupt::timer::get_million_time};
+use super::{address::Ipv4Address, interface_op, udp};
+
+const DNS_PORT : u16 = 53;
+/// hosts 文件位于 0 号磁盘根目录
+const HOSTS_DISK : usize = 0;
+const HOSTS_PATH : &str = "/hosts";
+const TYPE_A : u16 = 1;
+const CLASS_IN : u16 = 1;
+const FLAG_RESPONSE : u16 = 0x8000;
+const FLAG_RECURSION_DESIRED : u16 = 0x0100;
+const RCODE_MASK : u16 = 0x000f;
+const HEADER_LEN : usize = 12;
+const MAX_NAME_LEN : usize = 253;
+/// 重发间隔，单位毫秒
+const RETRY_INTERVAL : usize = 1000;
+const MAX_RETRIES : usize = 3;
+/// 失败结果的缓存时间，单位毫秒
+const NEGATIVE_TTL : usize = 30_000;
+const MAX_CACHE : usize = 64;
+/// 内核套接字的所有者，不会与进程号重复
+const KERNEL_OWNER : usize = usize::MAX;
+
+static mut RESOLVER : Option<ContentMutex<Resolver>> = None;
+
+struct CacheEntry {
+    /// None 表示名字不存在或者查询失败
+    addr : Option<Ipv4Address>,
+    expire : usize,
+}
+
+struct Query {
+    id : u16,
+    server : Ipv4Address,
+    send_time : usize,
+    retries : usize,
+}
+
+struct Resolver {
+    socket : Option<usize>,
+    hosts : Option<BTreeMap<String, Ipv4Address>>,
+    cache : BTreeMap<String, CacheEntry>,
+    pending : BTreeMap<String, Query>,
+    next_id : u16,
+}
+
+impl Resolver {
+    /// 第一次使用时读取 hosts 文件，文件不存在时只有 localhost
+    fn hosts(&mut self)->&BTreeMap<String, Ipv4Address> {
+        if self.hosts.is_none() {
+            let mut hosts = BTreeMap::new();
+            hosts.insert("localhost".to_string(), Ipv4Address::new(127, 0, 0, 1));
+            if let Some(text) = read_hosts() {
+                for line in text.lines() {
+                    let line = line.split('#').next().unwrap();
+                    let mut item = line.split_whitespace();
+                    let addr = if let Some(addr) = item.next().and_then(Ipv4Address::parse) {
+                        addr
+                    }
+                    else {
+                        continue;
+                    };
+                    for name in item {
+                        hosts.insert(name.to_lowercase(), addr);
+                    }
+                }
+            }
+            self.hosts = Some(hosts);
+        }
+        self.hosts.as_ref().unwrap()
+    }
+
+    fn socket(&mut self)->Option<usize> {
+        if self.socket.is_none() {
+            let id = udp::socket(KERNEL_OWNER)?;
+            udp::bind(id, 0).ok()?;
+            self.socket = Some(id);
+        }
+        self.socket
+    }
+
+    fn send_query(&mut self, name : &str, server : Ipv4Address, id : u16)->Result<(), ()> {
+        let socket = self.socket().ok_or(())?;
+        udp::send_to(socket, server, DNS_PORT, &build_query(name, id)).map(|_| ())
+    }
+
+    /// 取出套接字中的应答，写入缓存
+    fn poll(&mut self, now : usize) {
+        let socket = if let Some(s) = self.socket { s } else { return; };
+        while let Some(datagram) = udp::recv_from(socket) {
+            if datagram.src_port != DNS_PORT {
+                continue;
+            }
+            let (id, rt) = if let Some(rt) = parse_response(&datagram.data) { rt } else { continue; };
+            let name = self.pending.iter()
+                .find(|(_, q)| q.id == id && q.server == datagram.src).map(|(n, _)| n.clone());
+            if let Some(name) = name {
+                self.pending.remove(&name);
+                let entry = match rt {
+                    Some((addr, ttl)) => CacheEntry { addr : Some(addr), expire : now + ttl * 1000 },
+                    None => CacheEntry { addr : None, expire : now + NEGATIVE_TTL },
+                };
+                self.insert_cache(name, entry, now);
+            }
+        }
+    }
+
+    fn insert_cache(&mut self, name : String, entry : CacheEntry, now : usize) {
+        if self.cache.len() >= MAX_CACHE {
+            self.expire(now);
+        }
+        if self.cache.len() >= MAX_CACHE {
+            // 仍然已满时淘汰最早过期的一项
+            let oldest = self.cache.iter().min_by_key(|(_, e)| e.expire).map(|(n, _)| n.clone());
+            if let Some(oldest) = oldest {
+                self.cache.remove(&oldest);
+            }
+        }
+        self.cache.insert(name, entry);
+    }
+
+    fn expire(&mut self, now : usize) {
+        let expired : Vec<String> = self.cache.iter()
+            .filter(|(_, e)| e.expire <= now).map(|(n, _)| n.clone()).collect();
+        for name in expired {
+            self.cache.remove(&name);
+        }
+    }
+}
+
+pub fn init() {
+    unsafe {
+        RESOLVER = Some(ContentMutex::new(Resolver {
+            socket : None,
+            hosts : None,
+            cache : BTreeMap::new(),
+            pending : BTreeMap::new(),
+            next_id : 1,
+        }, true));
+    }
+}
+
+fn read_hosts()->Option<String> {
+    let sys = get_system(HOSTS_DISK)?;
+    let file = sys.open(HOSTS_PATH.to_string(), FileFlag::Read).ok()?.clone();
+    let mut data = vec![0u8; file.size];
+    sys.read(file.id, &mut data[..]).ok()?;
+    String::from_utf8(data).ok()
+}
+
+/// 第一个配置了 DNS 服务器的接口
+fn dns_server()->Option<Ipv4Address> {
+    interface_op(|ifaces| {
+        ifaces.iter().find(|iface| !iface.dns.is_unspecified()).map(|iface| iface.dns)
+    })?
+}
+
+fn build_query(name : &str, id : u16)->Vec<u8> {
+    let mut rt = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    rt.extend_from_slice(&id.to_be_bytes());
+    rt.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    rt.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        rt.push(label.len() as u8);
+        rt.extend_from_slice(label.as_bytes());
+    }
+    rt.push(0);
+    rt.extend_from_slice(&TYPE_A.to_be_bytes());
+    rt.extend_from_slice(&CLASS_IN.to_be_bytes());
+    rt
+}
+
+/// 跳过报文中的名字，返回名字之后的位置
+fn skip_name(data : &[u8], mut idx : usize)->Option<usize> {
+    loop {
+        let len = *data.get(idx)? as usize;
+        if len == 0 {
+            return Some(idx + 1);
+        }
+        // 压缩指针占两个字节，名字到此结束
+        if len & 0xc0 == 0xc0 {
+            return Some(idx + 2);
+        }
+        idx += 1 + len;
+    }
+}
+
+/// ## 解析应答
+/// 返回（查询 ID，（地址，TTL 秒）），名字不存在或者没有 A 记录时地址部分为 None
+fn parse_response(data : &[u8])->Option<(u16, Option<(Ipv4Address, usize)>)> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    let id = u16::from_be_bytes([data[0], data[1]]);
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    if flags & FLAG_RESPONSE == 0 {
+        return None;
+    }
+    if flags & RCODE_MASK != 0 {
+        return Some((id, None));
+    }
+    let qdcount = u16::from_be_bytes([data[4], data[5]]);
+    let ancount = u16::from_be_bytes([data[6], data[7]]);
+    let mut idx = HEADER_LEN;
+    for _ in 0..qdcount {
+        idx = skip_name(data, idx)? + 4;
+    }
+    for _ in 0..ancount {
+        idx = skip_name(data, idx)?;
+        if idx + 10 > data.len() {
+            return None;
+        }
+        let rtype = u16::from_be_bytes([data[idx], data[idx + 1]]);
+        let class = u16::from_be_bytes([data[idx + 2], data[idx + 3]]);
+        let ttl = u32::from_be_bytes([data[idx + 4], data[idx + 5], data[idx + 6], data[idx + 7]]);
+        let len = u16::from_be_bytes([data[idx + 8], data[idx + 9]]) as usize;
+        idx += 10;
+        if idx + len > data.len() {
+            return None;
+        }
+        // CNAME 等其它记录跳过，取第一条 A 记录
+        if rtype == TYPE_A && class == CLASS_IN && len == 4 {
+            return Some((id, Some((Ipv4Address::from_slice(&data[idx..idx + 4]), ttl as usize))));
+        }
+        idx += len;
+    }
+    Some((id, None))
+}
+
+/// ## 解析名字
+/// 得到结果时返回地址，查询还在进行时返回 None，名字无效、不存在或者查询失败时返回错误
+pub fn resolve(name : &str)->Result<Option<Ipv4Address>, ()> {
+    if let Some(addr) = Ipv4Address::parse(name) {
+        return Ok(Some(addr));
+    }
+    let name = name.trim_end_matches('.').to_lowercase();
+    if name.len() == 0 || name.len() > MAX_NAME_LEN
+        || name.split('.').any(|label| label.len() == 0 || label.len() > 63) {
+        return Err(());
+    }
+    let server = dns_server();
+    let now = get_million_time();
+    let mut resolver = unsafe {RESOLVER.as_mut().ok_or(())?.lock()};
+    if let Some(addr) = resolver.hosts().get(&name) {
+        return Ok(Some(*addr));
+    }
+    resolver.poll(now);
+    if let Some(entry) = resolver.cache.get(&name) {
+        if entry.expire > now {
+            return entry.addr.map(|addr| Some(addr)).ok_or(());
+        }
+    }
+    if resolver.pending.contains_key(&name) {
+        return Ok(None);
+    }
+    let server = server.ok_or(())?;
+    let id = resolver.next_id;
+    resolver.next_id = resolver.next_id.wrapping_add(1);
+    resolver.send_query(&name, server, id)?;
+    resolver.pending.insert(name, Query {
+        id,
+        server,
+        send_time : now,
+        retries : 0,
+    });
+    Ok(None)
+}
+
+/// 由定时器中断调用，取出应答、重发超时的查询、清理过期缓存
+pub fn tick() {
+    let now = get_million_time();
+    let mut resolver = unsafe {
+        if let Some(r) = RESOLVER.as_mut() { r.lock() } else { return; }
+    };
+    resolver.poll(now);
+    let timeout : Vec<String> = resolver.pending.iter()
+        .filter(|(_, q)| q.send_time + RETRY_INTERVAL <= now).map(|(n, _)| n.clone()).collect();
+    for name in timeout {
+        let query = resolver.pending.get_mut(&name).unwrap();
+        query.retries += 1;
+        query.send_time = now;
+        let (id, server, retries) = (query.id, query.server, query.retries);
+        if retries > MAX_RETRIES || resolver.send_query(&name, server, id).is_err() {
+            resolver.pending.remove(&name);
+            resolver.insert_cache(name, CacheEntry { addr : None, expire : now + NEGATIVE_TTL }, now);
+        }
+    }
+    resolver.expire(now);
+}
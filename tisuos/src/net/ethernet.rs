@@ -0,0 +1,47 @@
+//! # 以太网
+//! Ethernet II 帧的解析与构建，不包含 FCS（由设备处理）
+//!
+//! 2021年6月9日 zg
+
+use alloc::prelude::v1::*;
+use super::address::MacAddress;
+
+pub const ETHERTYPE_IPV4 : u16 = 0x0800;
+pub const ETHERTYPE_ARP : u16 = 0x0806;
+pub const HEADER_LEN : usize = 14;
+/// 不含 FCS 的最短帧长，不足时补零
+const MIN_FRAME_LEN : usize = 60;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EthernetHeader {
+    pub dst : MacAddress,
+    pub src : MacAddress,
+    pub ethertype : u16,
+}
+
+impl EthernetHeader {
+    /// 返回头部与负载，帧长度不足时返回 None
+    pub fn parse(frame : &[u8])->Option<(Self, &[u8])> {
+        if frame.len() < HEADER_LEN {
+            return None;
+        }
+        let rt = Self {
+            dst : MacAddress::from_slice(&frame[0..6]),
+            src : MacAddress::from_slice(&frame[6..12]),
+            ethertype : u16::from_be_bytes([frame[12], frame[13]]),
+        };
+        Some((rt, &frame[HEADER_LEN..]))
+    }
+
+    pub fn build(&self, payload : &[u8])->Vec<u8> {
+        let mut rt = Vec::with_capacity(MIN_FRAME_LEN.max(HEADER_LEN + payload.len()));
+        rt.extend_from_slice(&self.dst.0);
+        rt.extend_from_slice(&self.src.0);
+        rt.extend_from_slice(&self.ethertype.to_be_bytes());
+        rt.extend_from_slice(payload);
+        while rt.len() < MIN_FRAME_LEN {
+            rt.push(0);
+        }
+        rt
+    }
+}
@@ -0,0 +1,102 @@
+//! # ICMP
+//! 回应请求与回应应答。收到请求时原样回复，收到应答时记录往返时间供 ping 查询
+//!
+//! 2021年6月10日 zg
+
+use alloc::{collections::BTreeMap, prelude::v1::*};
+use tisu_sync::ContentMutex;
+use crate::interrupt::timer::get_million_time;
+use super::{address::Ipv4Address, checksum::checksum, flush, interface::Interface, interface_op, ipv4::{self, Ipv4Header, PROTOCOL_ICMP}};
+
+const TYPE_ECHO_REPLY : u8 = 0;
+const TYPE_ECHO_REQUEST : u8 = 8;
+const HEADER_LEN : usize = 8;
+/// 本机发出的回应请求使用的标识
+const PING_IDENT : u16 = 0x7475;
+const PING_DATA_LEN : usize = 56;
+
+/// 序号 -> 发送时间
+static mut PING_SEND : Option<ContentMutex<BTreeMap<u16, usize>>> = None;
+/// 序号 ->（往返时间，TTL）
+static mut PING_REPLY : Option<ContentMutex<BTreeMap<u16, (usize, u8)>>> = None;
+
+pub fn init() {
+    unsafe {
+        PING_SEND = Some(ContentMutex::new(BTreeMap::new(), true));
+        PING_REPLY = Some(ContentMutex::new(BTreeMap::new(), true));
+    }
+}
+
+fn build(icmp_type : u8, ident : u16, seq : u16, data : &[u8])->Vec<u8> {
+    let mut rt = Vec::with_capacity(HEADER_LEN + data.len());
+    rt.push(icmp_type);
+    rt.push(0);
+    rt.extend_from_slice(&[0, 0]);
+    rt.extend_from_slice(&ident.to_be_bytes());
+    rt.extend_from_slice(&seq.to_be_bytes());
+    rt.extend_from_slice(data);
+    let sum = checksum(&rt);
+    rt[2..4].copy_from_slice(&sum.to_be_bytes());
+    rt
+}
+
+pub fn receive(ifaces : &mut Vec<Interface>, idx : usize, header : &Ipv4Header, data : &[u8]) {
+    if data.len() < HEADER_LEN {
+        ifaces[idx].stats.rx_dropped += 1;
+        return;
+    }
+    if checksum(data) != 0 {
+        ifaces[idx].stats.checksum_errors += 1;
+        return;
+    }
+    let ident = u16::from_be_bytes([data[4], data[5]]);
+    let seq = u16::from_be_bytes([data[6], data[7]]);
+    match data[0] {
+        TYPE_ECHO_REQUEST => {
+            let reply = build(TYPE_ECHO_REPLY, ident, seq, &data[HEADER_LEN..]);
+            ipv4::send(ifaces, header.src, PROTOCOL_ICMP, &reply).ok();
+        }
+        TYPE_ECHO_REPLY if ident == PING_IDENT => {
+            let now = get_million_time();
+            unsafe {
+                if let Some(send) = PING_SEND.as_mut().unwrap().lock().remove(&seq) {
+                    PING_REPLY.as_mut().unwrap().lock().insert(seq, (now - send, header.ttl));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// ## 发送回应请求
+/// 没有路由时返回错误，结果通过 ping_result 查询
+pub fn ping(dst : Ipv4Address, seq : u16)->Result<(), ()> {
+    let mut data = Vec::with_capacity(PING_DATA_LEN);
+    for i in 0..PING_DATA_LEN {
+        data.push(i as u8);
+    }
+    let request = build(TYPE_ECHO_REQUEST, PING_IDENT, seq, &data);
+    unsafe {
+        PING_REPLY.as_mut().unwrap().lock().remove(&seq);
+        PING_SEND.as_mut().unwrap().lock().insert(seq, get_million_time());
+    }
+    interface_op(|ifaces| {
+        let rt = ipv4::send(ifaces, dst, PROTOCOL_ICMP, &request);
+        flush(ifaces);
+        rt
+    }).unwrap_or(Err(()))
+}
+
+/// 返回（往返时间，TTL），还没有收到应答时返回 None
+pub fn ping_result(seq : u16)->Option<(usize, u8)> {
+    unsafe {
+        PING_REPLY.as_mut().unwrap().lock().remove(&seq)
+    }
+}
+
+/// 放弃等待，避免超时的请求一直留在表中
+pub fn ping_cancel(seq : u16) {
+    unsafe {
+        PING_SEND.as_mut().unwrap().lock().remove(&seq);
+    }
+}
@@ -0,0 +1,245 @@
+//! # 网络接口
+//! 每个 virtio-net 设备对应一个接口，负责链路层：以太网帧的收发、ARP 以及发送队列
+//! 中断处理与系统调用产生的帧先进入发送队列，flush 时交给设备
+//! 地址配置默认由 DHCP 获取，也可以通过 ifconfig 系统调用改为静态配置
+//! 回环接口 lo 没有设备，发送队列中存放的是 IP 数据报，由 net::flush 送回 IP 层
+//!
+//! 2021年6月9日 zg
+
+use alloc::{collections::VecDeque, prelude::v1::*};
+use crate::{interrupt::timer::get_million_time, virtio::device::get_device};
+use super::{address::{Ipv4Address, MacAddress}, arp::{ArpCache, ArpEntry, ArpPacket, OPER_REPLY, OPER_REQUEST}, dhcp::{DhcpClient, DhcpState}, ethernet::{ETHERTYPE_ARP, ETHERTYPE_IPV4, EthernetHeader}, pcap};
+
+/// 发送队列的最大长度，超出时丢弃新的帧
+const TX_QUEUE_LEN : usize = 256;
+const NAME_LEN : usize = 8;
+const LOOPBACK_IP : Ipv4Address = Ipv4Address::new(127, 0, 0, 1);
+const LOOPBACK_NETMASK : Ipv4Address = Ipv4Address::new(255, 0, 0, 0);
+
+/// ## 接口配置
+/// 与用户程序共享的布局，名字与 MAC 地址只读
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterfaceConfig {
+    pub name : [u8;NAME_LEN],
+    pub mac : [u8;6],
+    pub dhcp : bool,
+    pub ip : Ipv4Address,
+    pub netmask : Ipv4Address,
+    pub gateway : Ipv4Address,
+    pub dns : Ipv4Address,
+}
+
+impl InterfaceConfig {
+    pub fn name(&self)->&str {
+        let len = self.name.iter().position(|c| *c == 0).unwrap_or(NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+}
+
+/// ## 接口计数
+/// 与用户程序共享的布局，ARP 缓存项数在查询时填入
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterfaceStats {
+    pub rx_frames : usize,
+    pub rx_bytes : usize,
+    pub tx_frames : usize,
+    pub tx_bytes : usize,
+    /// 格式错误、协议未知或者没有对应套接字而丢弃的帧
+    pub rx_dropped : usize,
+    /// 发送队列已满而丢弃的帧
+    pub tx_dropped : usize,
+    pub checksum_errors : usize,
+    pub arp_entries : usize,
+}
+
+pub struct Interface {
+    pub name : String,
+    /// 回环接口没有设备
+    device : Option<usize>,
+    pub mac : MacAddress,
+    pub ip : Ipv4Address,
+    pub netmask : Ipv4Address,
+    pub gateway : Ipv4Address,
+    pub dns : Ipv4Address,
+    pub dhcp : DhcpClient,
+    arp : ArpCache,
+    tx_queue : VecDeque<Vec<u8>>,
+    pub stats : InterfaceStats,
+}
+
+impl Interface {
+    /// 新建的接口没有地址，由 DHCP 或者 ifconfig 配置
+    pub fn new(name : String, device : usize, mac : MacAddress)->Self {
+        Self {
+            name,
+            device : Some(device),
+            mac,
+            ip : Ipv4Address::UNSPECIFIED,
+            netmask : Ipv4Address::UNSPECIFIED,
+            gateway : Ipv4Address::UNSPECIFIED,
+            dns : Ipv4Address::UNSPECIFIED,
+            dhcp : DhcpClient::new(),
+            arp : ArpCache::new(),
+            tx_queue : VecDeque::new(),
+            stats : InterfaceStats::default(),
+        }
+    }
+
+    /// 回环接口，地址固定为 127.0.0.1
+    pub fn loopback()->Self {
+        let mut rt = Self::new("lo".to_string(), 0, MacAddress::ZERO);
+        rt.device = None;
+        rt.ip = LOOPBACK_IP;
+        rt.netmask = LOOPBACK_NETMASK;
+        rt
+    }
+
+    pub fn device(&self)->Option<usize> {
+        self.device
+    }
+
+    pub fn is_loopback(&self)->bool {
+        self.device.is_none()
+    }
+
+    pub fn config(&self)->InterfaceConfig {
+        let mut name = [0;NAME_LEN];
+        let len = self.name.len().min(NAME_LEN);
+        name[..len].copy_from_slice(&self.name.as_bytes()[..len]);
+        InterfaceConfig {
+            name,
+            mac : self.mac.0,
+            dhcp : self.dhcp.state != DhcpState::Disabled,
+            ip : self.ip,
+            netmask : self.netmask,
+            gateway : self.gateway,
+            dns : self.dns,
+        }
+    }
+
+    pub fn stats(&self)->InterfaceStats {
+        let mut rt = self.stats;
+        rt.arp_entries = self.arp.entries().len();
+        rt
+    }
+
+    /// 设置静态地址，同时停止 DHCP
+    pub fn set_config(&mut self, config : &InterfaceConfig) {
+        self.dhcp.state = DhcpState::Disabled;
+        self.ip = config.ip;
+        self.netmask = config.netmask;
+        self.gateway = config.gateway;
+        self.dns = config.dns;
+    }
+
+    /// 构建以太网帧放入发送队列
+    pub fn send_frame(&mut self, dst : MacAddress, ethertype : u16, payload : &[u8]) {
+        if self.tx_queue.len() >= TX_QUEUE_LEN {
+            self.stats.tx_dropped += 1;
+            return;
+        }
+        let head = EthernetHeader {
+            dst,
+            src : self.mac,
+            ethertype,
+        };
+        self.tx_queue.push_back(head.build(payload));
+    }
+
+    /// ## 发送到同一链路上的地址
+    /// MAC 地址未知时先挂起并发送 ARP 请求
+    pub fn send_to(&mut self, next_hop : Ipv4Address, ethertype : u16, payload : Vec<u8>) {
+        if self.is_loopback() {
+            if ethertype != ETHERTYPE_IPV4 || self.tx_queue.len() >= TX_QUEUE_LEN {
+                self.stats.tx_dropped += 1;
+            }
+            else {
+                self.stats.tx_frames += 1;
+                self.stats.tx_bytes += payload.len();
+                self.tx_queue.push_back(payload);
+            }
+            return;
+        }
+        if next_hop == Ipv4Address::BROADCAST {
+            self.send_frame(MacAddress::BROADCAST, ethertype, &payload);
+            return;
+        }
+        let now = get_million_time();
+        if let Some(mac) = self.arp.lookup(next_hop, now) {
+            self.send_frame(mac, ethertype, &payload);
+        }
+        else if self.arp.wait(next_hop, ethertype, payload, now) {
+            self.arp_request(next_hop);
+        }
+    }
+
+    /// 处理 ARP 报文：记录发送者地址，回复发给本机的请求
+    pub fn receive_arp(&mut self, data : &[u8]) {
+        let packet = if let Some(p) = ArpPacket::parse(data) { p } else { return; };
+        let now = get_million_time();
+        if !packet.sender_ip.is_unspecified() {
+            for (ethertype, payload) in self.arp.update(packet.sender_ip, packet.sender_mac, now) {
+                self.send_frame(packet.sender_mac, ethertype, &payload);
+            }
+        }
+        if packet.oper == OPER_REQUEST && packet.target_ip == self.ip && !self.ip.is_unspecified() {
+            let reply = ArpPacket {
+                oper : OPER_REPLY,
+                sender_mac : self.mac,
+                sender_ip : self.ip,
+                target_mac : packet.sender_mac,
+                target_ip : packet.sender_ip,
+            };
+            self.send_frame(packet.sender_mac, ETHERTYPE_ARP, &reply.build());
+        }
+    }
+
+    /// 重发 ARP 请求，清理过期缓存
+    pub fn tick(&mut self) {
+        for ip in self.arp.tick(get_million_time()) {
+            self.arp_request(ip);
+        }
+    }
+
+    /// 把发送队列中的帧交给设备，回环接口的数据报留给 loopback_packet 取出
+    pub fn flush(&mut self) {
+        let device = if let Some(device) = self.device { device } else { return; };
+        let net = get_device().net_device.get_mut(device).unwrap();
+        while let Some(frame) = self.tx_queue.pop_front() {
+            pcap::capture(&frame);
+            self.stats.tx_frames += 1;
+            self.stats.tx_bytes += frame.len();
+            net.send(&frame);
+        }
+    }
+
+    /// 取出回环接口发送队列中的数据报
+    pub fn loopback_packet(&mut self)->Option<Vec<u8>> {
+        if self.is_loopback() {
+            let rt = self.tx_queue.pop_front()?;
+            self.stats.rx_frames += 1;
+            self.stats.rx_bytes += rt.len();
+            Some(rt)
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn arp_entries(&self)->Vec<(Ipv4Address, ArpEntry)> {
+        self.arp.entries()
+    }
+
+    fn arp_request(&mut self, ip : Ipv4Address) {
+        let request = ArpPacket {
+            oper : OPER_REQUEST,
+            sender_mac : self.mac,
+            sender_ip : self.ip,
+            target_mac : MacAddress::ZERO,
+            target_ip : ip,
+        };
+        self.send_frame(MacAddress::BROADCAST, ETHERTYPE_ARP, &request.build());
+    }
+}
@@ -0,0 +1,298 @@
+//! # IPv4
+//! 头部的解析、校验与构建，路由（本地子网直接发送，其余交给默认网关），分片与重组
+//! 收到的数据报按协议号交给上层，目前支持 ICMP、UDP、TCP
+//!
+//! 2021年6月10日 zg
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::{collections::BTreeMap, prelude::v1::*};
+use tisu_sync::ContentMutex;
+use crate::interrupt::timer::get_million_time;
+use super::{address::Ipv4Address, checksum::checksum, ethernet::ETHERTYPE_IPV4, icmp, interface::Interface, tcp, udp};
+
+pub const PROTOCOL_ICMP : u8 = 1;
+pub const PROTOCOL_TCP : u8 = 6;
+pub const PROTOCOL_UDP : u8 = 17;
+pub const HEADER_LEN : usize = 20;
+pub const MTU : usize = 1500;
+const DEFAULT_TTL : u8 = 64;
+const FLAG_MORE_FRAGMENT : u16 = 0x2000;
+const FLAG_DONT_FRAGMENT : u16 = 0x4000;
+const OFFSET_MASK : u16 = 0x1fff;
+/// 重组超时，单位毫秒
+const REASSEMBLY_TIMEOUT : usize = 30_000;
+const MAX_REASSEMBLY : usize = 16;
+
+static IDENT : AtomicUsize = AtomicUsize::new(1);
+static mut REASSEMBLY : Option<ContentMutex<BTreeMap<(Ipv4Address, u16, u8), Reassembly>>> = None;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4Header {
+    pub tos : u8,
+    pub total_len : u16,
+    pub ident : u16,
+    pub flags_offset : u16,
+    pub ttl : u8,
+    pub protocol : u8,
+    pub src : Ipv4Address,
+    pub dst : Ipv4Address,
+}
+
+impl Ipv4Header {
+    pub fn new(src : Ipv4Address, dst : Ipv4Address, protocol : u8, payload_len : usize)->Self {
+        Self {
+            tos : 0,
+            total_len : (HEADER_LEN + payload_len) as u16,
+            ident : IDENT.fetch_add(1, Ordering::SeqCst) as u16,
+            flags_offset : 0,
+            ttl : DEFAULT_TTL,
+            protocol,
+            src,
+            dst,
+        }
+    }
+
+    /// ## 解析并校验头部
+    /// 版本、头部长度、校验和、总长度任一不符时返回 None，返回的负载已去掉链路层填充
+    pub fn parse(data : &[u8])->Option<(Self, &[u8])> {
+        if data.len() < HEADER_LEN || data[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = (data[0] & 0xf) as usize * 4;
+        let total_len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        if header_len < HEADER_LEN || total_len < header_len || total_len > data.len() {
+            return None;
+        }
+        if checksum(&data[..header_len]) != 0 {
+            return None;
+        }
+        let rt = Self {
+            tos : data[1],
+            total_len : total_len as u16,
+            ident : u16::from_be_bytes([data[4], data[5]]),
+            flags_offset : u16::from_be_bytes([data[6], data[7]]),
+            ttl : data[8],
+            protocol : data[9],
+            src : Ipv4Address::from_slice(&data[12..16]),
+            dst : Ipv4Address::from_slice(&data[16..20]),
+        };
+        Some((rt, &data[header_len..total_len]))
+    }
+
+    /// 不带选项的头部，校验和在这里计算
+    pub fn build(&self, payload : &[u8])->Vec<u8> {
+        let mut rt = Vec::with_capacity(HEADER_LEN + payload.len());
+        rt.push(0x45);
+        rt.push(self.tos);
+        rt.extend_from_slice(&self.total_len.to_be_bytes());
+        rt.extend_from_slice(&self.ident.to_be_bytes());
+        rt.extend_from_slice(&self.flags_offset.to_be_bytes());
+        rt.push(self.ttl);
+        rt.push(self.protocol);
+        rt.extend_from_slice(&[0, 0]);
+        rt.extend_from_slice(&self.src.0);
+        rt.extend_from_slice(&self.dst.0);
+        let sum = checksum(&rt);
+        rt[10..12].copy_from_slice(&sum.to_be_bytes());
+        rt.extend_from_slice(payload);
+        rt
+    }
+
+    pub fn more_fragment(&self)->bool {
+        self.flags_offset & FLAG_MORE_FRAGMENT != 0
+    }
+
+    /// 分片偏移，单位字节
+    pub fn fragment_offset(&self)->usize {
+        (self.flags_offset & OFFSET_MASK) as usize * 8
+    }
+
+    pub fn is_fragment(&self)->bool {
+        self.more_fragment() || self.fragment_offset() != 0
+    }
+}
+
+/// 同一数据报的分片集合
+struct Reassembly {
+    fragment : Vec<(usize, Vec<u8>)>,
+    /// 收到最后一个分片后才知道总长度
+    total : Option<usize>,
+    expire : usize,
+}
+
+impl Reassembly {
+    fn new(now : usize)->Self {
+        Self {
+            fragment : Vec::new(),
+            total : None,
+            expire : now + REASSEMBLY_TIMEOUT,
+        }
+    }
+
+    /// 所有分片到齐时返回完整的负载
+    /// 超出已知总长度的分片、与已有分片矛盾的最后一个分片直接丢弃
+    fn push(&mut self, header : &Ipv4Header, data : &[u8])->Option<Vec<u8>> {
+        let offset = header.fragment_offset();
+        let end = offset + data.len();
+        if let Some(total) = self.total {
+            if end > total || (!header.more_fragment() && end != total) {
+                return None;
+            }
+        }
+        else if !header.more_fragment() {
+            if self.fragment.iter().any(|(off, d)| off + d.len() > end) {
+                return None;
+            }
+            self.total = Some(end);
+        }
+        if self.fragment.iter().all(|(off, _)| *off != offset) {
+            self.fragment.push((offset, data.to_vec()));
+        }
+        let total = self.total?;
+        self.fragment.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut end = 0;
+        for (off, d) in self.fragment.iter() {
+            if *off > end {
+                return None;
+            }
+            end = end.max(off + d.len());
+        }
+        if end < total {
+            return None;
+        }
+        let mut rt = vec![0u8;total];
+        for (off, d) in self.fragment.iter() {
+            if *off >= total {
+                continue;
+            }
+            let len = d.len().min(total - off);
+            rt[*off..*off + len].copy_from_slice(&d[..len]);
+        }
+        Some(rt)
+    }
+}
+
+pub fn init() {
+    unsafe {
+        REASSEMBLY = Some(ContentMutex::new(BTreeMap::new(), true));
+    }
+}
+
+/// ## 路由
+/// 返回（接口下标，下一跳地址），目的地址位于接口子网内时直接发送，否则交给网关
+pub fn route(ifaces : &[Interface], dst : Ipv4Address)->Option<(usize, Ipv4Address)> {
+    if dst == Ipv4Address::BROADCAST {
+        return ifaces.iter().position(|iface| !iface.is_loopback()).map(|idx| (idx, dst));
+    }
+    for (idx, iface) in ifaces.iter().enumerate() {
+        if !iface.ip.is_unspecified() && dst.same_subnet(&iface.ip, &iface.netmask) {
+            return Some((idx, dst));
+        }
+    }
+    for (idx, iface) in ifaces.iter().enumerate() {
+        if !iface.gateway.is_unspecified() {
+            return Some((idx, iface.gateway));
+        }
+    }
+    None
+}
+
+/// ## 发送数据报
+/// 超过 MTU 时分片，没有路由时返回错误
+pub fn send(ifaces : &mut Vec<Interface>, dst : Ipv4Address, protocol : u8, payload : &[u8])->Result<(), ()> {
+    let (idx, next_hop) = route(ifaces, dst).ok_or(())?;
+    send_via(&mut ifaces[idx], next_hop, dst, protocol, payload);
+    Ok(())
+}
+
+/// 从指定接口发给下一跳，超过 MTU 时分片
+pub fn send_via(iface : &mut Interface, next_hop : Ipv4Address, dst : Ipv4Address, protocol : u8, payload : &[u8]) {
+    let header = Ipv4Header::new(iface.ip, dst, protocol, payload.len());
+    if HEADER_LEN + payload.len() <= MTU {
+        iface.send_to(next_hop, ETHERTYPE_IPV4, header.build(payload));
+        return;
+    }
+    // 除最后一片外，分片长度必须是 8 的倍数
+    let max_len = (MTU - HEADER_LEN) / 8 * 8;
+    let mut offset = 0;
+    while offset < payload.len() {
+        let len = max_len.min(payload.len() - offset);
+        let mut h = header;
+        h.total_len = (HEADER_LEN + len) as u16;
+        h.flags_offset = (offset / 8) as u16;
+        if offset + len < payload.len() {
+            h.flags_offset |= FLAG_MORE_FRAGMENT;
+        }
+        iface.send_to(next_hop, ETHERTYPE_IPV4, h.build(&payload[offset..offset + len]));
+        offset += len;
+    }
+}
+
+/// ## 接收数据报
+/// 只接受发给本接口地址或广播地址的数据报，接口还没有地址时全部接受以便完成 DHCP
+/// 分片先重组再交给上层
+pub fn receive(ifaces : &mut Vec<Interface>, idx : usize, data : &[u8]) {
+    let (header, payload) = if let Some(rt) = Ipv4Header::parse(data) {
+        rt
+    }
+    else {
+        ifaces[idx].stats.rx_dropped += 1;
+        return;
+    };
+    let iface = &ifaces[idx];
+    if !iface.ip.is_unspecified() && header.dst != iface.ip && header.dst != Ipv4Address::BROADCAST
+        && header.dst != iface.ip.subnet_broadcast(&iface.netmask) {
+        return;
+    }
+    if header.is_fragment() {
+        if let Some(payload) = reassemble(&header, payload) {
+            deliver(ifaces, idx, &header, &payload);
+        }
+    }
+    else {
+        deliver(ifaces, idx, &header, payload);
+    }
+}
+
+/// 清理超时的重组
+pub fn tick() {
+    let now = get_million_time();
+    unsafe {
+        if let Some(r) = REASSEMBLY.as_mut() {
+            let mut r = r.lock();
+            let expired : Vec<(Ipv4Address, u16, u8)> = r.iter()
+                .filter(|(_, v)| v.expire <= now).map(|(k, _)| *k).collect();
+            for key in expired {
+                r.remove(&key);
+            }
+        }
+    }
+}
+
+fn reassemble(header : &Ipv4Header, data : &[u8])->Option<Vec<u8>> {
+    if header.flags_offset & FLAG_DONT_FRAGMENT != 0 {
+        return None;
+    }
+    let key = (header.src, header.ident, header.protocol);
+    let mut r = unsafe {REASSEMBLY.as_mut()?.lock()};
+    if !r.contains_key(&key) {
+        if r.len() >= MAX_REASSEMBLY {
+            return None;
+        }
+        r.insert(key, Reassembly::new(get_million_time()));
+    }
+    let rt = r.get_mut(&key).unwrap().push(header, data);
+    if rt.is_some() {
+        r.remove(&key);
+    }
+    rt
+}
+
+fn deliver(ifaces : &mut Vec<Interface>, idx : usize, header : &Ipv4Header, payload : &[u8]) {
+    match header.protocol {
+        PROTOCOL_ICMP => icmp::receive(ifaces, idx, header, payload),
+        PROTOCOL_UDP => udp::receive(ifaces, idx, header, payload),
+        PROTOCOL_TCP => tcp::receive(ifaces, idx, header, payload),
+        _ => ifaces[idx].stats.rx_dropped += 1,
+    }
+}
@@ -0,0 +1,169 @@
+//! # 网络
+//! 基于 virtio-net 的协议栈，目前包括链路层（以太网、ARP）、IPv4、ICMP、UDP 与 TCP
+//! 设备中断收到的帧交给 receive，根据以太网类型分发；定时器中断调用 tick 处理超时
+//! 回环接口 lo 总是存在，没有网卡时本机进程之间也可以通过 127.0.0.1 通信
+//!
+//! 2021年6月9日 zg
+
+pub mod address;
+pub mod arp;
+pub mod checksum;
+pub mod dhcp;
+pub mod dns;
+pub mod ethernet;
+pub mod icmp;
+pub mod interface;
+pub mod ipv4;
+pub mod pcap;
+pub mod tcp;
+pub mod udp;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::prelude::v1::*;
+use tisu_sync::ContentMutex;
+use crate::virtio::device::get_device;
+use self::{address::{Ipv4Address, MacAddress}, ethernet::{ETHERTYPE_ARP, ETHERTYPE_IPV4, EthernetHeader}, interface::Interface};
+
+static mut INTERFACE : Option<ContentMutex<Vec<Interface>>> = None;
+/// 流套接字类型
+pub const SOCK_STREAM : usize = 1;
+/// 数据报套接字类型
+pub const SOCK_DGRAM : usize = 2;
+/// 套接字 ID，各协议共用
+static SOCKET_ID : AtomicUsize = AtomicUsize::new(1);
+/// 一次 flush 最多送回 IP 层的回环数据报数量，其余留到下一次
+const LOOPBACK_BUDGET : usize = 256;
+
+/// ## 套接字信息
+/// 与用户程序共享的布局，UDP 套接字的状态为 0，TCP 套接字的状态为 TcpState 的序号
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketInfo {
+    pub id : usize,
+    pub owner : usize,
+    pub socket_type : usize,
+    pub state : usize,
+    pub local_ip : Ipv4Address,
+    pub local_port : u16,
+    pub remote_ip : Ipv4Address,
+    pub remote_port : u16,
+    /// 接收队列中的数据报数量或者字节数
+    pub recv_queue : usize,
+    pub send_queue : usize,
+    /// 接收队列已满而丢弃的数据报
+    pub dropped : usize,
+}
+
+/// 在设备初始化之后调用，建立回环接口并为每个网卡建立接口
+pub fn init() {
+    pcap::init();
+    let mut v = vec![Interface::loopback()];
+    for (idx, dev) in get_device().net_device.iter().enumerate() {
+        let mac = MacAddress::from_val(dev.mac() as usize);
+        let mut iface = Interface::new(format!("eth{}", idx), idx, mac);
+        println!("{} mac {}", iface.name, iface.mac);
+        dhcp::start(&mut iface);
+        v.push(iface);
+    }
+    unsafe {
+        INTERFACE = Some(ContentMutex::new(v, true));
+    }
+    ipv4::init();
+    icmp::init();
+    udp::init();
+    tcp::init();
+    dns::init();
+}
+
+/// 对所有接口进行操作
+pub fn interface_op<F, R>(f : F)->Option<R> where F : FnOnce(&mut Vec<Interface>)->R {
+    unsafe {
+        let mut iface = INTERFACE.as_mut()?.lock();
+        Some(f(&mut *iface))
+    }
+}
+
+/// ## 接收设备上的帧
+/// 目的地址不是本机也不是广播的帧直接丢弃
+pub fn receive(device : usize, frame : &[u8]) {
+    pcap::capture(frame);
+    let (head, payload) = if let Some(rt) = EthernetHeader::parse(frame) {
+        rt
+    }
+    else {
+        return;
+    };
+    interface_op(|ifaces| {
+        let idx = if let Some(idx) = ifaces.iter().position(|iface| iface.device() == Some(device)) {
+            idx
+        }
+        else {
+            return;
+        };
+        if head.dst != ifaces[idx].mac && !head.dst.is_broadcast() {
+            return;
+        }
+        ifaces[idx].stats.rx_frames += 1;
+        ifaces[idx].stats.rx_bytes += frame.len();
+        match head.ethertype {
+            ETHERTYPE_ARP => ifaces[idx].receive_arp(payload),
+            ETHERTYPE_IPV4 => ipv4::receive(ifaces, idx, payload),
+            _ => ifaces[idx].stats.rx_dropped += 1,
+        }
+        flush(ifaces);
+    });
+}
+
+/// 由定时器中断调用
+pub fn tick() {
+    interface_op(|ifaces| {
+        for iface in ifaces.iter_mut() {
+            iface.tick();
+            dhcp::tick(iface);
+        }
+        flush(ifaces);
+    });
+    ipv4::tick();
+    tcp::tick();
+    dns::tick();
+}
+
+/// ## 发送所有接口队列中的帧
+/// 回环接口的数据报直接交给 IP 层接收，处理过程中产生的应答也会在这里送出
+pub fn flush(ifaces : &mut Vec<Interface>) {
+    let lo = ifaces.iter().position(|iface| iface.is_loopback());
+    let mut budget = LOOPBACK_BUDGET;
+    loop {
+        for iface in ifaces.iter_mut() {
+            iface.flush();
+        }
+        let lo = if let Some(lo) = lo { lo } else { return; };
+        let packet = if let Some(p) = ifaces[lo].loopback_packet() { p } else { return; };
+        ipv4::receive(ifaces, lo, &packet);
+        budget -= 1;
+        if budget == 0 {
+            return;
+        }
+    }
+}
+
+pub fn alloc_socket_id()->usize {
+    SOCKET_ID.fetch_add(1, Ordering::SeqCst)
+}
+
+pub fn is_socket_own(id : usize, owner : usize)->bool {
+    udp::is_own(id, owner) || tcp::is_own(id, owner)
+}
+
+/// 所有套接字的信息，先 UDP 后 TCP
+pub fn sockets()->Vec<SocketInfo> {
+    let mut rt = udp::sockets();
+    rt.append(&mut tcp::sockets());
+    rt
+}
+
+/// 关闭套接字，进程退出时由资源表调用
+pub fn close_socket(id : usize) {
+    udp::close(id);
+    tcp::close(id);
+}
@@ -0,0 +1,137 @@
+//! # 抓包
+//! 把网卡收发的每个以太网帧复制到环形缓冲区，需要时导出为 pcap 格式，可以直接用 Wireshark 打开
+//! 缓冲区按字节数限制大小，满了之后丢弃最早的帧。回环接口不经过网卡，不会被记录
+//!
+//! 2021年6月15日 zg
+
+use alloc::{collections::VecDeque, prelude::v1::*};
+use tisu_sync::ContentMutex;
+use crate::interrupt::timer::{FREQUENCY, get_micro_time};
+
+const MAGIC : u32 = 0xa1b2c3d4;
+const VERSION_MAJOR : u16 = 2;
+const VERSION_MINOR : u16 = 4;
+/// 单帧最多保存的字节数
+const SNAPLEN : usize = 65535;
+const LINKTYPE_ETHERNET : u32 = 1;
+const FILE_HEADER_LEN : usize = 24;
+const RECORD_HEADER_LEN : usize = 16;
+/// 环形缓冲区保存的帧数据总量上限
+const BUFFER_SIZE : usize = 256 * 1024;
+
+static mut CAPTURE : Option<ContentMutex<Capture>> = None;
+
+struct Record {
+    /// 启动以来的微秒数
+    time : usize,
+    orig_len : usize,
+    data : Vec<u8>,
+}
+
+struct Capture {
+    enabled : bool,
+    record : VecDeque<Record>,
+    size : usize,
+    /// 因缓冲区已满被丢弃的帧数
+    overwritten : usize,
+}
+
+/// ## 抓包状态
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureStatus {
+    pub enabled : bool,
+    pub count : usize,
+    /// 导出为 pcap 文件时的长度
+    pub file_size : usize,
+    pub overwritten : usize,
+}
+
+pub fn init() {
+    unsafe {
+        CAPTURE = Some(ContentMutex::new(Capture {
+            enabled : false,
+            record : VecDeque::new(),
+            size : 0,
+            overwritten : 0,
+        }, true));
+    }
+}
+
+fn capture_op<F, R>(f : F)->Option<R> where F : FnOnce(&mut Capture)->R {
+    unsafe {
+        let mut capture = CAPTURE.as_mut()?.lock();
+        Some(f(&mut *capture))
+    }
+}
+
+pub fn start() {
+    capture_op(|c| c.enabled = true);
+}
+
+pub fn stop() {
+    capture_op(|c| c.enabled = false);
+}
+
+pub fn clear() {
+    capture_op(|c| {
+        c.record.clear();
+        c.size = 0;
+        c.overwritten = 0;
+    });
+}
+
+pub fn status()->CaptureStatus {
+    capture_op(|c| CaptureStatus {
+        enabled : c.enabled,
+        count : c.record.len(),
+        file_size : FILE_HEADER_LEN + c.record.len() * RECORD_HEADER_LEN + c.size,
+        overwritten : c.overwritten,
+    }).unwrap()
+}
+
+/// 记录一个收到或发出的帧，由接口在与设备交互时调用
+pub fn capture(frame : &[u8]) {
+    capture_op(|c| {
+        if !c.enabled {
+            return;
+        }
+        let len = frame.len().min(SNAPLEN);
+        while c.size + len > BUFFER_SIZE {
+            if let Some(r) = c.record.pop_front() {
+                c.size -= r.data.len();
+                c.overwritten += 1;
+            }
+            else {
+                return;
+            }
+        }
+        c.size += len;
+        c.record.push_back(Record {
+            time : get_micro_time() / (FREQUENCY / 1_000_000),
+            orig_len : frame.len(),
+            data : frame[..len].to_vec(),
+        });
+    });
+}
+
+/// ## 导出 pcap 文件
+/// 小端序，时间戳从系统启动开始计算
+pub fn file()->Vec<u8> {
+    capture_op(|c| {
+        let mut rt = Vec::with_capacity(FILE_HEADER_LEN + c.record.len() * RECORD_HEADER_LEN + c.size);
+        rt.extend_from_slice(&MAGIC.to_le_bytes());
+        rt.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        rt.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        rt.extend_from_slice(&[0;8]);
+        rt.extend_from_slice(&(SNAPLEN as u32).to_le_bytes());
+        rt.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        for r in c.record.iter() {
+            rt.extend_from_slice(&((r.time / 1_000_000) as u32).to_le_bytes());
+            rt.extend_from_slice(&((r.time % 1_000_000) as u32).to_le_bytes());
+            rt.extend_from_slice(&(r.data.len() as u32).to_le_bytes());
+            rt.extend_from_slice(&(r.orig_len as u32).to_le_bytes());
+            rt.extend_from_slice(&r.data);
+        }
+        rt
+    }).unwrap_or(Vec::new())
+}
@@ -0,0 +1,891 @@
+//! # TCP
+//! 流套接字：三次握手、滑动窗口、超时重传（回退 N）、FIN/RST 断开以及监听队列
+//! 只接收按序到达的报文段，乱序的报文段丢弃并回复 ACK，由对端重传
+//! 重传、零窗口探测与 TIME_WAIT 由时钟中断经 net::tick 驱动
+//!
+//! 2021年6月12日 zg
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::{collections::{BTreeMap, VecDeque}, prelude::v1::*};
+use tisu_sync::ContentMutex;
+use crate::interrupt::timer::get_million_time;
+use super::{SOCK_STREAM, SocketInfo, address::Ipv4Address, alloc_socket_id, checksum::{finish, pseudo_header, sum}, flush, interface::Interface, interface_op, ipv4::{self, Ipv4Header, PROTOCOL_TCP}};
+
+pub const HEADER_LEN : usize = 20;
+const FLAG_FIN : u8 = 0x01;
+const FLAG_SYN : u8 = 0x02;
+const FLAG_RST : u8 = 0x04;
+const FLAG_PSH : u8 = 0x08;
+const FLAG_ACK : u8 = 0x10;
+const OPTION_END : u8 = 0;
+const OPTION_NOP : u8 = 1;
+const OPTION_MSS : u8 = 2;
+/// 对端没有给出 MSS 选项时使用的最大报文段长度
+const DEFAULT_MSS : usize = 536;
+/// 本机通告的最大报文段长度
+const LOCAL_MSS : usize = ipv4::MTU - ipv4::HEADER_LEN - HEADER_LEN;
+const SEND_BUFFER_SIZE : usize = 16 * 1024;
+const RECV_BUFFER_SIZE : usize = 16 * 1024;
+/// 重传超时，单位毫秒，每次超时加倍
+const RTO_INIT : usize = 1000;
+const RTO_MAX : usize = 60_000;
+const MAX_RETRIES : usize = 8;
+/// TIME_WAIT 持续时间（2MSL），单位毫秒
+const TIME_WAIT_TIMEOUT : usize = 4_000;
+pub const MAX_BACKLOG : usize = 16;
+const EPHEMERAL_START : u16 = 49152;
+
+static ISN : AtomicUsize = AtomicUsize::new(0);
+static mut SOCKET : Option<ContentMutex<SocketTable>> = None;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+const STATE_NAME : [&str;11] = ["CLOSED", "LISTEN", "SYN_SENT", "SYN_RECEIVED", "ESTABLISHED",
+    "FIN_WAIT1", "FIN_WAIT2", "CLOSE_WAIT", "CLOSING", "LAST_ACK", "TIME_WAIT"];
+
+/// SocketInfo 中的状态序号对应的名字
+pub fn state_name(state : usize)->&'static str {
+    STATE_NAME.get(state).cloned().unwrap_or("UNKNOWN")
+}
+
+/// 序号比较，考虑回绕
+fn seq_lt(a : u32, b : u32)->bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a : u32, b : u32)->bool {
+    !seq_lt(b, a)
+}
+
+/// 初始序号随时间增长，避免与旧连接的报文段混淆
+fn gen_isn()->u32 {
+    (get_million_time() * 250 + ISN.fetch_add(64000, Ordering::SeqCst)) as u32
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TcpHeader {
+    src_port : u16,
+    dst_port : u16,
+    seq : u32,
+    ack : u32,
+    flags : u8,
+    window : u16,
+    mss : Option<u16>,
+}
+
+impl TcpHeader {
+    /// 解析头部，只识别 MSS 选项
+    fn parse(data : &[u8])->Option<(Self, &[u8])> {
+        if data.len() < HEADER_LEN {
+            return None;
+        }
+        let offset = (data[12] >> 4) as usize * 4;
+        if offset < HEADER_LEN || offset > data.len() {
+            return None;
+        }
+        let option = &data[HEADER_LEN..offset];
+        let mut mss = None;
+        let mut i = 0;
+        while i < option.len() {
+            match option[i] {
+                OPTION_END => break,
+                OPTION_NOP => i += 1,
+                kind => {
+                    if i + 1 >= option.len() || option[i + 1] < 2 {
+                        break;
+                    }
+                    let len = option[i + 1] as usize;
+                    if kind == OPTION_MSS && len == 4 && i + 4 <= option.len() {
+                        mss = Some(u16::from_be_bytes([option[i + 2], option[i + 3]]));
+                    }
+                    i += len;
+                }
+            }
+        }
+        let rt = Self {
+            src_port : u16::from_be_bytes([data[0], data[1]]),
+            dst_port : u16::from_be_bytes([data[2], data[3]]),
+            seq : u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ack : u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            flags : data[13],
+            window : u16::from_be_bytes([data[14], data[15]]),
+            mss,
+        };
+        Some((rt, &data[offset..]))
+    }
+
+    fn build(&self, src : Ipv4Address, dst : Ipv4Address, payload : &[u8])->Vec<u8> {
+        let header_len = if self.mss.is_some() { HEADER_LEN + 4 } else { HEADER_LEN };
+        let len = header_len + payload.len();
+        let mut rt = Vec::with_capacity(len);
+        rt.extend_from_slice(&self.src_port.to_be_bytes());
+        rt.extend_from_slice(&self.dst_port.to_be_bytes());
+        rt.extend_from_slice(&self.seq.to_be_bytes());
+        rt.extend_from_slice(&self.ack.to_be_bytes());
+        rt.push(((header_len / 4) as u8) << 4);
+        rt.push(self.flags);
+        rt.extend_from_slice(&self.window.to_be_bytes());
+        rt.extend_from_slice(&[0, 0, 0, 0]);
+        if let Some(mss) = self.mss {
+            rt.push(OPTION_MSS);
+            rt.push(4);
+            rt.extend_from_slice(&mss.to_be_bytes());
+        }
+        rt.extend_from_slice(payload);
+        let check = finish(sum(pseudo_header(src, dst, PROTOCOL_TCP, len), &rt));
+        rt[16..18].copy_from_slice(&check.to_be_bytes());
+        rt
+    }
+
+    /// 报文段占用的序号长度，SYN、FIN 各占一个
+    fn seq_len(&self, payload_len : usize)->u32 {
+        let mut rt = payload_len as u32;
+        if self.flags & FLAG_SYN != 0 {
+            rt += 1;
+        }
+        if self.flags & FLAG_FIN != 0 {
+            rt += 1;
+        }
+        rt
+    }
+}
+
+/// ## TCP 套接字
+/// 发送缓冲区保存从 snd_una 开始的数据，包括已发送未确认和未发送的部分
+pub struct TcpSocket {
+    pub owner : usize,
+    /// 用户已经关闭，连接结束后从表中删除
+    closed : bool,
+    pub state : TcpState,
+    pub local_ip : Ipv4Address,
+    pub local_port : u16,
+    pub remote_ip : Ipv4Address,
+    pub remote_port : u16,
+    iss : u32,
+    snd_una : u32,
+    snd_nxt : u32,
+    snd_wnd : usize,
+    rcv_nxt : u32,
+    mss : usize,
+    send_buffer : VecDeque<u8>,
+    recv_buffer : VecDeque<u8>,
+    /// 用户要求关闭，数据发送完毕后发送 FIN
+    fin_queued : bool,
+    fin_sent : bool,
+    fin_received : bool,
+    ack_pending : bool,
+    rto : usize,
+    retransmit : Option<usize>,
+    retries : usize,
+    time_wait : usize,
+    /// 连接被重置或者重传超时
+    error : bool,
+    /// 监听套接字中已建立、等待 accept 的连接
+    backlog : VecDeque<usize>,
+    max_backlog : usize,
+    /// 由监听套接字建立、尚未被 accept 的连接指向监听套接字
+    parent : Option<usize>,
+}
+
+impl TcpSocket {
+    fn new(owner : usize)->Self {
+        Self {
+            owner,
+            closed : false,
+            state : TcpState::Closed,
+            local_ip : Ipv4Address::UNSPECIFIED,
+            local_port : 0,
+            remote_ip : Ipv4Address::UNSPECIFIED,
+            remote_port : 0,
+            iss : 0,
+            snd_una : 0,
+            snd_nxt : 0,
+            snd_wnd : 0,
+            rcv_nxt : 0,
+            mss : DEFAULT_MSS,
+            send_buffer : VecDeque::new(),
+            recv_buffer : VecDeque::new(),
+            fin_queued : false,
+            fin_sent : false,
+            fin_received : false,
+            ack_pending : false,
+            rto : RTO_INIT,
+            retransmit : None,
+            retries : 0,
+            time_wait : 0,
+            error : false,
+            backlog : VecDeque::new(),
+            max_backlog : 0,
+            parent : None,
+        }
+    }
+
+    fn recv_window(&self)->usize {
+        RECV_BUFFER_SIZE - self.recv_buffer.len()
+    }
+
+    /// 双方序号已经同步
+    fn is_synchronized(&self)->bool {
+        match self.state {
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived => false,
+            _ => true,
+        }
+    }
+
+    fn can_send_data(&self)->bool {
+        match self.state {
+            TcpState::Established | TcpState::CloseWait | TcpState::FinWait1
+                | TcpState::Closing | TcpState::LastAck => true,
+            _ => false,
+        }
+    }
+
+    fn fin_acked(&self)->bool {
+        self.fin_sent && self.snd_una == self.snd_nxt
+    }
+
+    fn send_segment(&mut self, ifaces : &mut Vec<Interface>, flags : u8, seq : u32, data : &[u8]) {
+        let header = TcpHeader {
+            src_port : self.local_port,
+            dst_port : self.remote_port,
+            seq,
+            ack : if flags & FLAG_ACK != 0 { self.rcv_nxt } else { 0 },
+            flags,
+            window : self.recv_window() as u16,
+            mss : if flags & FLAG_SYN != 0 { Some(LOCAL_MSS as u16) } else { None },
+        };
+        let segment = header.build(self.local_ip, self.remote_ip, data);
+        ipv4::send(ifaces, self.remote_ip, PROTOCOL_TCP, &segment).ok();
+        if flags & FLAG_ACK != 0 {
+            self.ack_pending = false;
+        }
+    }
+
+    fn start_timer(&mut self, now : usize) {
+        if self.retransmit.is_none() {
+            self.retransmit = Some(now + self.rto);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = TcpState::Closed;
+        self.error = true;
+        self.retransmit = None;
+        self.send_buffer.clear();
+    }
+
+    fn enter_time_wait(&mut self, now : usize) {
+        self.state = TcpState::TimeWait;
+        self.time_wait = now + TIME_WAIT_TIMEOUT;
+        self.retransmit = None;
+    }
+
+    /// ## 输出
+    /// 发送窗口内未发送的数据，数据发完且用户已关闭时发送 FIN，最后补发需要的 ACK
+    /// probe 为真时窗口至少为 1，用于零窗口探测
+    fn output(&mut self, ifaces : &mut Vec<Interface>, now : usize, probe : bool) {
+        if self.can_send_data() {
+            let window = if probe { self.snd_wnd.max(1) } else { self.snd_wnd };
+            while !self.fin_sent {
+                let in_flight = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+                let unsent = self.send_buffer.len() - in_flight;
+                let len = window.saturating_sub(in_flight).min(self.mss).min(unsent);
+                if len == 0 {
+                    if unsent > 0 {
+                        self.start_timer(now);
+                    }
+                    break;
+                }
+                let data : Vec<u8> = self.send_buffer.iter().skip(in_flight).take(len).cloned().collect();
+                let flags = if len == unsent { FLAG_ACK | FLAG_PSH } else { FLAG_ACK };
+                self.send_segment(ifaces, flags, self.snd_nxt, &data);
+                self.snd_nxt = self.snd_nxt.wrapping_add(len as u32);
+                self.start_timer(now);
+            }
+            if self.fin_queued && !self.fin_sent
+                && self.snd_nxt.wrapping_sub(self.snd_una) as usize == self.send_buffer.len() {
+                self.send_segment(ifaces, FLAG_FIN | FLAG_ACK, self.snd_nxt, &[]);
+                self.snd_nxt = self.snd_nxt.wrapping_add(1);
+                self.fin_sent = true;
+                self.start_timer(now);
+                self.state = match self.state {
+                    TcpState::Established => TcpState::FinWait1,
+                    TcpState::CloseWait => TcpState::LastAck,
+                    state => state,
+                };
+            }
+        }
+        if self.ack_pending && self.is_synchronized() {
+            self.send_segment(ifaces, FLAG_ACK, self.snd_nxt, &[]);
+        }
+    }
+
+    /// 重传超时，SYN 单独重发，数据与 FIN 从 snd_una 开始全部重发
+    fn timeout(&mut self, ifaces : &mut Vec<Interface>, now : usize) {
+        self.retransmit = None;
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            if self.is_synchronized() {
+                self.send_segment(ifaces, FLAG_RST, self.snd_nxt, &[]);
+            }
+            self.reset();
+            return;
+        }
+        self.rto = (self.rto * 2).min(RTO_MAX);
+        match self.state {
+            TcpState::SynSent => {
+                self.send_segment(ifaces, FLAG_SYN, self.iss, &[]);
+                self.start_timer(now);
+            }
+            TcpState::SynReceived => {
+                self.send_segment(ifaces, FLAG_SYN | FLAG_ACK, self.iss, &[]);
+                self.start_timer(now);
+            }
+            _ => {
+                self.snd_nxt = self.snd_una;
+                self.fin_sent = false;
+                self.output(ifaces, now, true);
+            }
+        }
+    }
+
+    fn process_ack(&mut self, ack : u32, window : u16, now : usize) {
+        if seq_lt(self.snd_una, ack) && seq_le(ack, self.snd_nxt) {
+            let acked = ack.wrapping_sub(self.snd_una) as usize;
+            let data = acked.min(self.send_buffer.len());
+            self.send_buffer.drain(..data);
+            self.snd_una = ack;
+            self.retries = 0;
+            self.rto = RTO_INIT;
+            self.retransmit = if self.snd_una == self.snd_nxt { None } else { Some(now + self.rto) };
+        }
+        if seq_le(self.snd_una, ack) && seq_le(ack, self.snd_nxt) {
+            self.snd_wnd = window as usize;
+        }
+    }
+
+    /// ## 处理报文段
+    /// 监听状态由套接字表处理，这里处理主动打开及已有连接
+    fn input(&mut self, ifaces : &mut Vec<Interface>, header : &TcpHeader, payload : &[u8], now : usize) {
+        match self.state {
+            TcpState::Closed | TcpState::Listen => return,
+            TcpState::SynSent => {
+                if header.flags & FLAG_ACK != 0 && header.ack != self.iss.wrapping_add(1) {
+                    if header.flags & FLAG_RST == 0 {
+                        self.send_segment(ifaces, FLAG_RST, header.ack, &[]);
+                    }
+                    return;
+                }
+                if header.flags & FLAG_RST != 0 {
+                    if header.flags & FLAG_ACK != 0 {
+                        self.reset();
+                    }
+                    return;
+                }
+                if header.flags & FLAG_SYN != 0 && header.flags & FLAG_ACK != 0 {
+                    self.rcv_nxt = header.seq.wrapping_add(1);
+                    self.snd_una = header.ack;
+                    self.snd_wnd = header.window as usize;
+                    if let Some(mss) = header.mss {
+                        self.mss = (mss as usize).min(LOCAL_MSS);
+                    }
+                    self.state = TcpState::Established;
+                    self.retransmit = None;
+                    self.retries = 0;
+                    self.rto = RTO_INIT;
+                    self.ack_pending = true;
+                    self.output(ifaces, now, false);
+                }
+                return;
+            }
+            _ => {}
+        }
+        let seq = header.seq;
+        let offset = self.rcv_nxt.wrapping_sub(seq) as usize;
+        let acceptable = seq == self.rcv_nxt || (seq_lt(seq, self.rcv_nxt) && offset < payload.len());
+        if !acceptable {
+            // 重复或者超出窗口的报文段，回复 ACK 告知期望的序号
+            if header.flags & FLAG_RST == 0 && header.seq_len(payload.len()) > 0 {
+                self.ack_pending = true;
+                self.output(ifaces, now, false);
+            }
+            return;
+        }
+        let payload = if seq == self.rcv_nxt { payload } else { &payload[offset..] };
+        if header.flags & FLAG_RST != 0 {
+            self.reset();
+            return;
+        }
+        if header.flags & FLAG_SYN != 0 {
+            self.send_segment(ifaces, FLAG_RST, self.snd_nxt, &[]);
+            self.reset();
+            return;
+        }
+        if header.flags & FLAG_ACK == 0 {
+            return;
+        }
+        if self.state == TcpState::SynReceived {
+            if header.ack != self.iss.wrapping_add(1) {
+                self.send_segment(ifaces, FLAG_RST, header.ack, &[]);
+                return;
+            }
+            self.state = TcpState::Established;
+        }
+        self.process_ack(header.ack, header.window, now);
+        match self.state {
+            TcpState::FinWait1 if self.fin_acked() => self.state = TcpState::FinWait2,
+            TcpState::Closing if self.fin_acked() => self.enter_time_wait(now),
+            TcpState::LastAck if self.fin_acked() => {
+                self.state = TcpState::Closed;
+                self.retransmit = None;
+                return;
+            }
+            _ => {}
+        }
+        let mut consumed = true;
+        if payload.len() > 0 {
+            match self.state {
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => {
+                    // 用户已经关闭时数据无人读取，直接确认后丢弃
+                    let len = if self.closed {
+                        payload.len()
+                    }
+                    else {
+                        let len = payload.len().min(self.recv_window());
+                        self.recv_buffer.extend(payload[..len].iter());
+                        len
+                    };
+                    self.rcv_nxt = self.rcv_nxt.wrapping_add(len as u32);
+                    consumed = len == payload.len();
+                    self.ack_pending = true;
+                }
+                _ => {}
+            }
+        }
+        if header.flags & FLAG_FIN != 0 && consumed {
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            self.fin_received = true;
+            self.ack_pending = true;
+            match self.state {
+                TcpState::Established => self.state = TcpState::CloseWait,
+                TcpState::FinWait1 => {
+                    if self.fin_acked() {
+                        self.enter_time_wait(now);
+                    }
+                    else {
+                        self.state = TcpState::Closing;
+                    }
+                }
+                TcpState::FinWait2 => self.enter_time_wait(now),
+                _ => {}
+            }
+        }
+        self.output(ifaces, now, false);
+    }
+}
+
+struct SocketTable {
+    socket : BTreeMap<usize, TcpSocket>,
+    next_port : u16,
+}
+
+impl SocketTable {
+    fn port_used(&self, port : u16)->bool {
+        self.socket.values().any(|s| s.local_port == port)
+    }
+
+    fn bind(&mut self, id : usize, port : u16)->Result<u16, ()> {
+        let socket = self.socket.get(&id).ok_or(())?;
+        if socket.local_port != 0 || socket.state != TcpState::Closed {
+            return Err(());
+        }
+        let port = if port == 0 { self.ephemeral_port().ok_or(())? } else { port };
+        if self.port_used(port) {
+            return Err(());
+        }
+        self.socket.get_mut(&id).unwrap().local_port = port;
+        Ok(port)
+    }
+
+    fn ephemeral_port(&mut self)->Option<u16> {
+        for _ in EPHEMERAL_START..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = if port == u16::MAX { EPHEMERAL_START } else { port + 1 };
+            if !self.port_used(port) {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    /// 查找已有连接
+    fn find(&self, local_ip : Ipv4Address, local_port : u16, remote_ip : Ipv4Address, remote_port : u16)->Option<usize> {
+        self.socket.iter().find(|(_, s)| {
+            s.state != TcpState::Closed && s.state != TcpState::Listen
+                && s.local_port == local_port && s.local_ip == local_ip
+                && s.remote_port == remote_port && s.remote_ip == remote_ip
+        }).map(|(id, _)| *id)
+    }
+
+    fn find_listener(&self, port : u16)->Option<usize> {
+        self.socket.iter().find(|(_, s)| {
+            s.state == TcpState::Listen && s.local_port == port
+        }).map(|(id, _)| *id)
+    }
+
+    /// 删除已经结束且不再属于用户的连接
+    fn cleanup(&mut self, id : usize) {
+        let socket = if let Some(s) = self.socket.get(&id) { s } else { return; };
+        if socket.state != TcpState::Closed || (!socket.closed && socket.parent.is_none()) {
+            return;
+        }
+        if let Some(parent) = socket.parent {
+            if let Some(listener) = self.socket.get_mut(&parent) {
+                listener.backlog.retain(|child| *child != id);
+            }
+        }
+        self.socket.remove(&id);
+    }
+
+    fn receive(&mut self, ifaces : &mut Vec<Interface>, ip : &Ipv4Header, header : &TcpHeader, payload : &[u8], now : usize) {
+        if let Some(id) = self.find(ip.dst, header.dst_port, ip.src, header.src_port) {
+            let socket = self.socket.get_mut(&id).unwrap();
+            let before = socket.state;
+            socket.input(ifaces, header, payload, now);
+            if before == TcpState::SynReceived && socket.state != TcpState::SynReceived
+                && socket.state != TcpState::Closed {
+                if let Some(parent) = socket.parent {
+                    if let Some(listener) = self.socket.get_mut(&parent) {
+                        listener.backlog.push_back(id);
+                    }
+                }
+            }
+            self.cleanup(id);
+            return;
+        }
+        if let Some(lid) = self.find_listener(header.dst_port) {
+            if header.flags & FLAG_RST != 0 {
+                return;
+            }
+            if header.flags & FLAG_ACK != 0 {
+                send_reset(ifaces, ip, header, payload.len());
+                return;
+            }
+            if header.flags & FLAG_SYN == 0 {
+                return;
+            }
+            let pending = self.socket.values().filter(|s| s.parent == Some(lid)).count();
+            let listener = self.socket.get(&lid).unwrap();
+            // 队列已满时丢弃 SYN，等待对端重传
+            if pending >= listener.max_backlog {
+                return;
+            }
+            let mut child = TcpSocket::new(listener.owner);
+            child.parent = Some(lid);
+            child.state = TcpState::SynReceived;
+            child.local_ip = ip.dst;
+            child.local_port = header.dst_port;
+            child.remote_ip = ip.src;
+            child.remote_port = header.src_port;
+            child.iss = gen_isn();
+            child.snd_una = child.iss;
+            child.snd_nxt = child.iss.wrapping_add(1);
+            child.snd_wnd = header.window as usize;
+            child.rcv_nxt = header.seq.wrapping_add(1);
+            if let Some(mss) = header.mss {
+                child.mss = (mss as usize).min(LOCAL_MSS);
+            }
+            child.send_segment(ifaces, FLAG_SYN | FLAG_ACK, child.iss, &[]);
+            child.start_timer(now);
+            self.socket.insert(alloc_socket_id(), child);
+            return;
+        }
+        if header.flags & FLAG_RST == 0 {
+            send_reset(ifaces, ip, header, payload.len());
+        }
+    }
+}
+
+/// 回复没有对应连接的报文段
+fn send_reset(ifaces : &mut Vec<Interface>, ip : &Ipv4Header, header : &TcpHeader, payload_len : usize) {
+    let (seq, ack, flags) = if header.flags & FLAG_ACK != 0 {
+        (header.ack, 0, FLAG_RST)
+    }
+    else {
+        (0, header.seq.wrapping_add(header.seq_len(payload_len)), FLAG_RST | FLAG_ACK)
+    };
+    let reset = TcpHeader {
+        src_port : header.dst_port,
+        dst_port : header.src_port,
+        seq,
+        ack,
+        flags,
+        window : 0,
+        mss : None,
+    };
+    ipv4::send(ifaces, ip.src, PROTOCOL_TCP, &reset.build(ip.dst, ip.src, &[])).ok();
+}
+
+pub fn init() {
+    unsafe {
+        SOCKET = Some(ContentMutex::new(SocketTable {
+            socket : BTreeMap::new(),
+            next_port : EPHEMERAL_START,
+        }, true));
+    }
+}
+
+fn socket_op<F, R>(f : F)->Option<R> where F : FnOnce(&mut SocketTable)->R {
+    unsafe {
+        let mut table = SOCKET.as_mut()?.lock();
+        Some(f(&mut *table))
+    }
+}
+
+/// 先锁接口再锁套接字表，与接收路径的加锁顺序一致，结束后发送队列中的帧
+fn stack_op<F, R>(f : F)->Option<R> where F : FnOnce(&mut Vec<Interface>, &mut SocketTable)->R {
+    interface_op(|ifaces| {
+        let rt = socket_op(|table| f(ifaces, table));
+        flush(ifaces);
+        rt
+    })?
+}
+
+/// 为进程新建套接字，返回套接字 ID
+pub fn socket(owner : usize)->Option<usize> {
+    let id = alloc_socket_id();
+    socket_op(|table| {
+        table.socket.insert(id, TcpSocket::new(owner));
+        id
+    })
+}
+
+pub fn is_own(id : usize, owner : usize)->bool {
+    socket_op(|table| {
+        table.socket.get(&id).map_or(false, |s| s.owner == owner && !s.closed && s.parent.is_none())
+    }).unwrap_or(false)
+}
+
+/// ## 绑定本地端口
+/// 端口为 0 时分配临时端口，端口已被占用或者套接字已经绑定时返回错误
+pub fn bind(id : usize, port : u16)->Result<u16, ()> {
+    socket_op(|table| table.bind(id, port)).unwrap_or(Err(()))
+}
+
+/// 开始监听，套接字必须已经绑定端口
+pub fn listen(id : usize, backlog : usize)->Result<(), ()> {
+    socket_op(|table| {
+        let socket = table.socket.get_mut(&id).ok_or(())?;
+        if socket.state != TcpState::Closed || socket.local_port == 0 || socket.remote_port != 0 {
+            return Err(());
+        }
+        socket.state = TcpState::Listen;
+        socket.max_backlog = backlog.max(1).min(MAX_BACKLOG);
+        Ok(())
+    }).unwrap_or(Err(()))
+}
+
+/// ## 取出已建立的连接
+/// 返回（套接字 ID，对端地址，对端端口），队列为空时返回 None
+pub fn accept(id : usize)->Result<Option<(usize, Ipv4Address, u16)>, ()> {
+    socket_op(|table| {
+        let listener = table.socket.get_mut(&id).ok_or(())?;
+        if listener.state != TcpState::Listen {
+            return Err(());
+        }
+        if let Some(child) = listener.backlog.pop_front() {
+            let socket = table.socket.get_mut(&child).unwrap();
+            socket.parent = None;
+            Ok(Some((child, socket.remote_ip, socket.remote_port)))
+        }
+        else {
+            Ok(None)
+        }
+    }).unwrap_or(Err(()))
+}
+
+/// ## 主动连接
+/// 第一次调用时发送 SYN，之后的调用查询连接状态：已建立返回 true，正在建立返回 false，失败返回错误
+pub fn connect(id : usize, dst : Ipv4Address, dst_port : u16)->Result<bool, ()> {
+    let now = get_million_time();
+    stack_op(|ifaces, table| {
+        let socket = table.socket.get(&id).ok_or(())?;
+        match socket.state {
+            TcpState::Closed if socket.remote_port == 0 && dst_port != 0 => {}
+            TcpState::SynSent | TcpState::SynReceived => return Ok(false),
+            TcpState::Established | TcpState::CloseWait => return Ok(true),
+            _ => return Err(()),
+        }
+        let (idx, _) = ipv4::route(ifaces, dst).ok_or(())?;
+        if socket.local_port == 0 {
+            table.bind(id, 0)?;
+        }
+        let socket = table.socket.get_mut(&id).unwrap();
+        socket.local_ip = ifaces[idx].ip;
+        socket.remote_ip = dst;
+        socket.remote_port = dst_port;
+        socket.iss = gen_isn();
+        socket.snd_una = socket.iss;
+        socket.snd_nxt = socket.iss.wrapping_add(1);
+        socket.state = TcpState::SynSent;
+        socket.send_segment(ifaces, FLAG_SYN, socket.iss, &[]);
+        socket.start_timer(now);
+        Ok(false)
+    }).unwrap_or(Err(()))
+}
+
+/// ## 发送数据
+/// 返回放入发送缓冲区的字节数，缓冲区已满或者连接还未建立时返回 0，连接不可写时返回错误
+pub fn send(id : usize, data : &[u8])->Result<usize, ()> {
+    let now = get_million_time();
+    stack_op(|ifaces, table| {
+        let socket = table.socket.get_mut(&id).ok_or(())?;
+        match socket.state {
+            TcpState::SynSent | TcpState::SynReceived => return Ok(0),
+            TcpState::Established | TcpState::CloseWait if !socket.fin_queued => {}
+            _ => return Err(()),
+        }
+        let len = data.len().min(SEND_BUFFER_SIZE - socket.send_buffer.len());
+        socket.send_buffer.extend(data[..len].iter());
+        socket.output(ifaces, now, false);
+        Ok(len)
+    }).unwrap_or(Err(()))
+}
+
+/// ## 接收数据
+/// 返回读取的字节数，对端已关闭且数据读完时返回 0，暂时没有数据时返回 None，连接出错时返回错误
+pub fn recv(id : usize, data : &mut [u8])->Result<Option<usize>, ()> {
+    let now = get_million_time();
+    stack_op(|ifaces, table| {
+        let socket = table.socket.get_mut(&id).ok_or(())?;
+        if socket.recv_buffer.len() > 0 {
+            let before = socket.recv_window();
+            let len = data.len().min(socket.recv_buffer.len());
+            for (i, byte) in socket.recv_buffer.drain(..len).enumerate() {
+                data[i] = byte;
+            }
+            // 窗口从很小重新打开时主动通告，避免对端一直等待
+            if before < socket.mss && socket.recv_window() >= socket.mss {
+                socket.ack_pending = true;
+                socket.output(ifaces, now, false);
+            }
+            return Ok(Some(len));
+        }
+        if socket.error {
+            return Err(());
+        }
+        if socket.fin_received {
+            return Ok(Some(0));
+        }
+        match socket.state {
+            TcpState::SynSent | TcpState::SynReceived | TcpState::Established
+                | TcpState::FinWait1 | TcpState::FinWait2 => Ok(None),
+            _ => Err(()),
+        }
+    }).unwrap_or(Err(()))
+}
+
+pub fn sockets()->Vec<SocketInfo> {
+    socket_op(|table| {
+        table.socket.iter().map(|(id, s)| SocketInfo {
+            id : *id,
+            owner : s.owner,
+            socket_type : SOCK_STREAM,
+            state : s.state as usize,
+            local_ip : s.local_ip,
+            local_port : s.local_port,
+            remote_ip : s.remote_ip,
+            remote_port : s.remote_port,
+            recv_queue : s.recv_buffer.len(),
+            send_queue : s.send_buffer.len(),
+            dropped : 0,
+        }).collect()
+    }).unwrap_or(Vec::new())
+}
+
+/// ## 关闭套接字
+/// 已建立的连接在数据发送完毕后发送 FIN，连接结束后删除；监听套接字同时重置尚未 accept 的连接
+pub fn close(id : usize) {
+    let now = get_million_time();
+    stack_op(|ifaces, table| {
+        let socket = if let Some(s) = table.socket.get_mut(&id) { s } else { return; };
+        socket.closed = true;
+        match socket.state {
+            TcpState::Listen => {
+                socket.state = TcpState::Closed;
+                let children : Vec<usize> = table.socket.iter()
+                    .filter(|(_, s)| s.parent == Some(id)).map(|(id, _)| *id).collect();
+                for child in children {
+                    let socket = table.socket.get_mut(&child).unwrap();
+                    socket.send_segment(ifaces, FLAG_RST, socket.snd_nxt, &[]);
+                    table.socket.remove(&child);
+                }
+            }
+            TcpState::SynSent => socket.state = TcpState::Closed,
+            TcpState::Established | TcpState::CloseWait => {
+                socket.fin_queued = true;
+                socket.recv_buffer.clear();
+                socket.output(ifaces, now, false);
+            }
+            _ => {}
+        }
+        table.cleanup(id);
+    });
+}
+
+/// ## 接收报文段
+/// 校验和错误时丢弃，没有对应连接时回复 RST
+pub fn receive(ifaces : &mut Vec<Interface>, idx : usize, ip : &Ipv4Header, data : &[u8]) {
+    if data.len() < HEADER_LEN {
+        ifaces[idx].stats.rx_dropped += 1;
+        return;
+    }
+    if finish(sum(pseudo_header(ip.src, ip.dst, PROTOCOL_TCP, data.len()), data)) != 0 {
+        ifaces[idx].stats.checksum_errors += 1;
+        return;
+    }
+    let (header, payload) = if let Some(rt) = TcpHeader::parse(data) {
+        rt
+    }
+    else {
+        ifaces[idx].stats.rx_dropped += 1;
+        return;
+    };
+    let now = get_million_time();
+    socket_op(|table| table.receive(ifaces, ip, &header, payload, now));
+}
+
+/// 处理重传超时与 TIME_WAIT 超时
+pub fn tick() {
+    let now = get_million_time();
+    stack_op(|ifaces, table| {
+        let ids : Vec<usize> = table.socket.keys().cloned().collect();
+        for id in ids {
+            let socket = table.socket.get_mut(&id).unwrap();
+            if socket.state == TcpState::TimeWait {
+                if socket.time_wait <= now {
+                    socket.state = TcpState::Closed;
+                }
+            }
+            else if let Some(time) = socket.retransmit {
+                if time <= now {
+                    socket.timeout(ifaces, now);
+                }
+            }
+            table.cleanup(id);
+        }
+    });
+}
@@ -0,0 +1,216 @@
+//! # UDP
+//! 数据报套接字。每个套接字绑定一个本地端口，收到的数据报按目的端口放入对应套接字的接收队列
+//! 未绑定的套接字在第一次发送时自动分配临时端口
+//!
+//! 2021年6月11日 zg
+
+use alloc::{collections::{BTreeMap, VecDeque}, prelude::v1::*};
+use tisu_sync::ContentMutex;
+use super::{SOCK_DGRAM, SocketInfo, address::Ipv4Address, alloc_socket_id, dhcp, checksum::{finish, pseudo_header, sum}, flush, interface::Interface, interface_op, ipv4::{self, Ipv4Header, PROTOCOL_UDP}};
+
+pub const HEADER_LEN : usize = 8;
+/// 单个数据报的最大负载，IPv4 总长度 65535 减去两个头部
+pub const MAX_PAYLOAD : usize = 65535 - ipv4::HEADER_LEN - HEADER_LEN;
+/// 接收队列的最大长度，超出时丢弃新的数据报
+const RECV_QUEUE_LEN : usize = 64;
+const EPHEMERAL_START : u16 = 49152;
+
+static mut SOCKET : Option<ContentMutex<SocketTable>> = None;
+
+/// ## 收到的数据报
+pub struct Datagram {
+    pub src : Ipv4Address,
+    pub src_port : u16,
+    pub data : Vec<u8>,
+}
+
+pub struct UdpSocket {
+    pub owner : usize,
+    pub port : Option<u16>,
+    queue : VecDeque<Datagram>,
+    pub dropped : usize,
+}
+
+struct SocketTable {
+    socket : BTreeMap<usize, UdpSocket>,
+    /// 端口 -> 套接字 ID
+    port : BTreeMap<u16, usize>,
+    next_port : u16,
+}
+
+impl SocketTable {
+    fn bind(&mut self, id : usize, port : u16)->Result<u16, ()> {
+        if self.socket.get(&id).ok_or(())?.port.is_some() {
+            return Err(());
+        }
+        let port = if port == 0 { self.ephemeral_port().ok_or(())? } else { port };
+        if self.port.contains_key(&port) {
+            return Err(());
+        }
+        self.port.insert(port, id);
+        self.socket.get_mut(&id).unwrap().port = Some(port);
+        Ok(port)
+    }
+
+    fn ephemeral_port(&mut self)->Option<u16> {
+        for _ in EPHEMERAL_START..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = if port == u16::MAX { EPHEMERAL_START } else { port + 1 };
+            if !self.port.contains_key(&port) {
+                return Some(port);
+            }
+        }
+        None
+    }
+}
+
+pub fn init() {
+    unsafe {
+        SOCKET = Some(ContentMutex::new(SocketTable {
+            socket : BTreeMap::new(),
+            port : BTreeMap::new(),
+            next_port : EPHEMERAL_START,
+        }, true));
+    }
+}
+
+fn socket_op<F, R>(f : F)->Option<R> where F : FnOnce(&mut SocketTable)->R {
+    unsafe {
+        let mut table = SOCKET.as_mut()?.lock();
+        Some(f(&mut *table))
+    }
+}
+
+/// 为进程新建套接字，返回套接字 ID
+pub fn socket(owner : usize)->Option<usize> {
+    let id = alloc_socket_id();
+    socket_op(|table| {
+        table.socket.insert(id, UdpSocket {
+            owner,
+            port : None,
+            queue : VecDeque::new(),
+            dropped : 0,
+        });
+        id
+    })
+}
+
+pub fn is_own(id : usize, owner : usize)->bool {
+    socket_op(|table| {
+        table.socket.get(&id).map_or(false, |s| s.owner == owner)
+    }).unwrap_or(false)
+}
+
+/// ## 绑定本地端口
+/// 端口为 0 时分配临时端口，端口已被占用或者套接字已经绑定时返回错误
+pub fn bind(id : usize, port : u16)->Result<u16, ()> {
+    socket_op(|table| table.bind(id, port)).unwrap_or(Err(()))
+}
+
+/// ## 发送数据报
+/// 返回发送的字节数，没有路由或者数据过长时返回错误
+pub fn send_to(id : usize, dst : Ipv4Address, dst_port : u16, data : &[u8])->Result<usize, ()> {
+    if data.len() > MAX_PAYLOAD || dst_port == 0 {
+        return Err(());
+    }
+    let src_port = socket_op(|table| {
+        match table.socket.get(&id).ok_or(())?.port {
+            Some(port) => Ok(port),
+            None => table.bind(id, 0),
+        }
+    }).unwrap_or(Err(()))?;
+    interface_op(|ifaces| {
+        let (idx, _) = ipv4::route(ifaces, dst).ok_or(())?;
+        let datagram = build(ifaces[idx].ip, dst, src_port, dst_port, data);
+        let rt = ipv4::send(ifaces, dst, PROTOCOL_UDP, &datagram);
+        flush(ifaces);
+        rt.map(|_| data.len())
+    }).unwrap_or(Err(()))
+}
+
+/// 取出接收队列中的第一个数据报，队列为空时返回 None
+pub fn recv_from(id : usize)->Option<Datagram> {
+    socket_op(|table| {
+        table.socket.get_mut(&id)?.queue.pop_front()
+    })?
+}
+
+pub fn sockets()->Vec<SocketInfo> {
+    socket_op(|table| {
+        table.socket.iter().map(|(id, s)| SocketInfo {
+            id : *id,
+            owner : s.owner,
+            socket_type : SOCK_DGRAM,
+            local_port : s.port.unwrap_or(0),
+            recv_queue : s.queue.len(),
+            dropped : s.dropped,
+            ..SocketInfo::default()
+        }).collect()
+    }).unwrap_or(Vec::new())
+}
+
+pub fn close(id : usize) {
+    socket_op(|table| {
+        if let Some(socket) = table.socket.remove(&id) {
+            if let Some(port) = socket.port {
+                table.port.remove(&port);
+            }
+        }
+    });
+}
+
+pub fn build(src : Ipv4Address, dst : Ipv4Address, src_port : u16, dst_port : u16, data : &[u8])->Vec<u8> {
+    let len = HEADER_LEN + data.len();
+    let mut rt = Vec::with_capacity(len);
+    rt.extend_from_slice(&src_port.to_be_bytes());
+    rt.extend_from_slice(&dst_port.to_be_bytes());
+    rt.extend_from_slice(&(len as u16).to_be_bytes());
+    rt.extend_from_slice(&[0, 0]);
+    rt.extend_from_slice(data);
+    let mut check = finish(sum(pseudo_header(src, dst, PROTOCOL_UDP, len), &rt));
+    // 校验和为 0 表示不校验，计算结果为 0 时以全 1 代替
+    if check == 0 {
+        check = 0xffff;
+    }
+    rt[6..8].copy_from_slice(&check.to_be_bytes());
+    rt
+}
+
+/// ## 接收数据报
+/// 长度或校验和错误、目的端口没有套接字时丢弃，DHCP 客户端端口交给对应接口
+pub fn receive(ifaces : &mut Vec<Interface>, idx : usize, header : &Ipv4Header, data : &[u8]) {
+    let len = if data.len() >= HEADER_LEN { u16::from_be_bytes([data[4], data[5]]) as usize } else { 0 };
+    if len < HEADER_LEN || len > data.len() {
+        ifaces[idx].stats.rx_dropped += 1;
+        return;
+    }
+    let data = &data[..len];
+    let check = u16::from_be_bytes([data[6], data[7]]);
+    if check != 0 && finish(sum(pseudo_header(header.src, header.dst, PROTOCOL_UDP, len), data)) != 0 {
+        ifaces[idx].stats.checksum_errors += 1;
+        return;
+    }
+    let src_port = u16::from_be_bytes([data[0], data[1]]);
+    let dst_port = u16::from_be_bytes([data[2], data[3]]);
+    if dst_port == dhcp::CLIENT_PORT {
+        dhcp::receive(&mut ifaces[idx], &data[HEADER_LEN..]);
+        return;
+    }
+    let delivered = socket_op(|table| {
+        let id = if let Some(id) = table.port.get(&dst_port) { *id } else { return false; };
+        let socket = table.socket.get_mut(&id).unwrap();
+        if socket.queue.len() >= RECV_QUEUE_LEN {
+            socket.dropped += 1;
+            return false;
+        }
+        socket.queue.push_back(Datagram {
+            src : header.src,
+            src_port,
+            data : data[HEADER_LEN..].to_vec(),
+        });
+        true
+    }).unwrap_or(false);
+    if !delivered {
+        ifaces[idx].stats.rx_dropped += 1;
+    }
+}
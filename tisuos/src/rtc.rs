@@ -15,6 +15,11 @@ pub fn read()->usize {
     }
 }
 
+/// 1970 年起的秒数
+pub fn now()->usize {
+    read() / 10_0000_0000
+}
+
 #[derive(Debug)]
 pub struct Time {
     pub year : usize,
@@ -89,6 +94,6 @@ impl Time {
     }
 
     pub fn read()->Self {
-        Self::from_timestamp(read() / 10_0000_0000)
+        Self::from_timestamp(now())
     }
 }
\ No newline at end of file
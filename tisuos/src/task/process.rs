@@ -7,6 +7,8 @@ use alloc::{prelude::v1::*};
 
 pub static mut PID_CNT : AtomCounter = AtomCounter::new();
 pub const MAX_HEAP_SIZE : usize = PAGE_SIZE * 1024 * 4;
+/// 线程栈使用的虚拟地址范围大小，位于堆之后
+pub const STACK_SPACE_SIZE : usize = 1 << 32;
 
 
 /// ## 进程信息结构体
@@ -20,6 +22,9 @@ pub struct Process{
     resource : Resource,
     pub join_num : usize,
     pub is_kernel : bool,
+    /// 堆内存池的时钟指针
+    swap_hand : usize,
+    stack_space : StackSpace,
 }
 
 impl Process {
@@ -32,6 +37,7 @@ impl Process {
             satp : SATP::new(),
             state: TaskState::Running,
             is_kernel : program.is_kernel,
+            devices : program.devices(),
         };
         program.map(&info.satp);
         program.map_kernel_trap(&info.satp);
@@ -43,6 +49,10 @@ impl Process {
             resource:Resource::new(pid),
             join_num : 0,
             tid : Vec::<usize>::new(),
+            swap_hand : 0,
+            stack_space : StackSpace::new(
+                unsafe {MEMORY_END} / PAGE_SIZE * PAGE_SIZE + MAX_HEAP_SIZE + PAGE_SIZE,
+                STACK_SPACE_SIZE),
         };
         Some(rt)
     }
@@ -55,7 +65,7 @@ impl Process {
         self.program.entry()
     }
 
-    pub fn alloc_heap(&mut self, size : usize)->(usize, usize) {
+    pub fn alloc_heap(&mut self, size : usize)->Result<(usize, usize), MemoryError> {
         assert!(self.info.satp.is_map());
         self.heap.alloc(size, &self.info.satp)
     }
@@ -64,6 +74,11 @@ impl Process {
         self.heap.free(addr);
     }
 
+    /// 进程堆与程序区域占用的物理页面数，不包括线程栈
+    pub fn resident_pages(&self)->usize {
+        self.heap.page_num() + self.program.page_num()
+    }
+
     pub fn contain(&self, va:usize)->bool {
         if va >= unsafe {MEMORY_END} && va <= MAX_HEAP_SIZE + unsafe {MEMORY_END} {
             true
@@ -76,21 +91,52 @@ impl Process {
         }
     }
 
-    pub fn virt_to_phy(&self, va:usize)->usize {
+    /// 堆内存被换出时需要先换回，物理页面不足时返回错误
+    pub fn virt_to_phy(&mut self, va:usize)->Result<usize, MemoryError> {
         if va >= unsafe {MEMORY_END} {
             if va <= MAX_HEAP_SIZE + unsafe {MEMORY_END} {
-                self.heap.virt_to_phy(va)
+                self.heap.virt_to_phy(va, &self.info.satp)
             }
             else {
                 panic!("virt to phy va {:x}", va);
             }
         }
         else if self.program.contain(va) {
-            self.program.virt_to_phy(va)
+            Ok(self.program.virt_to_phy(va))
         }
         else {
-            va
+            Ok(va)
+        }
+    }
+
+    /// 缺页地址位于已换出的堆内存时换入，返回是否处理了该地址
+    pub fn swap_in(&mut self, va:usize)->Result<bool, MemoryError> {
+        self.heap.swap_in(va, &self.info.satp)
+    }
+
+    /// 换出最多 page_num 个页面，返回实际换出的页面数
+    pub fn reclaim(&mut self, page_num : usize)->usize {
+        if self.is_kernel {
+            return 0;
+        }
+        let (freed, hand) = self.heap.reclaim(self.swap_hand, page_num, &self.info.satp);
+        self.swap_hand = hand;
+        freed
+    }
+
+    /// 为线程分配栈范围，返回栈顶
+    pub fn alloc_stack(&mut self, page_num : usize)->Option<usize> {
+        self.stack_space.alloc(page_num)
+    }
+
+    /// ## 归还线程栈范围
+    /// 线程的物理页面随线程释放，这里取消映射，避免范围复用前仍能访问
+    pub fn free_stack(&mut self, stack_top : usize, page_num : usize) {
+        for i in 1..=page_num {
+            self.info.satp.unmap(stack_top - i * PAGE_SIZE);
         }
+        flush_tlb();
+        self.stack_space.free(stack_top, page_num);
     }
 
     /// 添加文件 ID
@@ -101,6 +147,14 @@ impl Process {
     pub fn release_file(&mut self, id:usize) {
         self.resource.release_file(id);
     }
+
+    pub fn push_socket(&mut self, id:usize) {
+        self.resource.push_socket(id);
+    }
+
+    pub fn release_socket(&mut self, id:usize) {
+        self.resource.release_socket(id);
+    }
 }
 /// 进程的释放发生在被从调度队列中剔除
 impl Drop for Process{
@@ -118,10 +172,10 @@ pub fn start_init_process(){
     let mut program = ProgramArea::new(init_process as usize, true);
     program.push_area(Area::kernel_code());
     program.push_area(Area::kernel_data());
-    program.push_area(Area::virtio_area());
-    program.push_area(Area::timer_area());
-    program.push_area(Area::rtc_area());
-    program.push_area(Area::test_area());
+    program.grant_device(MmioDevice::Virtio);
+    program.grant_device(MmioDevice::Timer);
+    program.grant_device(MmioDevice::Rtc);
+    program.grant_device(MmioDevice::Test);
     let id = mgr.create_task(program, &Environment::new()).unwrap();
     mgr.start(id, 0);
     panic!("start init process fail {}", 0);
@@ -132,6 +186,8 @@ pub fn init_process(){
     disk_cache::init();
     timer::set_next_interrupt(0);
     filesystem::init();
+    swap_memory::init();
+    branch(disk_cache::flush_handler as usize, 0, 0);
     if fork() == 0 {
         console_shell::run();
     }
@@ -161,7 +217,7 @@ pub fn init_process(){
 
 
 extern crate alloc;
-use crate::{desktop::plane::Plane, filesystem, interact::{console_input::output_handler, console_shell}, interrupt::{environment::Environment, timer}, libs::syscall::{branch, fork}, memory::{Area, ProgramArea, config::{MEMORY_END, PAGE_SIZE}, heap_memory::TaskHeap, map::SATP}, virtio::{device::gpu_support, disk_cache}};
+use crate::{desktop::plane::Plane, filesystem, interact::{console_input::output_handler, console_shell}, interrupt::{environment::Environment, timer}, libs::{cpu::flush_tlb, syscall::{branch, fork}}, memory::{Area, MemoryError, MmioDevice, ProgramArea, StackSpace, config::{MEMORY_END, PAGE_SIZE}, heap_memory::TaskHeap, map::SATP, swap_memory}, virtio::{device::gpu_support, disk_cache}};
 
 use super::{resource::Resource, task_info::{ProgramInfo, TaskState}};
 use tisu_sync::AtomCounter;
\ No newline at end of file
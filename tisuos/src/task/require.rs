@@ -1,4 +1,4 @@
-use crate::{interrupt::environment::Environment, memory::{ProgramArea, block::Block}};
+use crate::{interrupt::environment::Environment, memory::{MemoryError, ProgramArea, block::Block}};
 use alloc::prelude::v1::*;
 use super::task_info::{ExecutionInfo, ProgramInfo};
 
@@ -9,7 +9,8 @@ pub trait TaskPoolOp : TaskPoolBasicOp + TaskComplexOp + TaskResourceOp + TaskSc
 pub trait TaskPoolBasicOp {
     fn create(&mut self, program : ProgramArea, env : &Environment)->Option<usize>;
     fn fork(&mut self, env : &Environment)->Option<usize>;
-    fn branch(&mut self, env : &Environment)->Option<usize>;
+    /// stack_size 为 0 时使用默认栈大小
+    fn branch(&mut self, env : &Environment, stack_size : usize)->Option<usize>;
 
     fn get_task_exec(&self, id : usize)->Option<ExecutionInfo>;
     fn get_task_prog(&self, id : usize)->Option<ProgramInfo>;
@@ -36,11 +37,12 @@ pub trait TaskPoolBasicOp {
 }
 
 pub trait TaskComplexOp {
-    fn alloc_heap(&mut self, size : usize, id : usize)->(usize, usize);
+    fn alloc_heap(&mut self, size : usize, id : usize)->Result<(usize, usize), MemoryError>;
 
     fn free_heap(&mut self, addr : usize, id : usize);
 
-    fn virt_to_phy(&self, id:usize, va:usize)->usize;
+    /// 地址位于已换出的内存时先换入，没有内存换入时返回 None
+    fn virt_to_phy(&self, id:usize, va:usize)->Option<usize>;
 
     /// 等待某个 task 结束，等待者应该退出调度，唤醒操作应该在
     fn wait_task(&mut self, waiter: usize, target: usize);
@@ -50,15 +52,26 @@ pub trait TaskComplexOp {
 
     fn set_timer(&mut self, id : usize, time : usize);
 
-    fn expand_stack(&mut self, id : usize)->Result<(),()>;
+    /// 缺页地址位于栈的保护页或者栈范围之外时返回错误
+    fn expand_stack(&mut self, id : usize, va : usize)->Result<(),()>;
+
+    /// 缺页地址位于已换出的内存时换入，否则返回错误
+    fn swap_in(&mut self, id : usize, va : usize)->Result<(),()>;
 
     fn join(&mut self, id : usize);
+
+    /// 选出占用物理页面最多且没有线程在运行的用户进程，返回（主线程 ID，进程 ID，页面数）
+    fn oom_victim(&mut self)->Option<(usize, usize, usize)>;
 }
 
 pub trait TaskResourceOp {
     fn push_file(&mut self, task_id : usize, file_id:usize);
 
     fn release_file(&mut self, task_id : usize, file_id:usize);
+
+    fn push_socket(&mut self, task_id : usize, socket_id:usize);
+
+    fn release_socket(&mut self, task_id : usize, socket_id:usize);
 }
 
 pub trait TaskScheduleOp {
@@ -1,16 +1,17 @@
 //! # 任务资源
-//! 管理任务对公共资源的占用、释放
+//! 管理任务对公共资源的占用、释放，包括文件与套接字
 //!
 //! 2021年5月4日 zg
 
 
 use alloc::prelude::v1::*;
 use tisu_fs::SystemOp;
-use crate::filesystem::search_system;
+use crate::{filesystem::search_system, net::close_socket};
 
 pub struct Resource {
     pid : usize,
     files : Vec<usize>,
+    sockets : Vec<usize>,
 }
 
 impl Resource {
@@ -18,6 +19,7 @@ impl Resource {
         Self {
             pid,
             files : Vec::new(),
+            sockets : Vec::new(),
         }
     }
 
@@ -33,6 +35,19 @@ impl Resource {
             }
         }
     }
+
+    pub fn push_socket(&mut self, id:usize) {
+        self.sockets.push(id);
+    }
+
+    pub fn release_socket(&mut self, id:usize) {
+        for (i, socket_id) in self.sockets.iter().enumerate() {
+            if id == *socket_id {
+                self.sockets.remove(i);
+                break;
+            }
+        }
+    }
 }
 
 impl Drop for Resource {
@@ -51,5 +66,8 @@ impl Drop for Resource {
                 panic!("no file id {}", file_id);
             }
         }
+        for socket_id in self.sockets.iter() {
+            close_socket(*socket_id);
+        }
     }
 }
\ No newline at end of file
@@ -30,6 +30,8 @@ pub struct ProgramInfo {
     pub satp : SATP,
     pub state : TaskState,
     pub is_kernel : bool,
+    /// 被授予的设备，MmioDevice::bit 的掩码
+    pub devices : usize,
 }
 
 
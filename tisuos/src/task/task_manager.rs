@@ -5,7 +5,7 @@
 //! 2021年3月23日 zg
 
 
-use crate::{filesystem::{pop_task_out, push_task_in, push_task_out}, interrupt::{environment::{Environment}, timer}, libs::{help::{start_kernel_process, switch_kernel_process, switch_user_process}, syscall::trigger_timer}, memory::ProgramArea};
+use crate::{filesystem::{pop_task_out, push_task_in, push_task_out}, interrupt::{environment::{Environment}, timer}, libs::{help::{start_kernel_process, switch_kernel_process, switch_user_process}, syscall::trigger_timer}, memory::{MemoryError, ProgramArea, oom}};
 use tisu_sync::SpinMutex;
 use super::{require::{TaskPoolBasicOp, TaskPoolOp}, task_info::{ExecutionInfo, ProgramInfo, TaskState}};
 
@@ -65,6 +65,10 @@ impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
         if env.hartid == 0 {
             self.check_timer();
         }
+        // 此时不持有任务池的锁，处理之前记录下的内存耗尽
+        if let Some(size) = oom::pending() {
+            self.oom_kill(size);
+        }
         let next = self.scheduler.schedule(&mut self.task_pool);
         if let Some(next) = next {
             if self.task_pool.set_task_exec(next, |info|{
@@ -93,8 +97,8 @@ impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
         }).unwrap();
     }
 
-    pub fn fork_task(&mut self, env : &Environment)->usize {
-        self.task_pool.fork(env).unwrap()
+    pub fn fork_task(&mut self, env : &Environment)->Option<usize> {
+        self.task_pool.fork(env)
     }
 
     pub fn task_exit(&mut self, id : usize) {
@@ -125,8 +129,16 @@ impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
         }
     }
 
-    pub fn expand_stack(&mut self, id : usize)->Result<(), ()> {
-        self.task_pool.expand_stack(id)
+
+    /// ## 缺页处理
+    /// 优先换入被换出的页面，其次扩展栈
+    pub fn page_fault(&mut self, id : usize, va : usize)->Result<(), ()> {
+        if self.task_pool.swap_in(id, va).is_ok() {
+            Ok(())
+        }
+        else {
+            self.task_pool.expand_stack(id, va)
+        }
     }
 
     pub fn program_exit(&mut self, id : usize) {
@@ -158,6 +170,21 @@ impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
         self.task_pool.find(f)
     }
 
+    /// ## 内存耗尽处理
+    /// 结束占用页面最多的用户进程，返回是否有进程被结束
+    /// 只能在不持有任务池锁的地方调用，正在其它核上运行的进程不会被选中
+    pub fn oom_kill(&mut self, size : usize)->bool {
+        if let Some((tid, pid, pages)) = self.task_pool.oom_victim() {
+            println!("out of memory when alloc {} bytes, kill process #{}# holding {} pages",
+                size, pid, pages);
+            self.task_pool.remove_program(tid).is_ok()
+        }
+        else {
+            println!("out of memory when alloc {} bytes, no process to kill", size);
+            false
+        }
+    }
+
     /// 调试用函数，待删除
     pub fn print(&self) {
         self.task_pool.print()
@@ -180,11 +207,11 @@ impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
 
 /// 系统调用部分
 impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
-    pub fn branch(&mut self, env:&Environment)->Option<usize> {
-        self.task_pool.branch(env)
+    pub fn branch(&mut self, env:&Environment, stack_size : usize)->Option<usize> {
+        self.task_pool.branch(env, stack_size)
     }
 
-    pub fn alloc_heap(&mut self, size: usize, id : usize)->(usize, usize) {
+    pub fn alloc_heap(&mut self, size: usize, id : usize)->Result<(usize, usize), MemoryError> {
         self.task_pool.alloc_heap(size, id)
     }
 
@@ -217,7 +244,21 @@ impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
         }).unwrap();
     }
 
-    pub fn virt_to_phy(&self, id:usize, va:usize)->usize {
+    /// ## 定时重试系统调用
+    /// 与 sleep_timer 相同，但不跳过 ecall 指令，唤醒后重新执行同一个系统调用，用于阻塞等待
+    pub fn retry_timer(&mut self, env: &Environment, time : usize) {
+        let hartid = env.hartid;
+        let time = timer::get_micro_time() + time;
+        let id = self.task_pool.find(|info|{
+            info.state == TaskState::Running && info.env.hartid == hartid
+        }).unwrap();
+        self.task_pool.set_timer(id, time);
+        self.task_pool.set_task_exec(id, |info| {
+            info.env = env.clone();
+        }).unwrap();
+    }
+
+    pub fn virt_to_phy(&self, id:usize, va:usize)->Option<usize> {
         self.task_pool.virt_to_phy(id, va)
     }
 
@@ -254,6 +295,14 @@ impl<T1 : SchedulerOp, T2 : TaskPoolOp> TaskManager<T1, T2> {
         self.task_pool.release_file(task_id, file_id);
     }
 
+    pub fn push_socket(&mut self, task_id : usize, socket_id:usize) {
+        self.task_pool.push_socket(task_id, socket_id);
+    }
+
+    pub fn release_socket(&mut self, task_id : usize, socket_id:usize) {
+        self.task_pool.release_socket(task_id, socket_id);
+    }
+
     pub fn stdout(&mut self, id:usize, data:&[u8]) {
         // println!("mgr stdout");
         for c in data {
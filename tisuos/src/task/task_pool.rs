@@ -3,30 +3,60 @@
 //! 2021年3月23日 zg
 
 
-use crate::{interrupt::environment::Environment, memory::ProgramArea};
+use core::{ops::{Deref, DerefMut}, sync::atomic::{AtomicUsize, Ordering}};
+use crate::{interrupt::environment::Environment, memory::{MemoryError, ProgramArea, config::PAGE_SIZE, oom, heap_memory::{MAX_BLOCK_SIZE, POOL_RATE}, swap_memory}};
 use tisu_sync::ContentMutex;
-use super::{process::Process, require::{TaskComplexOp, TaskPoolBasicOp, TaskPoolOp, TaskResourceOp, TaskScheduleOp}, task_info::{ExecutionInfo, TaskState}, thread::Thread};
+use super::{process::Process, thread::{MAX_STACK_LIMIT, MAX_STACK_PAGE}, require::{TaskComplexOp, TaskPoolBasicOp, TaskPoolOp, TaskResourceOp, TaskScheduleOp}, task_info::{ExecutionInfo, TaskState}, thread::Thread};
 use alloc::{prelude::v1::*};
 use alloc::collections::BTreeMap;
 
 pub struct TaskPool {
-    process : ContentMutex<BTreeMap<usize, Process>>,
-    thread : ContentMutex<BTreeMap<usize, Thread>>,
-    waiting_list : ContentMutex<BTreeMap<usize, Vec<usize>>>,
-    wait_time_list : ContentMutex<BTreeMap<usize, usize>>,
-    time_list : ContentMutex<Vec<usize>>,
+    process : PoolMutex<BTreeMap<usize, Process>>,
+    thread : PoolMutex<BTreeMap<usize, Thread>>,
+    waiting_list : PoolMutex<BTreeMap<usize, Vec<usize>>>,
+    wait_time_list : PoolMutex<BTreeMap<usize, usize>>,
+    time_list : PoolMutex<Vec<usize>>,
+    /// 页面回收的时钟指针，记录下一个扫描的进程号
+    swap_hand : AtomicUsize,
 }
 
 impl TaskPool {
     pub fn new()->Self {
         Self{
-            process : ContentMutex::new(BTreeMap::new(), true),
-            thread : ContentMutex::new(BTreeMap::new(), true),
-            waiting_list : ContentMutex::new(BTreeMap::new(), true),
-            wait_time_list : ContentMutex::new(BTreeMap::new(), true),
-            time_list : ContentMutex::new(Vec::new(), true),
+            process : PoolMutex::new(BTreeMap::new()),
+            thread : PoolMutex::new(BTreeMap::new()),
+            waiting_list : PoolMutex::new(BTreeMap::new()),
+            wait_time_list : PoolMutex::new(BTreeMap::new()),
+            time_list : PoolMutex::new(Vec::new()),
+            swap_hand : AtomicUsize::new(0),
+        }
+    }
 
+    /// ## 回收用户堆内存
+    /// 从时钟指针处轮流扫描进程，正在运行的进程跳过，避免换出内核正在按物理地址访问的内存
+    /// 返回换出的页面数
+    fn reclaim(&self, process : &mut BTreeMap<usize, Process>,
+            thread : &BTreeMap<usize, Thread>, page_num : usize)->usize {
+        if !swap_memory::enabled() {
+            return 0;
+        }
+        let hand = self.swap_hand.load(Ordering::SeqCst);
+        let mut pids : Vec<usize> = process.range(hand..).map(|(pid, _)| *pid).collect();
+        pids.extend(process.range(..hand).map(|(pid, _)| *pid));
+        let mut freed = 0;
+        for pid in pids {
+            if freed >= page_num {
+                break;
+            }
+            if thread.values().any(|t| {
+                t.info.pid == pid && t.info.state == TaskState::Running
+            }) {
+                continue;
+            }
+            freed += process.get_mut(&pid).unwrap().reclaim(page_num - freed);
+            self.swap_hand.store(pid + 1, Ordering::SeqCst);
         }
+        freed
     }
 }
 
@@ -35,8 +65,9 @@ impl TaskPoolOp for TaskPool{}
 /// 为了防止死锁，线程必须先于进程上锁
 impl TaskPoolBasicOp for TaskPool {
     fn create(&mut self, program : ProgramArea, env : &Environment)->Option<usize> {
-        let mut p = Process::new(program).unwrap();
-        let t = Thread::new(&p, env).unwrap();
+        let mut p = Process::new(program)?;
+        let stack_top = p.alloc_stack(MAX_STACK_PAGE)?;
+        let t = Thread::new(&p, env, stack_top, MAX_STACK_PAGE)?;
         let tid = t.info.tid;
         p.tid.push(t.info.tid);
         self.thread.lock().insert(t.info.tid, t);
@@ -52,28 +83,46 @@ impl TaskPoolBasicOp for TaskPool {
         let mut thread = self.thread.lock();
         let src_th = thread.get_mut(&id).unwrap();
         src_th.save(env);
-        let th = Thread::fork(src_th).unwrap();
-        let id = th.info.tid;
         let mut process = self.process.lock();
-        let p = process.get_mut(&th.info.pid).unwrap();
+        let p = process.get_mut(&src_th.info.pid).unwrap();
+        let (_, stack_page) = src_th.stack_range();
+        let stack_top = p.alloc_stack(stack_page)?;
+        let th = if let Some(th) = Thread::fork(src_th, stack_top) {
+            th
+        }
+        else {
+            p.free_stack(stack_top, stack_page);
+            return None;
+        };
+        let id = th.info.tid;
         p.tid.push(id);
         thread.insert(id, th);
         Some(id)
     }
 
-    fn branch(&mut self, env : &Environment)->Option<usize> {
+    fn branch(&mut self, env : &Environment, stack_size : usize)->Option<usize> {
+        let stack_page = if stack_size == 0 {
+            MAX_STACK_PAGE
+        }
+        else {
+            (stack_size + PAGE_SIZE - 1) / PAGE_SIZE
+        };
+        if stack_page > MAX_STACK_LIMIT {
+            return None;
+        }
         let id = self.find(|info| {
             info.state == TaskState::Running && info.env.hartid == env.hartid
         }).unwrap();
         let mut thread = self.thread.lock();
         let src_th = (*thread).get_mut(&id).unwrap();
         src_th.save(env);
-        let th = Thread::branch(src_th).unwrap();
+        let mut process = self.process.lock();
+        let p = process.get_mut(&src_th.info.pid).unwrap();
+        let stack_top = p.alloc_stack(stack_page)?;
+        let th = Thread::branch(src_th, stack_top, stack_page).unwrap();
         let id = th.info.tid;
-        let pid = th.info.pid;
+        p.tid.push(id);
         (*thread).insert(id, th);
-        let mut process = self.process.lock();
-        process.get_mut(&pid).unwrap().tid.push(id);
         Some(id)
     }
 
@@ -174,8 +223,8 @@ impl TaskPoolBasicOp for TaskPool {
 
     fn remove_task(&mut self, id : usize)->Result<(), ()> {
         let mut thread = self.thread.lock();
-        let pid = thread.get(&id).unwrap().info.pid;
-        thread.remove(&id);
+        let th = thread.remove(&id).unwrap();
+        let pid = th.info.pid;
         let list = self.waiting_list.lock();
         if let Some(waiter) = list.get(&id) {
             for id in waiter {
@@ -184,6 +233,9 @@ impl TaskPoolBasicOp for TaskPool {
         }
         let mut process = self.process.lock();
         let p = process.get_mut(&pid).unwrap();
+        let (stack_top, stack_page) = th.stack_range();
+        drop(th);
+        p.free_stack(stack_top, stack_page);
         for (idx, tid) in p.tid.iter().enumerate() {
             if *tid == id {
                 p.tid.remove(idx);
@@ -230,12 +282,18 @@ impl TaskPoolBasicOp for TaskPool {
 }
 
 impl TaskComplexOp for TaskPool {
-    fn alloc_heap(&mut self, size : usize, id : usize)->(usize, usize) {
+    fn alloc_heap(&mut self, size : usize, id : usize)->Result<(usize, usize), MemoryError> {
         let thread = self.thread.lock();
         let pid = thread.get(&id).unwrap().info.pid;
         let mut process = self.process.lock();
-        let p = process.get_mut(&pid).unwrap();
-        p.alloc_heap(size)
+        let rt = process.get_mut(&pid).unwrap().alloc_heap(size);
+        if let Err(MemoryError::OutOfMemory) = rt {
+            let page_num = size * POOL_RATE / PAGE_SIZE + 1;
+            if self.reclaim(&mut process, &thread, page_num) > 0 {
+                return process.get_mut(&pid).unwrap().alloc_heap(size);
+            }
+        }
+        rt
     }
 
     fn free_heap(&mut self, addr : usize, id : usize) {
@@ -246,17 +304,44 @@ impl TaskComplexOp for TaskPool {
         p.free_heap(addr);
     }
 
-    fn virt_to_phy(&self, id:usize, va:usize)->usize {
+    fn virt_to_phy(&self, id:usize, va:usize)->Option<usize> {
         let thread = self.thread.lock();
         let t = thread.get(&id).unwrap();
         let pid = t.info.pid;
-        let process = self.process.lock();
-        let p = process.get(&pid).unwrap();
+        let mut process = self.process.lock();
+        let p = process.get_mut(&pid).unwrap();
         if p.contain(va) {
-            p.virt_to_phy(va)
+            if let Ok(pa) = p.virt_to_phy(va) {
+                return Some(pa);
+            }
+            let page_num = MAX_BLOCK_SIZE * POOL_RATE / PAGE_SIZE;
+            self.reclaim(&mut process, &thread, page_num);
+            let rt = process.get_mut(&pid).unwrap().virt_to_phy(va).ok();
+            if rt.is_none() {
+                oom::record(page_num * PAGE_SIZE);
+            }
+            rt
         }
         else {
-            t.virt_to_phy(va)
+            Some(t.virt_to_phy(va))
+        }
+    }
+
+    fn swap_in(&mut self, id : usize, va : usize)->Result<(), ()> {
+        let thread = self.thread.lock();
+        let pid = thread.get(&id).unwrap().info.pid;
+        let mut process = self.process.lock();
+        let rt = process.get_mut(&pid).unwrap().swap_in(va);
+        let rt = if let Err(MemoryError::OutOfMemory) = rt {
+            self.reclaim(&mut process, &thread, MAX_BLOCK_SIZE * POOL_RATE / PAGE_SIZE);
+            process.get_mut(&pid).unwrap().swap_in(va)
+        }
+        else {
+            rt
+        };
+        match rt {
+            Ok(true) => Ok(()),
+            _ => Err(()),
         }
     }
 
@@ -315,12 +400,16 @@ impl TaskComplexOp for TaskPool {
         time_list.sort();
     }
 
-    fn expand_stack(&mut self, id : usize)->Result<(),()> {
+    fn expand_stack(&mut self, id : usize, va : usize)->Result<(),()> {
         let mut thread = self.thread.lock();
         let th = thread.get_mut(&id).unwrap();
+        if th.is_stack_overflow(va) {
+            println!("thread #{}# stack overflow at {:x}", id, va);
+            return Err(());
+        }
         let process = self.process.lock();
         let p = process.get(&th.info.pid).unwrap();
-        th.expand_stack(&p.info.satp)
+        th.expand_stack(va, &p.info.satp)
     }
 
     fn join(&mut self, id : usize) {
@@ -339,6 +428,40 @@ impl TaskComplexOp for TaskPool {
             t.info.state = TaskState::Sleeping;
         }
     }
+
+    fn oom_victim(&mut self)->Option<(usize, usize, usize)> {
+        let thread = self.thread.lock();
+        let process = self.process.lock();
+        let mut rt = None;
+        let mut mx = 0;
+        for (pid, p) in process.iter() {
+            if p.is_kernel || p.tid.len() == 0 {
+                continue;
+            }
+            let mut pages = p.resident_pages();
+            let mut main = p.tid[0];
+            // 运行中的线程还在使用栈与堆，不能释放
+            let running = p.tid.iter().any(|tid| {
+                thread.get(tid).map(|t| t.info.state == TaskState::Running).unwrap_or(false)
+            });
+            if running {
+                continue;
+            }
+            for tid in p.tid.iter() {
+                if let Some(t) = thread.get(tid) {
+                    pages += t.stack_pages();
+                    if t.info.is_main {
+                        main = *tid;
+                    }
+                }
+            }
+            if pages > mx {
+                mx = pages;
+                rt = Some((main, *pid, pages));
+            }
+        }
+        rt
+    }
 }
 
 impl TaskResourceOp for TaskPool {
@@ -353,6 +476,18 @@ impl TaskResourceOp for TaskPool {
         let mut process = self.process.lock();
         process.get_mut(&pid).unwrap().release_file(file_id);
     }
+
+    fn push_socket(&mut self, task_id : usize, socket_id:usize) {
+        let pid = self.thread.lock().get(&task_id).unwrap().info.pid;
+        let mut process = self.process.lock();
+        process.get_mut(&pid).unwrap().push_socket(socket_id);
+    }
+
+    fn release_socket(&mut self, task_id : usize, socket_id:usize) {
+        let pid = self.thread.lock().get(&task_id).unwrap().info.pid;
+        let mut process = self.process.lock();
+        process.get_mut(&pid).unwrap().release_socket(socket_id);
+    }
 }
 
 impl TaskScheduleOp for TaskPool {
@@ -360,3 +495,48 @@ impl TaskScheduleOp for TaskPool {
         self.thread.lock().get_mut(&id).unwrap().info.priority = priority;
     }
 }
+
+/// ## 任务池的锁
+/// 持有期间记录到内存耗尽处理中，此时内核申请失败不能就地结束进程
+struct PoolMutex<T> {
+    inner : ContentMutex<T>,
+}
+
+impl<T> PoolMutex<T> {
+    fn new(val : T)->Self {
+        Self {
+            inner : ContentMutex::new(val, true),
+        }
+    }
+
+    fn lock(&self)->PoolGuard<impl DerefMut<Target = T> + '_> {
+        oom::hold();
+        PoolGuard {
+            guard : self.inner.lock(),
+        }
+    }
+}
+
+struct PoolGuard<G> {
+    guard : G,
+}
+
+impl<G : Deref> Deref for PoolGuard<G> {
+    type Target = G::Target;
+
+    fn deref(&self)->&G::Target {
+        &self.guard
+    }
+}
+
+impl<G : DerefMut> DerefMut for PoolGuard<G> {
+    fn deref_mut(&mut self)->&mut G::Target {
+        &mut self.guard
+    }
+}
+
+impl<G> Drop for PoolGuard<G> {
+    fn drop(&mut self) {
+        oom::release();
+    }
+}
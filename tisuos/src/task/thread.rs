@@ -10,7 +10,10 @@ extern "C" {
     fn process_exit();
 }
 
+/// 默认的栈大小
 pub const MAX_STACK_PAGE : usize = 64;
+/// 允许申请的最大栈大小
+pub const MAX_STACK_LIMIT : usize = 1024 * 16;
 pub const STACK_PAGE_NUM : usize = 16;
 
 /// ## 线程
@@ -23,14 +26,13 @@ pub struct Thread{
 /// ## 线程功能
 /// fork 拷贝原线程栈内容然后创建新的分支
 /// branch 直接根据传入地址（默认是原线程的第二个参数）创建新的线程
+/// 栈的虚拟地址范围由进程分配，见 Process::alloc_stack
 impl Thread {
     /// ## 为进程新建主线程线程
     /// 为线程映射栈，同时分别为用户、内核进程映射不同的内存区域
-    pub fn new(p : &Process, src_env : &Environment)->Option<Self>{
+    pub fn new(p : &Process, src_env : &Environment, stack_top : usize, stack_page : usize)->Option<Self>{
         let mut env = Environment::new();
         let tid = unsafe{ THREAD_CNT.add() };
-        let t = unsafe {MEMORY_END} / PAGE_SIZE * PAGE_SIZE + MAX_HEAP_SIZE;
-        let stack_top = t + (tid + 1) * MAX_STACK_PAGE * 2 * PAGE_SIZE;
         env.epc = p.entry();
         env.satp = p.info.satp.val();
         env.regs[Register::A0.val()] = src_env.a3();
@@ -38,8 +40,8 @@ impl Thread {
         env.regs[Register::A2.val()] = src_env.a5();
         env.regs[Register::SP.val()] = stack_top;
         env.regs[Register::RA.val()] = process_exit as usize;
-        let mut stack = TaskStack::new(tid, stack_top, MAX_STACK_PAGE, p.is_kernel);
-        stack.expand(STACK_PAGE_NUM, &p.info.satp).unwrap();
+        let mut stack = TaskStack::new(tid, stack_top, stack_page, p.is_kernel);
+        stack.expand(STACK_PAGE_NUM.min(stack_page), &p.info.satp).ok()?;
         Some(Self{
             info : ExecutionInfo {
                 priority : 1,
@@ -57,14 +59,12 @@ impl Thread {
     }
 
     /// ## 原地分支执行
-    /// 将会拷贝原线程栈环境
-    pub fn fork(src_th : &Thread)->Option<Self>{
+    /// 将会拷贝原线程栈环境，栈范围大小与原线程相同
+    pub fn fork(src_th : &Thread, stack_top : usize)->Option<Self>{
         let mut env = src_th.info.env;
         let tid = unsafe{ THREAD_CNT.add() };
-        let t = unsafe {MEMORY_END} / PAGE_SIZE * PAGE_SIZE + MAX_HEAP_SIZE;
-        let stack_top = t + (tid + 1) * MAX_STACK_PAGE * 2 * PAGE_SIZE;
-        let mut stack = TaskStack::new(tid, stack_top, MAX_STACK_PAGE, src_th.info.is_kernel);
-        stack.copy(&src_th.stack, &SATP::from(src_th.info.env.satp));
+        let mut stack = TaskStack::new(tid, stack_top, src_th.stack.max_page(), src_th.info.is_kernel);
+        stack.copy(&src_th.stack, &SATP::from(src_th.info.env.satp)).ok()?;
 
         env.epc = src_th.info.env.epc + 4;
         env.regs[Register::SP.val()] = stack_top -
@@ -90,11 +90,9 @@ impl Thread {
 
     /// ## 函数分支执行
     /// 区别于 fork，从传入的地址（应该是一个函数地址）开始执行，用全新的栈环境
-    pub fn branch(src_th : &Thread)->Option<Self>{
+    pub fn branch(src_th : &Thread, stack_top : usize, stack_page : usize)->Option<Self>{
         let mut env = src_th.info.env;
         let tid = unsafe{ THREAD_CNT.add() };
-        let t = unsafe {MEMORY_END} / PAGE_SIZE * PAGE_SIZE + MAX_HEAP_SIZE;
-        let stack_top = t + (tid + 1) * MAX_STACK_PAGE * 2 * PAGE_SIZE;
         env.epc = src_th.info.env.regs[Register::A1.val()];
         env.regs[Register::A0.val()] = env.a2();
         env.regs[Register::RA.val()] = thread_exit as usize;
@@ -111,7 +109,7 @@ impl Thread {
                 is_main : false,
                 trigger_time : 0,
             },
-            stack : TaskStack::new(tid, stack_top, MAX_STACK_PAGE, src_th.info.is_kernel),
+            stack : TaskStack::new(tid, stack_top, stack_page, src_th.info.is_kernel),
         })
     }
 
@@ -123,8 +121,13 @@ impl Thread {
         self.info.state = TaskState::Waiting
     }
 
-    pub fn expand_stack(&mut self, satp : &SATP)->Result<(), ()> {
-        self.stack.expand(STACK_PAGE_NUM, satp)
+    /// 缺页地址位于栈的保留范围内时扩展栈
+    pub fn expand_stack(&mut self, va : usize, satp : &SATP)->Result<(), ()> {
+        self.stack.expand_to(va, STACK_PAGE_NUM, satp)
+    }
+
+    pub fn is_stack_overflow(&self, va : usize)->bool {
+        self.stack.is_guard(va)
     }
 }
 
@@ -141,6 +144,15 @@ impl Thread {
     pub fn virt_to_phy(&self, va:usize)->usize {
         self.stack.virt_to_phy(va)
     }
+
+    pub fn stack_pages(&self)->usize {
+        self.stack.page_num()
+    }
+
+    /// 返回（栈顶，栈的最大页面数），用于归还栈范围
+    pub fn stack_range(&self)->(usize, usize) {
+        (self.info.stack_top as usize, self.stack.max_page())
+    }
 }
 
 
@@ -153,7 +165,7 @@ pub fn init(){
 
 use tisu_sync::AtomCounter;
 
-use crate::{interrupt::environment::{Environment, Register}, memory::{TaskStack, config::{MEMORY_END, PAGE_SIZE}, map::SATP}, task::process::MAX_HEAP_SIZE};
+use crate::{interrupt::environment::{Environment, Register}, memory::{TaskStack, map::SATP}};
 
 use super::{
     task_info::ExecutionInfo, process::Process,
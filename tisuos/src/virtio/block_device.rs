@@ -0,0 +1,235 @@
+#![allow(dead_code)]
+//! # 块设备驱动
+//! virtio 块设备，使用一个虚拟队列。请求由头、数据、状态三个描述符组成
+//! 提交后立即返回，设备完成时发出中断，由请求队列在中断中回收
+//! 请求队列保证每个设备同时只有一个请求在执行，因此固定使用前三个描述符
+//! 内核内存是恒等映射的，缓冲区的地址即物理地址
+//!
+//! 2021年6月16日 zg
+
+const RING_SIZE : usize = 8;
+pub const SECTOR_SIZE : usize = 512;
+/// 请求头的长度，状态紧跟在后面
+const HEADER_SIZE : usize = 16;
+
+const DESC_F_NEXT : u16 = 1;
+const DESC_F_WRITE : u16 = 2;
+
+const BLK_T_IN : u32 = 0;
+const BLK_T_OUT : u32 = 1;
+const BLK_F_RO : u32 = 5;
+/// 设备写回状态前填入的值
+const STATUS_PENDING : u8 = 0xff;
+
+const MAGIC_VALUE : usize = 0x000;
+const HOST_FEATURES : usize = 0x010;
+const GUEST_FEATURES : usize = 0x020;
+const GUEST_PAGE_SIZE : usize = 0x028;
+const QUEUE_SEL : usize = 0x030;
+const QUEUE_NUM_MAX : usize = 0x034;
+const QUEUE_NUM : usize = 0x038;
+const QUEUE_ALIGN : usize = 0x03c;
+const QUEUE_PFN : usize = 0x040;
+const QUEUE_NOTIFY : usize = 0x050;
+const INTERRUPT_STATUS : usize = 0x060;
+const INTERRUPT_ACK : usize = 0x064;
+const STATUS : usize = 0x070;
+/// 设备配置，块设备的前 8 字节为容量（扇区数）
+const CONFIG : usize = 0x100;
+
+const STATUS_ACKNOWLEDGE : u32 = 1;
+const STATUS_DRIVER : u32 = 2;
+const STATUS_DRIVER_OK : u32 = 4;
+const STATUS_FEATURES_OK : u32 = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr : u64,
+    len : u32,
+    flags : u16,
+    next : u16,
+}
+
+#[repr(C)]
+struct Available {
+    flags : u16,
+    idx : u16,
+    ring : [u16;RING_SIZE],
+    event : u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id : u32,
+    len : u32,
+}
+
+#[repr(C)]
+struct Used {
+    flags : u16,
+    idx : u16,
+    ring : [UsedElem;RING_SIZE],
+    event : u16,
+}
+
+/// 旧版接口要求 used 从页面边界开始
+#[repr(C)]
+struct Queue {
+    desc : [Descriptor;RING_SIZE],
+    avail : Available,
+    padding : [u8;PAGE_SIZE - size_of::<Descriptor>() * RING_SIZE - size_of::<Available>()],
+    used : Used,
+}
+
+#[repr(C)]
+struct Request {
+    blktype : u32,
+    reserved : u32,
+    sector : u64,
+    status : u8,
+}
+
+/// ## 块设备
+pub struct BlockDevice {
+    header : *mut u32,
+    queue : *mut Queue,
+    /// 请求头与状态，执行期间设备会访问
+    request : Box<Request>,
+    used_idx : u16,
+}
+
+impl BlockDevice {
+    /// 初始化设备与虚拟队列，失败返回 None
+    pub fn new(header : *mut u32)->Option<Self> {
+        let mut rt = Self {
+            header,
+            queue : null_mut(),
+            request : Box::new(Request {
+                blktype : 0,
+                reserved : 0,
+                sector : 0,
+                status : 0,
+            }),
+            used_idx : 0,
+        };
+        if rt.read(MAGIC_VALUE) != VIRTIO_VAL {
+            return None;
+        }
+        rt.write(STATUS, 0);
+        let mut status = STATUS_ACKNOWLEDGE;
+        rt.write(STATUS, status);
+        status |= STATUS_DRIVER;
+        rt.write(STATUS, status);
+        let features = rt.read(HOST_FEATURES) & !(1 << BLK_F_RO);
+        rt.write(GUEST_FEATURES, features);
+        status |= STATUS_FEATURES_OK;
+        rt.write(STATUS, status);
+        if rt.read(STATUS) & STATUS_FEATURES_OK == 0 {
+            println!("block device features fail");
+            return None;
+        }
+        rt.write(QUEUE_SEL, 0);
+        if (rt.read(QUEUE_NUM_MAX) as usize) < RING_SIZE {
+            println!("block device queue too small");
+            return None;
+        }
+        rt.write(QUEUE_NUM, RING_SIZE as u32);
+        let num = (size_of::<Queue>() + PAGE_SIZE - 1) / PAGE_SIZE;
+        let queue = alloc_kernel_page(num)?;
+        unsafe {
+            queue.write_bytes(0, num * PAGE_SIZE);
+        }
+        rt.queue = queue as *mut Queue;
+        rt.write(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        rt.write(QUEUE_ALIGN, PAGE_SIZE as u32);
+        rt.write(QUEUE_PFN, (queue as usize / PAGE_SIZE) as u32);
+        status |= STATUS_DRIVER_OK;
+        rt.write(STATUS, status);
+        Some(rt)
+    }
+
+    /// ## 提交请求
+    /// st、buf 的长度必须按扇区对齐，buf 在完成之前不能释放
+    pub fn submit(&mut self, write : bool, st : usize, buf : &mut [u8]) {
+        self.request.blktype = if write { BLK_T_OUT } else { BLK_T_IN };
+        self.request.reserved = 0;
+        self.request.sector = (st / SECTOR_SIZE) as u64;
+        self.request.status = STATUS_PENDING;
+        let rq = &*self.request as *const Request as usize;
+        unsafe {
+            let queue = &mut *self.queue;
+            queue.desc[0] = Descriptor {
+                addr : rq as u64,
+                len : HEADER_SIZE as u32,
+                flags : DESC_F_NEXT,
+                next : 1,
+            };
+            queue.desc[1] = Descriptor {
+                addr : buf.as_mut_ptr() as u64,
+                len : buf.len() as u32,
+                flags : DESC_F_NEXT | if write { 0 } else { DESC_F_WRITE },
+                next : 2,
+            };
+            queue.desc[2] = Descriptor {
+                addr : (rq + HEADER_SIZE) as u64,
+                len : 1,
+                flags : DESC_F_WRITE,
+                next : 0,
+            };
+            let idx = (&queue.avail.idx as *const u16).read_volatile();
+            queue.avail.ring[idx as usize % RING_SIZE] = 0;
+            fence(Ordering::SeqCst);
+            (&mut queue.avail.idx as *mut u16).write_volatile(idx.wrapping_add(1));
+            fence(Ordering::SeqCst);
+        }
+        self.write(QUEUE_NOTIFY, 0);
+    }
+
+    /// ## 回收请求
+    /// 设备已完成提交的请求时返回 true，出错时打印状态
+    pub fn complete(&mut self)->bool {
+        let idx = unsafe { (&(*self.queue).used.idx as *const u16).read_volatile() };
+        if idx == self.used_idx {
+            return false;
+        }
+        fence(Ordering::SeqCst);
+        self.used_idx = idx;
+        let status = unsafe { (&self.request.status as *const u8).read_volatile() };
+        if status != 0 {
+            println!("block device request sector {} status {}", self.request.sector, status);
+        }
+        true
+    }
+
+    /// 磁盘容量，单位字节
+    pub fn capacity(&self)->usize {
+        let low = self.read(CONFIG) as usize;
+        let high = self.read(CONFIG + 4) as usize;
+        ((high << 32) | low) * SECTOR_SIZE
+    }
+
+    /// 应答中断
+    pub fn pending(&mut self) {
+        let status = self.read(INTERRUPT_STATUS);
+        self.write(INTERRUPT_ACK, status);
+    }
+
+    fn read(&self, offset : usize)->u32 {
+        unsafe {
+            self.header.add(offset / 4).read_volatile()
+        }
+    }
+
+    fn write(&self, offset : usize, val : u32) {
+        unsafe {
+            self.header.add(offset / 4).write_volatile(val);
+        }
+    }
+}
+
+
+use core::{mem::size_of, ptr::null_mut, sync::atomic::{Ordering, fence}};
+use alloc::prelude::v1::*;
+use crate::memory::{config::PAGE_SIZE, oom::alloc_kernel_page};
+use super::device::VIRTIO_VAL;
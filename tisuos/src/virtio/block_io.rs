@@ -0,0 +1,236 @@
+//! # 块设备请求队列
+//! 每个块设备一个请求队列，方向相同、地址相邻的请求合并成一批交给设备
+//! 设备空闲时提交即开始执行，忙碌时只入队，完成中断中回收这一批并开始下一批
+//! 线程通过 IO_WAIT 睡眠，请求完成时在中断中被唤醒；陷入处理中不能睡眠，检查设备的完成情况等待
+//!
+//! 2021年6月16日 zg
+
+/// 合并后单批请求的最大长度
+const MAX_MERGE : usize = 128 * 1024;
+static mut BLOCK_IO : Option<ContentMutex<BlockIo>> = None;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BlockOp {
+    Read,
+    Write,
+}
+
+struct Request {
+    disk : usize,
+    st : usize,
+    /// 写请求为待写入的内容，读请求完成后为读到的内容
+    data : Vec<u8>,
+    done : bool,
+    /// 睡眠等待的线程
+    waiter : Option<usize>,
+}
+
+/// 合并后的一批请求，覆盖 [st, st + len)
+struct Batch {
+    op : BlockOp,
+    st : usize,
+    len : usize,
+    member : Vec<usize>,
+}
+
+struct Queue {
+    pending : VecDeque<Batch>,
+    /// 设备上正在执行的一批请求与其缓冲区，缓冲区在完成之前不能释放
+    running : Option<(Batch, Vec<u8>)>,
+}
+
+struct BlockIo {
+    request : BTreeMap<usize, Request>,
+    queue : Vec<Queue>,
+    next_id : usize,
+}
+
+impl BlockIo {
+    fn submit(&mut self, disk : usize, op : BlockOp, st : usize, data : Vec<u8>)->usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let len = data.len();
+        self.request.insert(id, Request {
+            disk,
+            st,
+            data,
+            done : false,
+            waiter : None,
+        });
+        let queue = &mut self.queue[disk];
+        // 只与队尾合并，不越过方向不同的请求，先提交的写入总在之后的读取之前完成
+        let mut merged = false;
+        if let Some(batch) = queue.pending.back_mut() {
+            if batch.op == op && batch.len + len <= MAX_MERGE {
+                if batch.st + batch.len == st {
+                    batch.len += len;
+                    batch.member.push(id);
+                    merged = true;
+                }
+                else if st + len == batch.st {
+                    batch.st = st;
+                    batch.len += len;
+                    batch.member.insert(0, id);
+                    merged = true;
+                }
+            }
+        }
+        if !merged {
+            queue.pending.push_back(Batch {
+                op,
+                st,
+                len,
+                member : vec![id],
+            });
+        }
+        self.start(disk);
+        id
+    }
+
+    /// ## 开始执行
+    /// 设备空闲时取出下一批请求交给设备，写请求拼接好要写入的内容
+    /// 读取的范围扩展到扇区边界，写请求由磁盘缓冲整块写回，本身是对齐的
+    fn start(&mut self, disk : usize) {
+        let queue = &mut self.queue[disk];
+        if queue.running.is_some() {
+            return;
+        }
+        let batch = if let Some(b) = queue.pending.pop_front() { b } else { return; };
+        let st = batch.st / SECTOR_SIZE * SECTOR_SIZE;
+        let mut buf;
+        if batch.op == BlockOp::Write {
+            buf = Vec::with_capacity(batch.len);
+            for id in batch.member.iter() {
+                buf.extend_from_slice(&self.request[id].data[..]);
+            }
+        }
+        else {
+            let ed = (batch.st + batch.len + SECTOR_SIZE - 1) / SECTOR_SIZE * SECTOR_SIZE;
+            buf = vec![0;ed - st];
+        }
+        get_device().block_device[disk].submit(batch.op == BlockOp::Write, st, &mut buf[..]);
+        self.queue[disk].running = Some((batch, buf));
+    }
+
+    /// ## 回收
+    /// 设备完成时取回正在执行的一批请求，开始下一批，返回是否有请求完成
+    fn complete(&mut self, disk : usize)->bool {
+        if self.queue[disk].running.is_none() || !get_device().block_device[disk].complete() {
+            return false;
+        }
+        let (batch, buf) = self.queue[disk].running.take().unwrap();
+        self.finish(batch, buf);
+        self.start(disk);
+        true
+    }
+
+    /// 一批请求完成，读请求取回各自的部分，唤醒等待的线程
+    fn finish(&mut self, batch : Batch, buf : Vec<u8>) {
+        let st = batch.st / SECTOR_SIZE * SECTOR_SIZE;
+        for id in batch.member {
+            let rq = self.request.get_mut(&id).unwrap();
+            if batch.op == BlockOp::Read {
+                let offset = rq.st - st;
+                let len = rq.data.len();
+                rq.data.copy_from_slice(&buf[offset..offset + len]);
+            }
+            rq.done = true;
+            if let Some(tid) = rq.waiter.take() {
+                get_task_mgr().unwrap().wake_task(tid);
+            }
+        }
+    }
+
+    fn take(&mut self, id : usize)->Option<Vec<u8>> {
+        if self.request.get(&id)?.done {
+            self.request.remove(&id).map(|rq| rq.data)
+        }
+        else {
+            None
+        }
+    }
+}
+
+pub fn init(disk_num : usize) {
+    let mut queue = Vec::new();
+    for _ in 0..disk_num {
+        queue.push(Queue {
+            pending : VecDeque::new(),
+            running : None,
+        });
+    }
+    unsafe {
+        BLOCK_IO = Some(ContentMutex::new(BlockIo {
+            request : BTreeMap::new(),
+            queue,
+            next_id : 1,
+        }, true));
+    }
+}
+
+fn get_block_io()->&'static mut ContentMutex<BlockIo> {
+    unsafe {
+        BLOCK_IO.as_mut().unwrap()
+    }
+}
+
+/// 提交一个请求，读请求的长度由 data 给出，返回请求号
+pub fn submit(disk : usize, op : BlockOp, st : usize, data : Vec<u8>)->usize {
+    get_block_io().lock().submit(disk, op, st, data)
+}
+
+/// ## 完成中断
+/// 回收设备上完成的一批请求，唤醒等待的线程，并开始下一批
+pub fn interrupt(disk : usize) {
+    let mut io = get_block_io().lock();
+    if disk < io.queue.len() {
+        io.complete(disk);
+    }
+}
+
+fn disk_of(id : usize)->Option<usize> {
+    get_block_io().lock().request.get(&id).map(|rq| rq.disk)
+}
+
+fn is_done(id : usize)->bool {
+    get_block_io().lock().request.get(&id).map_or(true, |rq| rq.done)
+}
+
+/// ## 等待请求完成
+/// 线程中通过 IO_WAIT 睡眠，由完成中断唤醒
+/// 陷入处理中不能睡眠，中断也可能被屏蔽，直接检查设备是否完成
+pub fn wait(id : usize)->Option<Vec<u8>> {
+    let disk = disk_of(id)?;
+    if interrupt_enabled() {
+        while !is_done(id) {
+            io_wait(id);
+        }
+    }
+    else {
+        while !is_done(id) {
+            get_block_io().lock().complete(disk);
+        }
+    }
+    get_block_io().lock().take(id)
+}
+
+/// ## 登记等待
+/// 请求未完成时记录等待的线程并调用 sleep，两者在同一次加锁内完成，不会错过唤醒
+/// 返回是否睡眠
+pub fn sleep_on<F : FnOnce(usize)>(id : usize, tid : usize, sleep : F)->bool {
+    let mut io = get_block_io().lock();
+    if let Some(rq) = io.request.get_mut(&id) {
+        if !rq.done {
+            rq.waiter = Some(tid);
+            sleep(tid);
+            return true;
+        }
+    }
+    false
+}
+
+
+use alloc::{collections::{BTreeMap, VecDeque}, prelude::v1::*};
+use tisu_sync::ContentMutex;
+use crate::{libs::{cpu::interrupt_enabled, syscall::io_wait}, task::get_task_mgr};
+use super::{block_device::SECTOR_SIZE, device::get_device};
@@ -6,16 +6,15 @@
 const VIRTIO_START_ADDR : usize = 0x1000_1000;
 const VIRTIO_END_ADDR : usize = 0x1000_8000;
 const VIRTIO_STEP_SIZE : usize = 0x1000;
-const VIRTIO_VAL : u32 = 0x74726976;
+pub const VIRTIO_VAL : u32 = 0x74726976;
 
-type BlockDevice = tisu_driver::Block;
 type GraphicDevice = tisu_driver::GPU;
 type InputDevice = tisu_driver::InputDevice;
 type NetDevice = tisu_driver::Net;
 
 /// ## 全局设备管理
 pub struct Device {
-    pub block_device : Vec<Box<dyn BlockDriver>>,
+    pub block_device : Vec<BlockDevice>,
     pub gpu_device : Vec<Box<dyn GraphicDriver>>,
     pub input_device : Vec<Box<dyn Driver>>,
     pub net_device : Vec<Box<dyn NetDriver>>,
@@ -56,12 +55,13 @@ impl Device {
         let memory = get_manager();
         match DeviceType::from(device_id as usize) {
             DeviceType::Block => {
-                self.dtype[pin_idx] = (DeviceType::Block, self.block_device.len());
-                let b = Box::new(BlockDevice::new(header,memory));
-                self.block_device.push(b);
+                if let Some(b) = BlockDevice::new(header as *mut u32) {
+                    self.dtype[pin_idx] = (DeviceType::Block, self.block_device.len());
+                    self.block_device.push(b);
+                }
             }
             DeviceType::Gpu => {
-                self.dtype[pin_idx] = (DeviceType::Block, self.gpu_device.len());
+                self.dtype[pin_idx] = (DeviceType::Gpu, self.gpu_device.len());
                 let b = Box::new(GraphicDevice::new(header, WIDTH, HEIGHT, memory));
                 self.gpu_device.push(b);
             }
@@ -85,7 +85,7 @@ impl Device {
         match self.dtype[pin_idx].0 {
             DeviceType::Block => {
                 let blk = self.block_device.get_mut(self.dtype[pin_idx].1).unwrap();
-                blk.pending().unwrap();
+                blk.pending();
             }
             DeviceType::Gpu => {
                 let gpu = self.gpu_device.get_mut(self.dtype[pin_idx].1).unwrap();
@@ -98,6 +98,10 @@ impl Device {
                     println!("{:?}", e);
                 }
             }
+            DeviceType::Network => {
+                let net = self.net_device.get_mut(self.dtype[pin_idx].1).unwrap();
+                net.pending().unwrap();
+            }
             _ => {
                 println!("device pending err pin idx {}", pin_idx);
             }
@@ -107,8 +111,8 @@ impl Device {
     pub fn handler(&mut self, pin_idx : usize) {
         match self.dtype[pin_idx].0 {
             DeviceType::Block => {
-                let blk = self.block_device.get_mut(self.dtype[pin_idx].1).unwrap();
-                blk.handler().unwrap();
+                // 完成的请求在队列中回收，并唤醒等待的线程
+                block_io::interrupt(self.dtype[pin_idx].1);
             }
             DeviceType::Gpu => {
                 let gpu = self.gpu_device.get_mut(self.dtype[pin_idx].1).unwrap();
@@ -153,6 +157,18 @@ impl Device {
                     }
                 }
             }
+            DeviceType::Network => {
+                let idx = self.dtype[pin_idx].1;
+                let net = self.net_device.get_mut(idx).unwrap();
+                let mut frames = Vec::new();
+                while let Ok(InterruptOk::Net(frame)) = net.handler() {
+                    frames.push(frame);
+                }
+                // 处理时可能需要发送，先释放对设备的引用
+                for frame in frames {
+                    net::receive(idx, &frame);
+                }
+            }
             _ => {
                 println!("device handle err pin idx {}", pin_idx);
             }
@@ -174,14 +190,6 @@ impl Device {
             gpu.refresh();
         }
     }
-
-    #[allow(dead_code)]
-    pub fn send(&mut self) {
-        let net = self.net_device.get_mut(0).unwrap();
-        let data = Block::<Ip>::new(1); 
-        data.set(0, Ip::new(), 1);
-        net.send(&data.to_array(0, 1));
-    }
 }
 
 pub static mut DEVICE : Option<Device> = None;
@@ -244,8 +252,8 @@ pub fn invalid(){
 use core::ptr::slice_from_raw_parts;
 
 use alloc::prelude::v1::*;
-use crate::{filesystem::push_input, memory::{block::Block, get_manager}};
-use super::{config::{HEIGHT, WIDTH}, input_buffer::{add_key_press, add_key_release, add_mouse_x, add_mouse_y, add_scroll}, ip::Ip};
+use crate::{filesystem::push_input, memory::get_manager, net};
+use super::{block_device::BlockDevice, block_io, config::{HEIGHT, WIDTH}, input_buffer::{add_key_press, add_key_release, add_mouse_x, add_mouse_y, add_scroll}};
 use virtio_input_decoder::Decoder;
-use tisu_driver::{BlockDriver, DeviceType, Driver, GraphicDriver, Pixel, Rect, VirtHeader, NetDriver};
+use tisu_driver::{DeviceType, Driver, GraphicDriver, Pixel, Rect, VirtHeader, NetDriver};
 use tisu_driver::InterruptOk;
\ No newline at end of file
@@ -1,26 +1,337 @@
 //! # 磁盘缓冲
-//! 作为磁盘读写的中介，每个磁盘都有相同数量的缓冲区。优先替换最近最少使用的缓冲区
+//! 作为磁盘读写的中介，以 4096 字节的块为单位缓冲磁盘内容。干净的块过多时替换最近最少使用的
+//! 写入先记录为脏块，由后台线程定期写回，也可以通过 sync、fsync 主动写回，关机前全部写回
+//! 与底层的读写都经过块设备请求队列，写回时相邻的脏块会被合并
+//! 等待磁盘时不持有缓冲的锁，线程可以睡眠，陷入处理也可以同时访问缓冲
+//! 对外以卷为单位读写，卷是磁盘上的一段连续区域，编号小于磁盘数的卷即整个磁盘
 //!
 //! 2021年4月 zg
 
-use device_buffer::{Cache, CacheBuffer};
-use crate::memory::get_manager;
-use super::device::get_device;
+use alloc::{collections::BTreeMap, prelude::v1::*};
+use device_buffer::CacheBuffer;
+use tisu_sync::ContentMutex;
+use crate::{interrupt::timer::{FREQUENCY, get_million_time}, libs::syscall::set_timer};
+use super::{block_io::{self, BlockOp}, device::get_device};
 
 
-const BUFFER_SIZE : usize = 1024 * 4096; // 4 MB
-pub static mut CACHE : Option<Cache> = None;
+/// 缓冲的块的大小，读写磁盘以块为单位
+const BLOCK_SIZE : usize = 4096;
+/// 每个磁盘最多保留的干净块数量，共 4 MB
+const MAX_CLEAN : usize = 1024;
+/// 每个磁盘最多保留的脏块数量，超出时先写回最早变脏的块
+const MAX_DIRTY : usize = 1024;
+/// 后台写回的周期
+const FLUSH_INTERVAL : usize = 5 * FREQUENCY;
+pub static mut CACHE : Option<WriteBackCache> = None;
+
+struct DirtyBlock {
+    data : Vec<u8>,
+    /// 变脏的时间，单位毫秒
+    time : usize,
+    /// 每次写入递增，写回完成时据此判断期间是否又被修改
+    version : usize,
+}
+
+/// 与磁盘内容一致的块
+struct CleanBlock {
+    data : Vec<u8>,
+    /// 最近使用的时间，单位毫秒
+    time : usize,
+}
+
+/// ## 卷
+/// 磁盘上的一段连续区域，读写地址加上 offset 后交给磁盘
+#[derive(Clone, Copy)]
+pub struct Volume {
+    pub disk : usize,
+    pub offset : usize,
+    pub size : usize,
+}
+
+struct DiskCache {
+    /// 每个磁盘的脏块，块号 -> 内容
+    dirty : Vec<BTreeMap<usize, DirtyBlock>>,
+    /// 每个磁盘的干净块，块号 -> 内容，与脏块不重叠
+    clean : Vec<BTreeMap<usize, CleanBlock>>,
+    version : usize,
+    volume : Vec<Volume>,
+    /// 正在记录的事务，卷号 -> 按顺序记录的写入，提交前不进入缓冲
+    capture : BTreeMap<usize, Vec<(usize, Vec<u8>)>>,
+}
+
+impl DiskCache {
+    /// 把卷内地址转换为磁盘地址，越界返回 None
+    fn translate(&self, volume : usize, st : usize, len : usize)->Option<(usize, usize)> {
+        let v = self.volume.get(volume)?;
+        if st + len > v.size {
+            println!("volume {} access out of range {:x}", volume, st);
+            return None;
+        }
+        Some((v.disk, v.offset + st))
+    }
+
+    /// ## 读取缓冲
+    /// 把已缓冲的块中 [st, st + data.len()) 的部分复制到 data，返回没有缓冲的块号
+    fn copy_out(&mut self, disk : usize, data : &mut [u8], st : usize)->Vec<usize> {
+        let mut missing = Vec::new();
+        if data.len() == 0 {
+            return missing;
+        }
+        let ed = st + data.len();
+        let now = get_million_time();
+        for block in st / BLOCK_SIZE..=(ed - 1) / BLOCK_SIZE {
+            let bst = block * BLOCK_SIZE;
+            let from = st.max(bst);
+            let to = ed.min(bst + BLOCK_SIZE);
+            let src = if let Some(d) = self.dirty[disk].get(&block) {
+                &d.data
+            }
+            else if let Some(c) = self.clean[disk].get_mut(&block) {
+                c.time = now;
+                &c.data
+            }
+            else {
+                missing.push(block);
+                continue;
+            };
+            data[from - st..to - st].copy_from_slice(&src[from - bst..to - bst]);
+        }
+        missing
+    }
+
+    /// 返回 [st, st + len) 中没有缓冲的块号
+    fn missing(&self, disk : usize, st : usize, len : usize)->Vec<usize> {
+        if len == 0 {
+            return Vec::new();
+        }
+        (st / BLOCK_SIZE..=(st + len - 1) / BLOCK_SIZE).filter(|block| {
+            !self.dirty[disk].contains_key(block) && !self.clean[disk].contains_key(block)
+        }).collect()
+    }
+
+    /// 记录与磁盘一致的块，超出数量时丢弃最近最少使用的
+    fn keep(&mut self, disk : usize, block : usize, data : Vec<u8>) {
+        self.clean[disk].insert(block, CleanBlock {
+            data,
+            time : get_million_time(),
+        });
+        while self.clean[disk].len() > MAX_CLEAN {
+            let oldest = self.clean[disk].iter().min_by_key(|(_, c)| c.time).map(|(b, _)| *b).unwrap();
+            self.clean[disk].remove(&oldest);
+        }
+    }
+
+    /// ## 填入读到的块
+    /// 读取提交后没有任何写入时才缓冲，否则磁盘上的内容可能已经过时
+    fn fill(&mut self, disk : usize, block : usize, data : Vec<u8>, version : usize) {
+        if version == self.version && !self.dirty[disk].contains_key(&block)
+                && !self.clean[disk].contains_key(&block) {
+            self.keep(disk, block, data);
+        }
+    }
+
+    /// ## 写入
+    /// 干净的块转为脏块，没有缓冲的块以 loaded 中读到的内容为基础
+    /// 读取提交后有过写入时读到的内容可能已经过时，不能使用，返回 Err(需要读取的块号)
+    /// 成功返回脏块过多时写回的请求号，由调用者释放锁后等待
+    fn write(&mut self, disk : usize, data : &[u8], st : usize,
+            loaded : &mut BTreeMap<usize, Vec<u8>>, version : usize)->Result<Vec<usize>, Vec<usize>> {
+        if data.len() == 0 {
+            return Ok(Vec::new());
+        }
+        if version != self.version {
+            loaded.clear();
+        }
+        let missing : Vec<usize> = self.missing(disk, st, data.len()).into_iter()
+            .filter(|block| !loaded.contains_key(block)).collect();
+        if missing.len() > 0 {
+            return Err(missing);
+        }
+        let ed = st + data.len();
+        let now = get_million_time();
+        self.version += 1;
+        for block in st / BLOCK_SIZE..=(ed - 1) / BLOCK_SIZE {
+            let bst = block * BLOCK_SIZE;
+            if !self.dirty[disk].contains_key(&block) {
+                let data = if let Some(c) = self.clean[disk].remove(&block) {
+                    c.data
+                }
+                else {
+                    loaded.remove(&block).unwrap()
+                };
+                self.dirty[disk].insert(block, DirtyBlock {
+                    data,
+                    time : now,
+                    version : 0,
+                });
+            }
+            let from = st.max(bst);
+            let to = ed.min(bst + BLOCK_SIZE);
+            let d = self.dirty[disk].get_mut(&block).unwrap();
+            d.version = self.version;
+            d.data[from - bst..to - bst].copy_from_slice(&data[from - st..to - st]);
+        }
+        let mut rt = Vec::new();
+        while self.dirty[disk].len() > MAX_DIRTY {
+            let oldest = self.dirty[disk].iter().min_by_key(|(_, d)| d.time).map(|(b, _)| *b).unwrap();
+            rt.push(self.write_back(disk, oldest));
+        }
+        Ok(rt)
+    }
+
+    /// 提交脏块的写回，之后的读取排在这次写入之后，可以直接转为干净块
+    fn write_back(&mut self, disk : usize, block : usize)->usize {
+        let d = self.dirty[disk].remove(&block).unwrap();
+        let id = block_io::submit(disk, BlockOp::Write, block * BLOCK_SIZE, d.data.clone());
+        self.keep(disk, block, d.data);
+        id
+    }
+
+    /// 复制当前所有脏块，返回 (块号, 版本, 内容)
+    fn snapshot(&self, disk : usize)->Vec<(usize, usize, Vec<u8>)> {
+        self.dirty[disk].iter().map(|(block, d)| (*block, d.version, d.data.clone())).collect()
+    }
+
+    /// 写回完成，未被再次修改的脏块转为干净块
+    fn clean(&mut self, disk : usize, block : usize, version : usize) {
+        if self.dirty[disk].get(&block).map(|d| d.version) == Some(version) {
+            let d = self.dirty[disk].remove(&block).unwrap();
+            self.keep(disk, block, d.data);
+        }
+    }
+}
+
+/// 从磁盘读取若干块，全部提交后再逐个等待，返回 (块号, 内容)
+fn load(disk : usize, blocks : Vec<usize>)->Vec<(usize, Vec<u8>)> {
+    let mut wait = Vec::new();
+    for block in blocks {
+        wait.push((block, block_io::submit(disk, BlockOp::Read, block * BLOCK_SIZE, vec![0;BLOCK_SIZE])));
+    }
+    wait.into_iter().map(|(block, id)| {
+        (block, block_io::wait(id).unwrap_or(vec![0;BLOCK_SIZE]))
+    }).collect()
+}
+
+/// ## 写回缓冲
+/// 在请求队列之上缓冲磁盘内容并记录脏块，内部加锁，可以同时被文件系统与后台线程使用
+pub struct WriteBackCache {
+    inner : ContentMutex<DiskCache>,
+}
+
+impl WriteBackCache {
+    /// ## 写回
+    /// 复制脏块后释放锁，全部提交到请求队列再等待完成，相邻块由队列合并成一次写入
+    fn flush(&mut self, disk : usize) {
+        let blocks = self.inner.lock().snapshot(disk);
+        let mut wait = Vec::new();
+        for (block, version, data) in blocks {
+            let id = block_io::submit(disk, BlockOp::Write, block * BLOCK_SIZE, data);
+            wait.push((block, version, id));
+        }
+        for (block, version, id) in wait {
+            block_io::wait(id);
+            self.inner.lock().clean(disk, block, version);
+        }
+    }
+
+    fn disk_num(&mut self)->usize {
+        self.inner.lock().dirty.len()
+    }
+}
+
+impl CacheBuffer for WriteBackCache {
+    /// 已缓冲的部分与事务记录在同一次加锁内取得，没有缓冲的块释放锁后从磁盘读取
+    fn read(&mut self, block_idx : usize, data : &mut [u8], st : usize) {
+        let ed = st + data.len();
+        let mut target = None;
+        let capture : Vec<(usize, Vec<u8>)>;
+        {
+            let mut inner = self.inner.lock();
+            if let Some((disk, dst)) = inner.translate(block_idx, st, data.len()) {
+                let missing = inner.copy_out(disk, data, dst);
+                target = Some((disk, dst, missing, inner.version));
+            }
+            capture = inner.capture.get(&block_idx).map(|c| {
+                c.iter().filter(|(wst, w)| *wst < ed && wst + w.len() > st).cloned().collect()
+            }).unwrap_or(Vec::new());
+        }
+        if let Some((disk, dst, missing, version)) = target {
+            if missing.len() > 0 {
+                let loaded = load(disk, missing);
+                let ded = dst + data.len();
+                let mut inner = self.inner.lock();
+                for (block, buf) in loaded {
+                    let bst = block * BLOCK_SIZE;
+                    let from = dst.max(bst);
+                    let to = ded.min(bst + BLOCK_SIZE);
+                    data[from - dst..to - dst].copy_from_slice(&buf[from - bst..to - bst]);
+                    inner.fill(disk, block, buf, version);
+                }
+            }
+        }
+        for (wst, w) in capture.iter() {
+            let from = st.max(*wst);
+            let to = ed.min(wst + w.len());
+            data[from - st..to - st].copy_from_slice(&w[from - wst..to - wst]);
+        }
+    }
+
+    /// 涉及的块没有缓冲时释放锁读入，期间有其它写入则重新读取
+    fn write(&mut self, block_idx : usize, data : &[u8], st : usize) {
+        let mut loaded = BTreeMap::new();
+        let mut version = 0;
+        loop {
+            let (disk, missing) = {
+                let mut inner = self.inner.lock();
+                if let Some(capture) = inner.capture.get_mut(&block_idx) {
+                    capture.push((st, data.to_vec()));
+                    return;
+                }
+                let (disk, dst) = if let Some(t) = inner.translate(block_idx, st, data.len()) { t } else { return; };
+                match inner.write(disk, data, dst, &mut loaded, version) {
+                    Ok(wait) => {
+                        drop(inner);
+                        for id in wait {
+                            block_io::wait(id);
+                        }
+                        return;
+                    }
+                    Err(missing) => {
+                        version = inner.version;
+                        (disk, missing)
+                    }
+                }
+            };
+            loaded.extend(load(disk, missing));
+        }
+    }
+}
 
 pub fn init() {
     unsafe {
-        let mut cache = Cache::new(8, BUFFER_SIZE);
-        let device = get_device();
-        for (idx, blk) in device.block_device.iter_mut().enumerate() {
-            let blk = blk.as_mut();
-            cache.add_buffer(idx, blk, get_manager());
+        let mut dirty = Vec::new();
+        let mut clean = Vec::new();
+        let mut volume = Vec::new();
+        for idx in 0..get_device().block_device.len() {
+            dirty.push(BTreeMap::new());
+            clean.push(BTreeMap::new());
+            volume.push(Volume {
+                disk : idx,
+                offset : 0,
+                size : usize::max_value(),
+            });
         }
+        block_io::init(dirty.len());
         
-        CACHE = Some(cache);
+        CACHE = Some(WriteBackCache {
+            inner : ContentMutex::new(DiskCache {
+                dirty,
+                clean,
+                version : 0,
+                volume,
+                capture : BTreeMap::new(),
+            }, true),
+        });
     }
 }
 
@@ -50,3 +361,84 @@ pub fn sync_write_buffer(block_idx : usize, data : &[u8], st : usize){
     }
 }
 
+/// ## 添加卷
+/// 把磁盘上 [offset, offset + size) 作为一个新的卷，返回卷号
+pub fn add_volume(disk : usize, offset : usize, size : usize)->Option<usize> {
+    unsafe {
+        let mut inner = CACHE.as_mut()?.inner.lock();
+        if disk >= inner.dirty.len() {
+            return None;
+        }
+        inner.volume.push(Volume {
+            disk,
+            offset,
+            size,
+        });
+        Some(inner.volume.len() - 1)
+    }
+}
+
+pub fn get_volume(block_idx : usize)->Option<Volume> {
+    unsafe {
+        CACHE.as_mut()?.inner.lock().volume.get(block_idx).copied()
+    }
+}
+
+/// ## 记录事务
+/// 之后对卷的写入只记录下来，读取时能看到，直到 end_capture 取出
+pub fn begin_capture(block_idx : usize) {
+    unsafe {
+        if let Some(cache) = &mut CACHE {
+            cache.inner.lock().capture.insert(block_idx, Vec::new());
+        }
+    }
+}
+
+/// 结束记录，按写入顺序返回 (地址, 内容)
+pub fn end_capture(block_idx : usize)->Vec<(usize, Vec<u8>)> {
+    unsafe {
+        if let Some(cache) = &mut CACHE {
+            cache.inner.lock().capture.remove(&block_idx).unwrap_or(Vec::new())
+        }
+        else {
+            Vec::new()
+        }
+    }
+}
+
+/// 把卷所在磁盘的脏块全部写回
+pub fn flush(block_idx : usize) {
+    unsafe {
+        if let Some(cache) = &mut CACHE {
+            let disk = cache.inner.lock().volume.get(block_idx).map(|v| v.disk);
+            if let Some(disk) = disk {
+                cache.flush(disk);
+            }
+        }
+    }
+}
+
+/// 把所有磁盘的脏块写回
+pub fn flush_all() {
+    unsafe {
+        if let Some(cache) = &mut CACHE {
+            for idx in 0..cache.disk_num() {
+                cache.flush(idx);
+            }
+        }
+    }
+}
+
+/// 后台写回线程，由初始化进程创建，等待写回时睡眠
+pub fn flush_handler() {
+    loop {
+        set_timer(FLUSH_INTERVAL);
+        unsafe {
+            if let Some(cache) = &mut CACHE {
+                for idx in 0..cache.disk_num() {
+                    cache.flush(idx);
+                }
+            }
+        }
+    }
+}
@@ -8,7 +8,8 @@ pub mod device;
 pub mod input_buffer;
 pub mod config;
 pub mod disk_cache;
-pub mod ip;
+pub mod block_io;
+pub mod block_device;
 
 pub fn init() {
     device::init();
@@ -0,0 +1,43 @@
+//! # fsck
+//! 检查镜像中的 FAT32 或天目文件系统
+//! 用法：fsck <镜像> [-r] [-o 偏移字节]，-r 表示修复，有未修复的错误时返回 1
+
+use std::{env, process::exit};
+use tisu_tools::{fsck, image::ImageDisk};
+
+fn main() {
+    let args : Vec<String> = env::args().skip(1).collect();
+    let mut path = None;
+    let mut repair = false;
+    let mut offset = 0;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-r" => repair = true,
+            "-o" => offset = iter.next().and_then(|s| s.parse().ok()).unwrap_or_else(|| usage()),
+            _ => path = Some(arg.clone()),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let mut disk = ImageDisk::open(&path, offset, repair).unwrap_or_else(|e| {
+        eprintln!("open {} fail: {}", path, e);
+        exit(2);
+    });
+    let report = fsck::check(&mut disk, repair).unwrap_or_else(|| {
+        eprintln!("{}: unknown filesystem", path);
+        exit(2);
+    });
+    for err in report.error.iter() {
+        println!("{}", err);
+    }
+    println!("{:?}: {} directories, {} files, {} used, {} lost, {} repaired",
+        report.fs, report.directory, report.file, report.used, report.lost, report.repaired);
+    if !report.is_clean() && !repair {
+        exit(1);
+    }
+}
+
+fn usage()->! {
+    eprintln!("usage: fsck <image> [-r] [-o offset]");
+    exit(2);
+}
@@ -0,0 +1,246 @@
+//! # mkfs
+//! 在主机上创建天目文件系统镜像，并把一个目录下的文件复制进去作为根目录
+//! 用法：mkfs <镜像> <大小 MB> [源目录] [-b 块大小] [--no-journal]
+//! 布局：块 0 为超级块，之后是块映射，再之后是根目录。元数据块与卷末尾的日志块在块映射中标记为已占用
+//! 源目录中的符号链接原样保存目标路径，同一文件的多个硬链接共用一条数据链
+
+use std::{collections::HashMap, env, fs, os::unix::fs::MetadataExt, path::Path, process::exit};
+use tisu_tools::{fsck::Disk, image::ImageDisk, tianmu_disk::{DIR_ITEM_SIZE, DirItem, END, FREE, JOURNAL_BLOCKS, LINK_MAX,
+    MAP_ITEM_SIZE, NAME_LEN, SUPER_BLOCK_SIZE, SuperBlock, TYPE_DIRECTORY, TYPE_FILE, TYPE_SYMLINK}};
+
+const DEFAULT_BLOCK_SIZE : usize = 4096;
+
+struct Builder {
+    disk : ImageDisk,
+    sp : SuperBlock,
+    map : Vec<u64>,
+    /// 下一个尝试分配的块
+    next : usize,
+    file : usize,
+    directory : usize,
+    symlink : usize,
+    /// 有多个硬链接的文件在镜像中出现的次数，键为设备号与 inode
+    link_count : HashMap<(u64, u64), usize>,
+    /// 已写入的硬链接文件的起始块与长度
+    placed : HashMap<(u64, u64), (usize, usize)>,
+}
+
+impl Builder {
+    fn alloc(&mut self)->usize {
+        while self.next < self.map.len() && self.map[self.next] != FREE {
+            self.next += 1;
+        }
+        if self.next >= self.map.len() {
+            eprintln!("image is full");
+            exit(1);
+        }
+        self.map[self.next] = END;
+        self.next
+    }
+
+    /// 分配 num 个块并连成链
+    fn alloc_chain(&mut self, num : usize)->Vec<usize> {
+        let chain : Vec<usize> = (0..num).map(|_| self.alloc()).collect();
+        for pair in chain.windows(2) {
+            self.map[pair[0]] = pair[1] as u64;
+        }
+        chain
+    }
+
+    fn write_chain(&mut self, chain : &[usize], data : &[u8]) {
+        for (i, block) in chain.iter().enumerate() {
+            let st = i * self.sp.block_size;
+            let ed = data.len().min(st + self.sp.block_size);
+            let mut buf = vec![0u8;self.sp.block_size];
+            if st < ed {
+                buf[..ed - st].copy_from_slice(&data[st..ed]);
+            }
+            self.disk.write(self.sp.block_addr(*block), &buf);
+        }
+    }
+
+    /// 复制目录 src 的内容到以 chain 为块链的目录中
+    fn copy_dir(&mut self, src : &Path, chain : Vec<usize>) {
+        let mut items = Vec::new();
+        let mut entries : Vec<_> = fs::read_dir(src).unwrap_or_else(|e| {
+            eprintln!("read {} fail: {}", src.display(), e);
+            exit(1);
+        }).filter_map(|e| e.ok()).collect();
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.len() >= NAME_LEN {
+                eprintln!("skip {}: name longer than {} bytes", entry.path().display(), NAME_LEN - 1);
+                continue;
+            }
+            let path = entry.path();
+            let ftype = match entry.file_type() {
+                Ok(ftype) => ftype,
+                Err(_) => continue,
+            };
+            if ftype.is_symlink() {
+                let target = fs::read_link(&path).unwrap_or_else(|e| {
+                    eprintln!("read link {} fail: {}", path.display(), e);
+                    exit(1);
+                });
+                let target = target.to_string_lossy().to_string();
+                let chain = self.alloc_chain(target.len().div_ceil(self.sp.block_size));
+                self.write_chain(&chain, target.as_bytes());
+                items.push(DirItem::new(&name, TYPE_SYMLINK, chain.first().copied().unwrap_or(0), target.len()));
+                self.symlink += 1;
+            }
+            else if ftype.is_dir() {
+                let sub = self.alloc_chain(1);
+                items.push(DirItem::new(&name, TYPE_DIRECTORY, sub[0], self.sp.block_size));
+                self.copy_dir(&path, sub);
+                self.directory += 1;
+            }
+            else if ftype.is_file() {
+                let key = entry.metadata().map(|m| (m.dev(), m.ino())).ok();
+                let links = key.and_then(|k| self.link_count.get(&k)).copied().unwrap_or(1);
+                let (start, len) = if let Some(placed) = key.and_then(|k| self.placed.get(&k)) {
+                    *placed
+                }
+                else {
+                    let data = fs::read(&path).unwrap_or_else(|e| {
+                        eprintln!("read {} fail: {}", path.display(), e);
+                        exit(1);
+                    });
+                    let num = data.len().div_ceil(self.sp.block_size);
+                    let file = self.alloc_chain(num);
+                    self.write_chain(&file, &data);
+                    let start = file.first().copied().unwrap_or(0);
+                    if let (Some(k), true) = (key, links > 1) {
+                        self.placed.insert(k, (start, data.len()));
+                    }
+                    (start, data.len())
+                };
+                let mut item = DirItem::new(&name, TYPE_FILE, start, len);
+                // 空文件没有数据链，各个链接互不相关
+                if start != 0 {
+                    item.links = links.min(LINK_MAX) as u8;
+                }
+                items.push(item);
+                self.file += 1;
+            }
+        }
+        let per_block = self.sp.block_size / DIR_ITEM_SIZE;
+        let mut chain = chain;
+        let need = items.len().div_ceil(per_block).max(1);
+        if chain.len() < need {
+            let more = self.alloc_chain(need - chain.len());
+            self.map[*chain.last().unwrap()] = more[0] as u64;
+            chain.extend(more);
+        }
+        let mut data = Vec::with_capacity(items.len() * DIR_ITEM_SIZE);
+        for item in items.iter() {
+            data.extend(item.to_bytes());
+        }
+        self.write_chain(&chain, &data);
+    }
+
+    /// 统计源目录中有多个硬链接的文件各出现几次，不跟随符号链接
+    fn count_links(&mut self, src : &Path) {
+        let entries = match fs::read_dir(src) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let (ftype, meta) = match (entry.file_type(), entry.metadata()) {
+                (Ok(ftype), Ok(meta)) => (ftype, meta),
+                _ => continue,
+            };
+            if ftype.is_dir() {
+                self.count_links(&entry.path());
+            }
+            else if ftype.is_file() && meta.nlink() > 1 && meta.len() > 0 {
+                *self.link_count.entry((meta.dev(), meta.ino())).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn save_map(&mut self) {
+        let mut raw = Vec::with_capacity(self.map.len() * MAP_ITEM_SIZE);
+        for val in self.map.iter() {
+            raw.extend_from_slice(&val.to_le_bytes());
+        }
+        self.disk.write(self.sp.block_map_addr, &raw);
+    }
+}
+
+fn main() {
+    let args : Vec<String> = env::args().skip(1).collect();
+    let mut pos = Vec::new();
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut journal = true;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-b" => block_size = iter.next().and_then(|s| s.parse().ok()).unwrap_or_else(|| usage()),
+            "--no-journal" => journal = false,
+            _ => pos.push(arg.clone()),
+        }
+    }
+    if pos.len() < 2 || pos.len() > 3 {
+        usage();
+    }
+    let size_mb : usize = pos[1].parse().unwrap_or_else(|_| usage());
+    // 块映射从块 1 开始，块不能小于超级块，否则会覆盖魔数
+    if block_size < SUPER_BLOCK_SIZE || !block_size.is_power_of_two() {
+        eprintln!("block size must be a power of two not less than {}", SUPER_BLOCK_SIZE);
+        exit(2);
+    }
+    let total_size = size_mb * 1024 * 1024;
+    let num = total_size / block_size;
+    let map_blocks = (num * MAP_ITEM_SIZE).div_ceil(block_size);
+    let reserved = if journal { JOURNAL_BLOCKS } else { 0 };
+    if num < 2 + map_blocks + reserved {
+        eprintln!("image too small");
+        exit(2);
+    }
+    let sp = SuperBlock {
+        total_size,
+        block_size,
+        block_map_addr : block_size,
+        root_idx : 1 + map_blocks,
+    };
+    let disk = ImageDisk::create(&pos[0], total_size as u64).unwrap_or_else(|e| {
+        eprintln!("create {} fail: {}", pos[0], e);
+        exit(1);
+    });
+    let mut builder = Builder {
+        disk,
+        sp,
+        map : vec![FREE;num],
+        next : 0,
+        file : 0,
+        directory : 1,
+        symlink : 0,
+        link_count : HashMap::new(),
+        placed : HashMap::new(),
+    };
+    for i in 0..=sp.root_idx {
+        builder.map[i] = END;
+    }
+    for i in num - reserved..num {
+        builder.map[i] = END;
+    }
+    builder.disk.write(0, &sp.to_bytes());
+    if let Some(src) = pos.get(2) {
+        builder.count_links(Path::new(src));
+        builder.copy_dir(Path::new(src), vec![sp.root_idx]);
+    }
+    else {
+        builder.write_chain(&[sp.root_idx], &[]);
+    }
+    builder.save_map();
+    let used = builder.map.iter().filter(|v| **v != FREE).count();
+    println!("{}: {} MB, block size {}, {} directories, {} files, {} symlinks, {}/{} blocks used{}",
+        pos[0], size_mb, block_size, builder.directory, builder.file, builder.symlink, used, num,
+        if journal { ", with journal" } else { "" });
+}
+
+fn usage()->! {
+    eprintln!("usage: mkfs <image> <size MB> [source directory] [-b block size] [--no-journal]");
+    exit(2);
+}
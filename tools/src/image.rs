@@ -0,0 +1,47 @@
+//! # 镜像文件
+//! 把镜像文件中从 offset 开始的部分当作磁盘读写，超出文件末尾的部分读出为 0
+
+use std::{fs::{File, OpenOptions}, io::{self, Read, Seek, SeekFrom, Write}};
+use crate::fsck::Disk;
+
+pub struct ImageDisk {
+    file : File,
+    offset : u64,
+}
+
+impl ImageDisk {
+    pub fn open(path : &str, offset : u64, writable : bool)->io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(writable).open(path)?;
+        Ok(Self { file, offset })
+    }
+
+    pub fn create(path : &str, size : u64)->io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(size)?;
+        Ok(Self { file, offset : 0 })
+    }
+}
+
+impl Disk for ImageDisk {
+    fn read(&mut self, st : usize, data : &mut [u8]) {
+        for b in data.iter_mut() {
+            *b = 0;
+        }
+        if self.file.seek(SeekFrom::Start(self.offset + st as u64)).is_err() {
+            return;
+        }
+        let mut done = 0;
+        while done < data.len() {
+            match self.file.read(&mut data[done..]) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => done += n,
+            }
+        }
+    }
+
+    fn write(&mut self, st : usize, data : &[u8]) {
+        self.file.seek(SeekFrom::Start(self.offset + st as u64))
+            .and_then(|_| self.file.write_all(data))
+            .expect("write image fail");
+    }
+}
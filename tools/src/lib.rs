@@ -0,0 +1,15 @@
+//! # 主机工具
+//! 直接引用内核 filesystem/format 中只依赖 alloc 的模块，保证与内核解析方式一致
+//!
+//! 2021年6月18日 zg
+
+// 共用的代码要能用内核的工具链编译，不能使用 div_ceil 等较新的写法
+#![allow(clippy::len_zero, clippy::manual_div_ceil)]
+
+extern crate alloc;
+
+#[path = "../../tisuos/src/filesystem/format/tianmu_disk.rs"]
+pub mod tianmu_disk;
+#[path = "../../tisuos/src/filesystem/format/fsck.rs"]
+pub mod fsck;
+pub mod image;
@@ -4,18 +4,22 @@
     alloc_prelude,
 )]
 
-use user_lib::libs::fs::Directory;
+use user_lib::libs::fs::{Directory, Stat};
 
 #[macro_use]
 extern crate user_lib;
 extern crate alloc;
-use alloc::prelude::v1::*;
+use alloc::{format, prelude::v1::*};
 
 #[no_mangle]
 extern "C" fn _start(){
     let dir = Directory::read("0/".to_string());
-    for file in dir.file.iter() {
-        print!("{} ", file);
+    for file in dir.directory.iter().chain(dir.file.iter()) {
+        if let Some(stat) = Stat::get(format!("0/{}", file)) {
+            println!("{} {:>8} {}", stat.mode_string(), stat.size, file);
+        }
+        else {
+            println!("?????????? {:>8} {}", 0, file);
+        }
     }
-    println!()
 }
\ No newline at end of file
@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+#![feature(alloc_prelude)]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+use alloc::{format, prelude::v1::*};
+use user_lib::libs::{fs::File, http::{HttpServer, Request, Response}, net::SocketAddr};
+
+/// 网页文件所在目录，位于 0 号磁盘
+const ROOT : &str = "0/www";
+const INDEX : &str = "index.html";
+const PORT : u16 = 80;
+
+fn content_type(path : &str)->&'static str {
+    let ext = path.rsplit('.').next().unwrap_or("");
+    match ext {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "bmp" => "image/bmp",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+fn handle(request : &Request, peer : SocketAddr)->Response {
+    let rt = serve_file(request);
+    println!("httpd {} {} {} {}", peer, request.method, request.path, rt.status);
+    rt
+}
+
+fn serve_file(request : &Request)->Response {
+    if request.method != "GET" && request.method != "HEAD" {
+        return Response::error(405).header("Allow", "GET, HEAD");
+    }
+    // 不允许访问根目录之外的文件
+    if request.path.split('/').any(|name| name == "..") {
+        return Response::error(403);
+    }
+    let mut path = request.path.clone();
+    if path.ends_with('/') {
+        path += INDEX;
+    }
+    match File::read(format!("{}{}", ROOT, path)) {
+        Ok(data) => {
+            let body = data.to_array(0, data.size).to_vec();
+            Response::ok(content_type(&path), body)
+        }
+        Err(_) => Response::error(404),
+    }
+}
+
+/// 在 80 号端口提供 0 号磁盘 www 目录下的文件，主机上通过 make run 的端口转发访问 8080 端口
+#[no_mangle]
+extern "C" fn _start(){
+    let server = HttpServer::bind(PORT).unwrap();
+    println!("httpd listening on port {}", PORT);
+    server.serve(handle);
+}
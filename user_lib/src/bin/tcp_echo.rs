@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+use user_lib::{libs::net::TcpListener, println};
+
+extern crate user_lib;
+
+/// 在 7 号端口回显 TCP 连接收到的数据，主机上通过 make run 的端口转发访问 5555 端口
+#[no_mangle]
+extern "C" fn _start(){
+    let listener = TcpListener::bind(7).unwrap();
+    let mut buffer = [0u8;1024];
+    loop {
+        if let Ok((stream, peer)) = listener.accept() {
+            println!("tcp echo connection from {}", peer);
+            while let Ok(len) = stream.read(&mut buffer) {
+                if len == 0 {
+                    break;
+                }
+                if stream.write_all(&buffer[..len]).is_err() {
+                    break;
+                }
+            }
+            println!("tcp echo {} closed", peer);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+use user_lib::{libs::net::UdpSocket, println};
+
+extern crate user_lib;
+
+/// 在 7 号端口回显收到的数据报
+#[no_mangle]
+extern "C" fn _start(){
+    let socket = UdpSocket::bind(7).unwrap();
+    let mut buffer = [0u8;1500];
+    loop {
+        if let Ok((len, src)) = socket.recv_from(&mut buffer) {
+            println!("udp echo {} bytes from {}", len, src);
+            socket.send_to(&buffer[..len], src).ok();
+        }
+    }
+}
@@ -1,7 +1,50 @@
 use alloc::prelude::v1::*;
-use fs_format::DirectoryInfo;
+use crate::libs::syscall::getdents;
+use super::{STAT_DIRECTORY, Stat};
 
-use crate::libs::syscall::{directory_info, free};
+/// 记录头的长度，之后是以 0 结尾的名字
+const DIRENT_HEAD : usize = 27;
+const BUFFER_SIZE : usize = 4096;
+
+/// ## 目录项
+/// GETDENTS 写入的变长记录，布局与内核一致
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name : String,
+    pub dtype : usize,
+    pub size : usize,
+    pub start_idx : usize,
+    /// 下一项的序号，传回 getdents 从这一项之后继续
+    pub cookie : usize,
+}
+
+impl DirEntry {
+    pub fn parse_all(data : &[u8])->Vec<Self> {
+        let mut rt = Vec::new();
+        let mut st = 0;
+        while st + DIRENT_HEAD <= data.len() {
+            let rec = &data[st..];
+            let reclen = rec[24] as usize | (rec[25] as usize) << 8;
+            if reclen < DIRENT_HEAD || st + reclen > data.len() {
+                break;
+            }
+            let name : Vec<u8> = rec[DIRENT_HEAD..reclen].iter().take_while(|c| **c != 0).cloned().collect();
+            rt.push(Self {
+                name : String::from_utf8_lossy(&name[..]).to_string(),
+                dtype : rec[26] as usize,
+                size : read_u64(rec, 8),
+                start_idx : read_u64(rec, 0),
+                cookie : read_u64(rec, 16),
+            });
+            st += reclen;
+        }
+        rt
+    }
+
+    pub fn is_dir(&self)->bool {
+        self.dtype == STAT_DIRECTORY
+    }
+}
 
 pub struct Directory {
     pub path : String,
@@ -9,27 +52,41 @@ pub struct Directory {
     pub device_id : usize,
     pub file : Vec<String>,
     pub directory : Vec<String>,
+    pub entry : Vec<DirEntry>,
 }
 
 impl Directory {
+    /// 分多次读取整个目录，目录不存在时各项为空
     pub fn read(path : String)->Self {
-        let ptr = directory_info(path.clone());
-        let info = unsafe {&*(ptr as *const DirectoryInfo)};
-        let mut file = Vec::new();
-        let mut directory = Vec::new();
-        for i in 0..info.file_num {
-            file.push(info.get_name(i));
-        }
-        for i in info.file_num..(info.dir_num + info.file_num) {
-            directory.push(info.get_name(i));
+        let mut entry = Vec::new();
+        let mut buf = Vec::new();
+        buf.resize(BUFFER_SIZE, 0u8);
+        let mut cookie = 0;
+        loop {
+            let len = getdents(path.clone(), &mut buf[..], cookie);
+            if len <= 0 {
+                break;
+            }
+            let items = DirEntry::parse_all(&buf[..len as usize]);
+            if let Some(last) = items.last() {
+                cookie = last.cookie;
+            }
+            entry.extend(items);
         }
-        free(ptr);
+        let stat = Stat::get(path.clone()).unwrap_or_default();
         Self {
+            file : entry.iter().filter(|e| !e.is_dir()).map(|e| e.name.clone()).collect(),
+            directory : entry.iter().filter(|e| e.is_dir()).map(|e| e.name.clone()).collect(),
             path,
-            block_idx:info.block_idx,
-            device_id:info.device_id,
-            file,
-            directory,
+            block_idx : stat.start_idx,
+            device_id : stat.device,
+            entry,
         }
     }
-}
\ No newline at end of file
+}
+
+fn read_u64(data : &[u8], st : usize)->usize {
+    let mut b = [0;8];
+    b.copy_from_slice(&data[st..st + 8]);
+    u64::from_le_bytes(b) as usize
+}
@@ -1,7 +1,7 @@
 use alloc::prelude::v1::*;
 use fs_format::FileInfo;
 
-use crate::libs::{Block, syscall::{file_info, free, open, read}};
+use crate::libs::{Block, syscall::{close, file_info, free, open, read}};
 
 pub struct File {}
 
@@ -16,6 +16,7 @@ impl File {
         let data = Block::<u8>::new(info.size);
         let len = read(id as usize, data.to_array(0, info.size));
         free(ptr);
+        close(id as usize);
         if len <= 0 {
             return Err(FileError::ReadFail);
         }
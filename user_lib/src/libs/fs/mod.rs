@@ -1,5 +1,7 @@
 mod file;
 mod directory;
+mod stat;
 
 pub use file::*;
-pub use directory::*;
\ No newline at end of file
+pub use directory::*;
+pub use stat::*;
\ No newline at end of file
@@ -0,0 +1,58 @@
+use alloc::prelude::v1::*;
+use crate::libs::syscall::{fstat, stat};
+
+pub const STAT_FILE : usize = 1;
+pub const STAT_DIRECTORY : usize = 2;
+
+/// ## 文件状态
+/// 与内核共享的布局，时间为 1970 年起的秒数，未知时为 0
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    pub ftype : usize,
+    /// rwx 权限位，如 0o644
+    pub mode : usize,
+    pub size : usize,
+    pub blocks : usize,
+    pub block_size : usize,
+    pub create_time : usize,
+    pub modify_time : usize,
+    pub access_time : usize,
+    pub device : usize,
+    pub start_idx : usize,
+}
+
+impl Stat {
+    /// 路径以文件系统编号开头，如 0/www，不存在时返回 None
+    pub fn get(path : String)->Option<Self> {
+        let mut rt = Self::default();
+        if stat(path, &mut rt) < 0 { None } else { Some(rt) }
+    }
+
+    /// 已打开文件的状态
+    pub fn of(id : usize)->Option<Self> {
+        let mut rt = Self::default();
+        if fstat(id, &mut rt) < 0 { None } else { Some(rt) }
+    }
+
+    pub fn is_dir(&self)->bool {
+        self.ftype == STAT_DIRECTORY
+    }
+
+    pub fn is_file(&self)->bool {
+        self.ftype == STAT_FILE
+    }
+
+    /// 形如 rw-r--r-- 的权限字符串，目录前加 d
+    pub fn mode_string(&self)->String {
+        let mut rt = String::new();
+        rt.push(if self.is_dir() { 'd' } else { '-' });
+        for i in (0..3).rev() {
+            let bits = self.mode >> (i * 3);
+            rt.push(if bits & 4 != 0 { 'r' } else { '-' });
+            rt.push(if bits & 2 != 0 { 'w' } else { '-' });
+            rt.push(if bits & 1 != 0 { 'x' } else { '-' });
+        }
+        rt
+    }
+}
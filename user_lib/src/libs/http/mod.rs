@@ -0,0 +1,25 @@
+//! # HTTP
+//! 基于 TCP 套接字的简单 HTTP/1.1 服务端
+//! 每个连接只处理一个请求，应答后关闭连接
+//!
+//! 2021年6月15日 zg
+
+mod request;
+mod response;
+mod server;
+
+pub use request::*;
+pub use response::*;
+pub use server::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpError {
+    /// 请求行或头部格式错误
+    BadRequest,
+    /// 头部超过长度限制
+    HeaderTooLarge,
+    /// 请求体超过长度限制
+    BodyTooLarge,
+    /// 连接在请求完整之前断开
+    ConnectionClosed,
+}
@@ -0,0 +1,126 @@
+use alloc::prelude::v1::*;
+use crate::libs::net::TcpStream;
+use super::HttpError;
+
+/// 请求行与头部的最大长度
+const MAX_HEADER_LEN : usize = 8192;
+/// 请求体的最大长度
+const MAX_BODY_LEN : usize = 65536;
+
+/// ## HTTP 请求
+/// 头部名字统一转为小写，路径中的查询串单独存放
+pub struct Request {
+    pub method : String,
+    pub path : String,
+    pub query : Option<String>,
+    pub version : String,
+    pub headers : Vec<(String, String)>,
+    pub body : Vec<u8>,
+}
+
+impl Request {
+    /// ## 读取请求
+    /// 阻塞直到读到完整的头部以及 Content-Length 指定长度的请求体
+    pub fn read(stream : &TcpStream)->Result<Self, HttpError> {
+        let mut data = Vec::new();
+        let mut buffer = [0u8;1024];
+        let end = loop {
+            if let Some(idx) = find(&data, b"\r\n\r\n") {
+                break idx;
+            }
+            if data.len() > MAX_HEADER_LEN {
+                return Err(HttpError::HeaderTooLarge);
+            }
+            match stream.read(&mut buffer) {
+                Ok(len) if len > 0 => data.extend_from_slice(&buffer[..len]),
+                _ => return Err(HttpError::ConnectionClosed),
+            }
+        };
+        let head = core::str::from_utf8(&data[..end]).map_err(|_| HttpError::BadRequest)?;
+        let mut rt = Self::parse(head)?;
+        let len = match rt.header("content-length") {
+            Some(len) => len.parse::<usize>().map_err(|_| HttpError::BadRequest)?,
+            None => 0,
+        };
+        if len > MAX_BODY_LEN {
+            return Err(HttpError::BodyTooLarge);
+        }
+        let mut body = data[end + 4..].to_vec();
+        while body.len() < len {
+            match stream.read(&mut buffer) {
+                Ok(n) if n > 0 => body.extend_from_slice(&buffer[..n]),
+                _ => return Err(HttpError::ConnectionClosed),
+            }
+        }
+        body.truncate(len);
+        rt.body = body;
+        Ok(rt)
+    }
+
+    fn parse(head : &str)->Result<Self, HttpError> {
+        let mut lines = head.split("\r\n");
+        let mut item = lines.next().ok_or(HttpError::BadRequest)?.split(' ');
+        let method = item.next().ok_or(HttpError::BadRequest)?;
+        let target = item.next().ok_or(HttpError::BadRequest)?;
+        let version = item.next().ok_or(HttpError::BadRequest)?;
+        if item.next().is_some() || method.len() == 0 || !target.starts_with('/')
+            || !version.starts_with("HTTP/1.") {
+            return Err(HttpError::BadRequest);
+        }
+        let mut target = target.splitn(2, '?');
+        let path = target.next().unwrap();
+        let query = target.next().map(|q| q.to_string());
+        let mut headers = Vec::new();
+        for line in lines {
+            let idx = line.find(':').ok_or(HttpError::BadRequest)?;
+            headers.push((line[..idx].trim().to_lowercase(), line[idx + 1..].trim().to_string()));
+        }
+        Ok(Self {
+            method : method.to_string(),
+            path : decode(path).ok_or(HttpError::BadRequest)?,
+            query,
+            version : version.to_string(),
+            headers,
+            body : Vec::new(),
+        })
+    }
+
+    /// 查找头部，名字不区分大小写
+    pub fn header(&self, name : &str)->Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+fn find(data : &[u8], pattern : &[u8])->Option<usize> {
+    data.windows(pattern.len()).position(|w| w == pattern)
+}
+
+fn hex(c : u8)->Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// 还原路径中的百分号编码
+fn decode(path : &str)->Option<String> {
+    let data = path.as_bytes();
+    let mut rt = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'%' {
+            let h = hex(*data.get(i + 1)?)?;
+            let l = hex(*data.get(i + 2)?)?;
+            rt.push(h << 4 | l);
+            i += 3;
+        }
+        else {
+            rt.push(data[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(rt).ok()
+}
@@ -0,0 +1,71 @@
+use alloc::{format, prelude::v1::*};
+use crate::libs::net::{SocketError, TcpStream};
+
+/// ## HTTP 应答
+/// 写出时自动加上 Content-Length 与 Connection: close
+pub struct Response {
+    pub status : u16,
+    pub headers : Vec<(String, String)>,
+    pub body : Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status : u16)->Self {
+        Self {
+            status,
+            headers : Vec::new(),
+            body : Vec::new(),
+        }
+    }
+
+    pub fn ok(content_type : &str, body : Vec<u8>)->Self {
+        Self::new(200).header("Content-Type", content_type).body(body)
+    }
+
+    /// 以状态说明作为正文的纯文本应答
+    pub fn error(status : u16)->Self {
+        let body = format!("{} {}\n", status, reason(status)).into_bytes();
+        Self::new(status).header("Content-Type", "text/plain").body(body)
+    }
+
+    pub fn header(mut self, name : &str, value : &str)->Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body : Vec<u8>)->Self {
+        self.body = body;
+        self
+    }
+
+    /// ## 写出应答
+    /// head 为 true 时只写状态行与头部，用于 HEAD 请求
+    pub fn write(&self, stream : &TcpStream, head : bool)->Result<(), SocketError> {
+        let mut rt = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in self.headers.iter() {
+            rt += &format!("{}: {}\r\n", name, value);
+        }
+        rt += &format!("Content-Length: {}\r\nConnection: close\r\nServer: TisuOS\r\n\r\n", self.body.len());
+        stream.write_all(rt.as_bytes())?;
+        if !head {
+            stream.write_all(&self.body)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn reason(status : u16)->&'static str {
+    match status {
+        200 => "OK",
+        301 => "Moved Permanently",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _ => "Unknown",
+    }
+}
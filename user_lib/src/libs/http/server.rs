@@ -0,0 +1,39 @@
+use crate::libs::net::{SocketAddr, SocketError, TcpListener};
+use super::{HttpError, Request, Response};
+
+/// ## HTTP 服务端
+/// 依次处理到来的连接，每个连接读取一个请求，交给处理函数得到应答
+pub struct HttpServer {
+    listener : TcpListener,
+}
+
+impl HttpServer {
+    pub fn bind(port : u16)->Result<Self, SocketError> {
+        Ok(Self {
+            listener : TcpListener::bind(port)?,
+        })
+    }
+
+    /// 不断接受连接，不会返回
+    pub fn serve(&self, handler : fn(&Request, SocketAddr)->Response)->! {
+        loop {
+            let (stream, peer) = if let Ok(rt) = self.listener.accept() { rt } else { continue; };
+            match Request::read(&stream) {
+                Ok(request) => {
+                    let response = handler(&request, peer);
+                    response.write(&stream, request.method == "HEAD").ok();
+                }
+                Err(HttpError::ConnectionClosed) => {}
+                Err(HttpError::HeaderTooLarge) => {
+                    Response::error(431).write(&stream, false).ok();
+                }
+                Err(HttpError::BodyTooLarge) => {
+                    Response::error(413).write(&stream, false).ok();
+                }
+                Err(HttpError::BadRequest) => {
+                    Response::error(400).write(&stream, false).ok();
+                }
+            }
+        }
+    }
+}
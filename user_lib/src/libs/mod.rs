@@ -3,7 +3,9 @@ mod memory_block;
 mod intershell;
 pub mod fs;
 pub mod graphic;
+pub mod http;
 pub mod input;
+pub mod net;
 pub mod syscall;
 pub mod str;
 pub mod stdio;
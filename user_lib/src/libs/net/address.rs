@@ -0,0 +1,69 @@
+use core::fmt::{Display, Formatter, Result};
+
+/// IPv4 地址，按网络字节序保存
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ipv4Addr(pub [u8;4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED : Self = Self([0;4]);
+    pub const BROADCAST : Self = Self([255;4]);
+
+    pub const fn new(a : u8, b : u8, c : u8, d : u8)->Self {
+        Self([a, b, c, d])
+    }
+
+    pub fn from_val(val : u32)->Self {
+        Self(val.to_be_bytes())
+    }
+
+    pub fn val(&self)->u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// 解析点分十进制字符串
+    pub fn parse(s : &str)->Option<Self> {
+        let mut rt = [0u8;4];
+        let mut idx = 0;
+        for part in s.split('.') {
+            if idx >= 4 || part.len() == 0 || part.len() > 3 {
+                return None;
+            }
+            let mut val = 0u32;
+            for c in part.chars() {
+                val = val * 10 + c.to_digit(10)?;
+            }
+            if val > 255 {
+                return None;
+            }
+            rt[idx] = val as u8;
+            idx += 1;
+        }
+        if idx == 4 { Some(Self(rt)) } else { None }
+    }
+}
+
+impl Display for Ipv4Addr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
+}
+
+/// 地址与端口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketAddr {
+    pub ip : Ipv4Addr,
+    pub port : u16,
+}
+
+impl SocketAddr {
+    pub const fn new(ip : Ipv4Addr, port : u16)->Self {
+        Self { ip, port }
+    }
+}
+
+impl Display for SocketAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}:{}", self.ip, self.port)
+    }
+}
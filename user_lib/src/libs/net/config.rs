@@ -0,0 +1,60 @@
+use alloc::prelude::v1::*;
+use crate::libs::syscall::ifconfig;
+use super::Ipv4Addr;
+
+const IFCONFIG_GET : usize = 0;
+const IFCONFIG_SET : usize = 1;
+const IFCONFIG_DHCP : usize = 2;
+
+/// ## 接口配置
+/// 与内核共享的布局，名字与 MAC 地址只读
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterfaceConfig {
+    name : [u8;8],
+    pub mac : [u8;6],
+    pub dhcp : bool,
+    pub ip : Ipv4Addr,
+    pub netmask : Ipv4Addr,
+    pub gateway : Ipv4Addr,
+    pub dns : Ipv4Addr,
+}
+
+impl InterfaceConfig {
+    /// 查询第 idx 个接口，不存在时返回 None
+    pub fn get(idx : usize)->Option<Self> {
+        let mut rt = Self::default();
+        if ifconfig(idx, &mut rt, IFCONFIG_GET) < 0 {
+            None
+        }
+        else {
+            Some(rt)
+        }
+    }
+
+    /// 所有接口的配置
+    pub fn all()->Vec<Self> {
+        let mut rt = Vec::new();
+        while let Some(config) = Self::get(rt.len()) {
+            rt.push(config);
+        }
+        rt
+    }
+
+    pub fn name(&self)->&str {
+        let len = self.name.iter().position(|c| *c == 0).unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+
+    /// 以当前内容作为第 idx 个接口的静态配置，DHCP 随之停止
+    pub fn apply(&self, idx : usize)->Result<(), ()> {
+        let mut config = *self;
+        if ifconfig(idx, &mut config, IFCONFIG_SET) < 0 { Err(()) } else { Ok(()) }
+    }
+
+    /// 清除第 idx 个接口的地址并重新通过 DHCP 获取
+    pub fn renew(idx : usize)->Result<(), ()> {
+        let mut config = Self::default();
+        if ifconfig(idx, &mut config, IFCONFIG_DHCP) < 0 { Err(()) } else { Ok(()) }
+    }
+}
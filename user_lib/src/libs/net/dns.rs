@@ -0,0 +1,19 @@
+use crate::libs::syscall;
+use super::{Ipv4Addr, SocketAddr};
+
+/// ## 解析主机名
+/// 依次查询 hosts 文件、内核缓存与 DNS 服务器，点分十进制地址直接返回
+pub fn resolve(name : &str)->Option<Ipv4Addr> {
+    let rt = syscall::resolve(name);
+    if rt < 0 {
+        None
+    }
+    else {
+        Some(Ipv4Addr::from_val(rt as u32))
+    }
+}
+
+/// 解析主机名并加上端口
+pub fn resolve_addr(name : &str, port : u16)->Option<SocketAddr> {
+    Some(SocketAddr::new(resolve(name)?, port))
+}
@@ -0,0 +1,35 @@
+mod address;
+mod config;
+mod dns;
+pub mod pcap;
+mod stat;
+mod tcp;
+mod udp;
+
+pub use address::*;
+pub use config::*;
+pub use dns::*;
+pub use stat::*;
+pub use tcp::*;
+pub use udp::*;
+
+const SOCK_STREAM : usize = 1;
+const SOCK_DGRAM : usize = 2;
+const MSG_DONTWAIT : usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketError {
+    /// 新建套接字失败
+    CreateFail,
+    /// 端口已被占用
+    BindFail,
+    ListenFail,
+    /// 连接被拒绝或者超时
+    ConnectFail,
+    /// 没有路由或数据过长
+    SendFail,
+    /// 非阻塞收发时没有数据、缓冲区已满或者没有连接
+    WouldBlock,
+    /// 套接字无效或者连接已经断开
+    InvalidSocket,
+}
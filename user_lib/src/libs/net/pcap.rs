@@ -0,0 +1,33 @@
+use alloc::prelude::v1::*;
+use crate::libs::syscall::pcap;
+
+const PCAP_START : usize = 0;
+const PCAP_STOP : usize = 1;
+const PCAP_CLEAR : usize = 2;
+const PCAP_READ : usize = 3;
+
+pub fn start() {
+    pcap(PCAP_START, &mut []);
+}
+
+pub fn stop() {
+    pcap(PCAP_STOP, &mut []);
+}
+
+pub fn clear() {
+    pcap(PCAP_CLEAR, &mut []);
+}
+
+/// ## 读取抓包结果
+/// 内核把网卡收发的帧记录在环形缓冲区中，返回完整的 pcap 文件，读取期间新记录的帧会被截掉
+pub fn read()->Vec<u8> {
+    let len = pcap(PCAP_READ, &mut []);
+    if len <= 0 {
+        return Vec::new();
+    }
+    let mut rt = Vec::with_capacity(len as usize);
+    rt.resize(len as usize, 0);
+    let len = pcap(PCAP_READ, &mut rt[..]) as usize;
+    rt.truncate(len);
+    rt
+}
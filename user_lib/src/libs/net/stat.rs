@@ -0,0 +1,81 @@
+use alloc::prelude::v1::*;
+use crate::libs::syscall::{interface_stats, socket_info};
+use super::{Ipv4Addr, SOCK_STREAM};
+
+const STATE_NAME : [&str;11] = ["CLOSED", "LISTEN", "SYN_SENT", "SYN_RECEIVED", "ESTABLISHED",
+    "FIN_WAIT1", "FIN_WAIT2", "CLOSE_WAIT", "CLOSING", "LAST_ACK", "TIME_WAIT"];
+
+/// ## 接口计数
+/// 与内核共享的布局，顺序与 InterfaceConfig::all 一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterfaceStats {
+    pub rx_frames : usize,
+    pub rx_bytes : usize,
+    pub tx_frames : usize,
+    pub tx_bytes : usize,
+    pub rx_dropped : usize,
+    pub tx_dropped : usize,
+    pub checksum_errors : usize,
+    pub arp_entries : usize,
+}
+
+impl InterfaceStats {
+    /// 查询第 idx 个接口，不存在时返回 None
+    pub fn get(idx : usize)->Option<Self> {
+        let mut rt = Self::default();
+        if interface_stats(idx, &mut rt) < 0 { None } else { Some(rt) }
+    }
+
+    pub fn all()->Vec<Self> {
+        let mut rt = Vec::new();
+        while let Some(stats) = Self::get(rt.len()) {
+            rt.push(stats);
+        }
+        rt
+    }
+}
+
+/// ## 套接字信息
+/// 与内核共享的布局，包括其它进程与内核的套接字
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketInfo {
+    pub id : usize,
+    /// 内核套接字的所有者为 usize::MAX
+    pub owner : usize,
+    socket_type : usize,
+    state : usize,
+    pub local_ip : Ipv4Addr,
+    pub local_port : u16,
+    pub remote_ip : Ipv4Addr,
+    pub remote_port : u16,
+    pub recv_queue : usize,
+    pub send_queue : usize,
+    pub dropped : usize,
+}
+
+impl SocketInfo {
+    pub fn all()->Vec<Self> {
+        let mut rt = Vec::new();
+        let mut info = Self::default();
+        while socket_info(rt.len(), &mut info) >= 0 {
+            rt.push(info);
+        }
+        rt
+    }
+
+    pub fn is_tcp(&self)->bool {
+        self.socket_type == SOCK_STREAM
+    }
+
+    /// TCP 连接状态，UDP 套接字返回空串
+    pub fn state(&self)->&'static str {
+        if self.is_tcp() {
+            STATE_NAME.get(self.state).cloned().unwrap_or("UNKNOWN")
+        }
+        else {
+            ""
+        }
+    }
+}
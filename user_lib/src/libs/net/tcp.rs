@@ -0,0 +1,139 @@
+use crate::libs::syscall::{accept, bind, connect, listen, recv, send, socket, socket_close};
+use super::{Ipv4Addr, MSG_DONTWAIT, SOCK_STREAM, SocketAddr, SocketError};
+
+/// 默认监听队列长度
+const DEFAULT_BACKLOG : usize = 8;
+
+fn socket_addr(val : usize)->SocketAddr {
+    SocketAddr::new(Ipv4Addr::from_val((val >> 16) as u32), val as u16)
+}
+
+/// ## TCP 连接
+/// 默认阻塞收发，drop 时关闭，内核在数据发送完毕后断开连接
+pub struct TcpStream {
+    id : usize,
+    peer : SocketAddr,
+    nonblocking : bool,
+}
+
+impl TcpStream {
+    /// 连接到目标地址，阻塞直到连接建立或失败
+    pub fn connect(addr : SocketAddr)->Result<Self, SocketError> {
+        let id = socket(SOCK_STREAM);
+        if id < 0 {
+            return Err(SocketError::CreateFail);
+        }
+        let rt = Self {
+            id : id as usize,
+            peer : addr,
+            nonblocking : false,
+        };
+        if connect(rt.id, addr.ip.val(), addr.port) < 0 {
+            return Err(SocketError::ConnectFail);
+        }
+        Ok(rt)
+    }
+
+    pub fn peer_addr(&self)->SocketAddr {
+        self.peer
+    }
+
+    pub fn set_nonblocking(&mut self, nonblocking : bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    fn flag(&self)->usize {
+        if self.nonblocking { MSG_DONTWAIT } else { 0 }
+    }
+
+    /// 返回读取的字节数，0 表示对端已关闭
+    pub fn read(&self, data : &mut [u8])->Result<usize, SocketError> {
+        match recv(self.id, data, self.flag()) {
+            -2 => Err(SocketError::WouldBlock),
+            len if len < 0 => Err(SocketError::InvalidSocket),
+            len => Ok(len as usize),
+        }
+    }
+
+    /// 返回放入发送缓冲区的字节数，可能少于数据长度
+    pub fn write(&self, data : &[u8])->Result<usize, SocketError> {
+        match send(self.id, data, self.flag()) {
+            -2 => Err(SocketError::WouldBlock),
+            len if len < 0 => Err(SocketError::InvalidSocket),
+            len => Ok(len as usize),
+        }
+    }
+
+    /// 阻塞直到所有数据放入发送缓冲区
+    pub fn write_all(&self, mut data : &[u8])->Result<(), SocketError> {
+        while data.len() > 0 {
+            match send(self.id, data, 0) {
+                len if len < 0 => return Err(SocketError::InvalidSocket),
+                len => data = &data[len as usize..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        socket_close(self.id);
+    }
+}
+
+/// ## TCP 监听
+/// drop 时关闭，尚未 accept 的连接会被重置
+pub struct TcpListener {
+    id : usize,
+    nonblocking : bool,
+}
+
+impl TcpListener {
+    /// 绑定端口并开始监听，端口为 0 时分配临时端口
+    pub fn bind(port : u16)->Result<Self, SocketError> {
+        let id = socket(SOCK_STREAM);
+        if id < 0 {
+            return Err(SocketError::CreateFail);
+        }
+        let rt = Self {
+            id : id as usize,
+            nonblocking : false,
+        };
+        if bind(rt.id, port) < 0 {
+            return Err(SocketError::BindFail);
+        }
+        if listen(rt.id, DEFAULT_BACKLOG) < 0 {
+            return Err(SocketError::ListenFail);
+        }
+        Ok(rt)
+    }
+
+    pub fn set_nonblocking(&mut self, nonblocking : bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    /// 取出一个已建立的连接，阻塞模式下等待直到有连接到来
+    pub fn accept(&self)->Result<(TcpStream, SocketAddr), SocketError> {
+        let flag = if self.nonblocking { MSG_DONTWAIT } else { 0 };
+        match accept(self.id, flag) {
+            (-2, _) => Err(SocketError::WouldBlock),
+            (id, _) if id < 0 => Err(SocketError::InvalidSocket),
+            (id, peer) => {
+                let peer = socket_addr(peer);
+                let stream = TcpStream {
+                    id : id as usize,
+                    peer,
+                    nonblocking : false,
+                };
+                Ok((stream, peer))
+            }
+        }
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        socket_close(self.id);
+    }
+}
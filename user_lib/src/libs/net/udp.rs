@@ -0,0 +1,67 @@
+use crate::libs::syscall::{bind, recv_from, send_to, socket, socket_close};
+use super::{Ipv4Addr, MSG_DONTWAIT, SOCK_DGRAM, SocketAddr, SocketError};
+
+/// ## UDP 套接字
+/// 默认阻塞接收，drop 时关闭
+pub struct UdpSocket {
+    id : usize,
+    nonblocking : bool,
+}
+
+impl UdpSocket {
+    /// 新建未绑定的套接字，第一次发送时由内核分配临时端口
+    pub fn new()->Result<Self, SocketError> {
+        let id = socket(SOCK_DGRAM);
+        if id < 0 {
+            return Err(SocketError::CreateFail);
+        }
+        Ok(Self {
+            id : id as usize,
+            nonblocking : false,
+        })
+    }
+
+    /// 新建套接字并绑定端口，端口为 0 时分配临时端口
+    pub fn bind(port : u16)->Result<Self, SocketError> {
+        let rt = Self::new()?;
+        if bind(rt.id, port) < 0 {
+            return Err(SocketError::BindFail);
+        }
+        Ok(rt)
+    }
+
+    pub fn set_nonblocking(&mut self, nonblocking : bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    pub fn send_to(&self, data : &[u8], addr : SocketAddr)->Result<usize, SocketError> {
+        let len = send_to(self.id, data, addr.ip.val(), addr.port);
+        if len < 0 {
+            Err(SocketError::SendFail)
+        }
+        else {
+            Ok(len as usize)
+        }
+    }
+
+    /// ## 接收数据报
+    /// 数据超过缓冲区时截断，返回（长度，来源）
+    pub fn recv_from(&self, data : &mut [u8])->Result<(usize, SocketAddr), SocketError> {
+        let flag = if self.nonblocking { MSG_DONTWAIT } else { 0 };
+        let (len, src) = recv_from(self.id, data, flag);
+        match len {
+            -2 => Err(SocketError::WouldBlock),
+            len if len < 0 => Err(SocketError::InvalidSocket),
+            len => {
+                let addr = SocketAddr::new(Ipv4Addr::from_val((src >> 16) as u32), src as u16);
+                Ok((len as usize, addr))
+            }
+        }
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        socket_close(self.id);
+    }
+}
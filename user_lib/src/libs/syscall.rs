@@ -1,4 +1,4 @@
-use super::{str::to_char_slice};
+use super::{fs::Stat, net::{InterfaceConfig, InterfaceStats, SocketInfo}, str::to_char_slice};
 use alloc::prelude::v1::*;
 use tisu_driver::Pixel;
 global_asm!(include_str!("../func.S"));
@@ -24,15 +24,37 @@ const GET_KEY_PRESS     : usize = 18;
 const GET_KEY_RELEASE   : usize = 19;
 const GET_MOUSE_SCROLL  : usize = 20;
 const GET_MOUSE_POS     : usize = 21;
-const DIRECTORY_INFO    : usize = 22;
+const CLOSE             : usize = 23;
 const SHUTDOWN          : usize = 27;
 const SLEEP             : usize = 28;
 const WAKE              : usize = 29;
 const JOIN              : usize = 30;
 const GET_TID           : usize = 31;
+const SOCKET            : usize = 33;
+const BIND              : usize = 34;
+const SEND_TO           : usize = 35;
+const RECV_FROM         : usize = 36;
+const SOCKET_CLOSE      : usize = 37;
+const LISTEN            : usize = 38;
+const ACCEPT            : usize = 39;
+const CONNECT           : usize = 40;
+const SEND              : usize = 41;
+const RECV              : usize = 42;
+const IFCONFIG          : usize = 43;
+const RESOLVE           : usize = 44;
+const PCAP              : usize = 45;
+const NETSTAT           : usize = 46;
+const SYNC              : usize = 47;
+const FSYNC             : usize = 48;
+const STAT              : usize = 50;
+const FSTAT             : usize = 51;
+const GETDENTS          : usize = 52;
+const SYMLINK           : usize = 53;
+const LINK              : usize = 54;
+const READLINK          : usize = 55;
 
 extern  "C" {
-    fn env_call_tuple(num:usize, a0 : usize, a1: usize, a2: usize)->(usize, usize);
+    fn env_call_tuple(num:usize, a0 : usize, a1: usize, a2: usize, a3: usize)->(usize, usize);
     fn env_call(num:usize, a0 : usize, a1: usize, a2: usize)->usize;
     fn env_call_long(num:usize, a0:usize, a1:usize, a2:usize, a3:usize, a4:usize,a5:usize)->usize;
 }
@@ -57,15 +79,43 @@ pub fn exit() {
     syscall(SHUTDOWN, 0, 0, 0);
 }
 
-pub fn directory_info(path : String)->usize {
+/// 在 path 处新建指向 target 的符号链接，target 为同一文件系统内的路径，如 /bin/sh，失败返回 -1
+pub fn symlink(target : String, path : String)->isize {
+    let target = to_char_slice(&target);
     let path = to_char_slice(&path);
+    let t = target.as_slice() as *const [char] as *const char as usize;
     let p = path.as_slice() as *const [char] as *const char as usize;
-    syscall(DIRECTORY_INFO, p, 0, 0)
+    syscall(SYMLINK, t, p, 0) as isize
+}
+
+/// 新建指向 old 的硬链接，两个路径须在同一文件系统，失败返回 -1
+pub fn link(old : String, new : String)->isize {
+    let old = to_char_slice(&old);
+    let new = to_char_slice(&new);
+    let o = old.as_slice() as *const [char] as *const char as usize;
+    let n = new.as_slice() as *const [char] as *const char as usize;
+    syscall(LINK, o, n, 0) as isize
+}
+
+/// 读取符号链接的目标，返回目标的完整长度，超过缓冲的部分不复制，失败返回 -1
+pub fn readlink(path : String, buf : &mut [u8])->isize {
+    let path = to_char_slice(&path);
+    let p = path.as_slice() as *const [char] as *const char as usize;
+    let ptr = buf as *mut [u8] as *mut u8 as usize;
+    syscall(READLINK, p, ptr, buf.len()) as isize
+}
+
+/// 从第 cookie 项开始读取目录项，返回写入的字节数，读完返回 0，失败返回 -1
+pub fn getdents(path : String, buf : &mut [u8], cookie : usize)->isize {
+    let path = to_char_slice(&path);
+    let p = path.as_slice() as *const [char] as *const char as usize;
+    let ptr = buf as *mut [u8] as *mut u8 as usize;
+    syscall_long(GETDENTS, p, ptr, buf.len(), cookie, 0, 0) as isize
 }
 
 pub fn sys_mouse_position()->(usize, usize) {
     unsafe {
-        env_call_tuple(GET_MOUSE_POS, 0, 0, 0)
+        env_call_tuple(GET_MOUSE_POS, 0, 0, 0, 0)
     }
 }
 
@@ -112,6 +162,112 @@ pub fn open(path : String, flag : usize)->isize {
     syscall(OPEN, p, flag, 0) as isize
 }
 
+pub fn close(id : usize) {
+    syscall(CLOSE, id, 0, 0);
+}
+
+/// 把所有磁盘缓冲中的脏块写回
+pub fn sync() {
+    syscall(SYNC, 0, 0, 0);
+}
+
+/// 把文件所在磁盘的脏块写回，文件未打开时返回 -1
+pub fn fsync(id : usize)->isize {
+    syscall(FSYNC, id, 0, 0) as isize
+}
+
+/// 获取文件或目录的状态，不存在时返回 -1
+pub fn stat(path : String, stat : &mut Stat)->isize {
+    let path = to_char_slice(&path);
+    let p = path.as_slice() as *const [char] as *const char as usize;
+    syscall(STAT, p, stat as *mut Stat as usize, 0) as isize
+}
+
+/// 获取已打开文件的状态，文件未打开时返回 -1
+pub fn fstat(id : usize, stat : &mut Stat)->isize {
+    syscall(FSTAT, id, stat as *mut Stat as usize, 0) as isize
+}
+
+/// 新建套接字，失败返回 -1
+pub fn socket(socket_type : usize)->isize {
+    syscall(SOCKET, socket_type, 0, 0) as isize
+}
+
+/// 绑定本地端口，返回实际绑定的端口，失败返回 -1
+pub fn bind(id : usize, port : u16)->isize {
+    syscall(BIND, id, port as usize, 0) as isize
+}
+
+pub fn send_to(id : usize, data : &[u8], addr : u32, port : u16)->isize {
+    let ptr = data as *const [u8] as *const u8 as usize;
+    syscall_long(SEND_TO, id, ptr, data.len(), addr as usize, port as usize, 0) as isize
+}
+
+/// ## 接收数据报
+/// 返回（长度，来源地址 << 16 | 来源端口），套接字无效时长度为 -1，非阻塞且没有数据时为 -2
+pub fn recv_from(id : usize, data : &mut [u8], flag : usize)->(isize, usize) {
+    let ptr = data as *mut [u8] as *mut u8 as usize;
+    let (len, src) = unsafe {env_call_tuple(RECV_FROM, id, ptr, data.len(), flag)};
+    (len as isize, src)
+}
+
+pub fn socket_close(id : usize) {
+    syscall(SOCKET_CLOSE, id, 0, 0);
+}
+
+pub fn listen(id : usize, backlog : usize)->isize {
+    syscall(LISTEN, id, backlog, 0) as isize
+}
+
+/// 返回（新套接字，对端地址 << 16 | 对端端口），非阻塞且没有连接时套接字为 -2
+pub fn accept(id : usize, flag : usize)->(isize, usize) {
+    let (id, src) = unsafe {env_call_tuple(ACCEPT, id, flag, 0, 0)};
+    (id as isize, src)
+}
+
+/// 阻塞直到连接建立，失败返回 -1
+pub fn connect(id : usize, addr : u32, port : u16)->isize {
+    syscall(CONNECT, id, addr as usize, port as usize) as isize
+}
+
+/// 返回放入发送缓冲区的字节数，非阻塞且缓冲区已满时返回 -2
+pub fn send(id : usize, data : &[u8], flag : usize)->isize {
+    let ptr = data as *const [u8] as *const u8 as usize;
+    syscall_long(SEND, id, ptr, data.len(), flag, 0, 0) as isize
+}
+
+/// 返回 0 表示对端已关闭，非阻塞且没有数据时返回 -2
+pub fn recv(id : usize, data : &mut [u8], flag : usize)->isize {
+    let ptr = data as *mut [u8] as *mut u8 as usize;
+    syscall_long(RECV, id, ptr, data.len(), flag, 0, 0) as isize
+}
+
+/// 查询或修改接口配置，op 为 0 查询、1 设置静态配置、2 重新 DHCP，失败返回 -1
+pub fn ifconfig(idx : usize, config : &mut InterfaceConfig, op : usize)->isize {
+    syscall(IFCONFIG, idx, config as *mut InterfaceConfig as usize, op) as isize
+}
+
+/// 解析主机名，阻塞直到得到结果，失败返回 -1
+pub fn resolve(name : &str)->isize {
+    syscall(RESOLVE, name.as_ptr() as usize, name.len(), 0) as isize
+}
+
+/// 查询第 idx 个接口的计数，接口不存在时返回 -1
+pub fn interface_stats(idx : usize, stats : &mut InterfaceStats)->isize {
+    syscall(NETSTAT, 0, idx, stats as *mut InterfaceStats as usize) as isize
+}
+
+/// 查询第 idx 个套接字的信息，套接字不存在时返回 -1
+pub fn socket_info(idx : usize, info : &mut SocketInfo)->isize {
+    syscall(NETSTAT, 1, idx, info as *mut SocketInfo as usize) as isize
+}
+
+/// 控制抓包，op 为 0 开始、1 停止、2 清空、3 读取，读取时返回 pcap 文件总长度
+pub fn pcap(op : usize, data : &mut [u8])->isize {
+    let ptr = data as *mut [u8] as *mut u8 as usize;
+    syscall(PCAP, op, ptr, data.len()) as isize
+}
+
 pub fn syscall_test() {
     syscall(1, 0, 0, 0);
 }
@@ -130,9 +286,15 @@ pub fn branch(entry : usize)->usize {
     syscall(BRANCH, entry, 0, 0)
 }
 
-/// 申请内存，采用内存池实现
+/// 分支执行并指定栈大小（字节），超出允许的大小时返回 -1
+pub fn branch_with_stack(entry : usize, stack_size : usize)->usize {
+    syscall(BRANCH, entry, 0, stack_size)
+}
+
+/// 申请内存，采用内存池实现，失败时返回 0
 pub fn malloc(size : usize)->usize {
-    syscall(MALLOC, size, 0, 0)
+    let rt = syscall(MALLOC, size, 0, 0);
+    if rt == -1 as isize as usize { 0 } else { rt }
 }
 
 /// 等待某个线程死亡